@@ -0,0 +1,286 @@
+//! Object detection shared by all cameras. One worker thread per model owns
+//! its session and runs frames from every analyzer through it in batches, so
+//! the number of inference threads no longer grows with the camera count.
+
+use std::cmp::Reverse;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ndarray::{Array4, Axis};
+
+use crate::config::{InferenceConfig, ObjectDetectionConfig};
+
+use super::object::{self, BatchItem, Detection, Detector, Letterbox};
+
+/// How often an idle worker checks whether its clients are gone
+const IDLE_CHECK: Duration = Duration::from_secs(1);
+
+struct Request {
+    camera_id: String,
+    priority: u8,
+    /// Arrival order, oldest first among equal priorities
+    order: u64,
+    input: Array4<f32>,
+    letterbox: Letterbox,
+    confidence_threshold: f32,
+    classes: Arc<Vec<String>>,
+    reply: mpsc::Sender<Result<Vec<Detection>, String>>,
+}
+
+impl Request {
+    fn reject(self, reason: &str) {
+        tracing::debug!(camera = %self.camera_id, reason, "inference request dropped");
+        let _ = self.reply.send(Err(reason.to_string()));
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    requests: Vec<Request>,
+    next_order: u64,
+}
+
+struct Queue {
+    pending: Mutex<Pending>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl Queue {
+    /// Queue a request. When full, the lowest priority request waiting,
+    /// newest first, makes room if it ranks below the new one; otherwise the
+    /// new one is turned away.
+    fn submit(&self, mut request: Request) {
+        let mut pending = self.pending.lock().unwrap();
+        request.order = pending.next_order;
+        pending.next_order += 1;
+
+        if pending.requests.len() >= self.capacity {
+            let lowest = pending
+                .requests
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, r)| (r.priority, Reverse(r.order)))
+                .map(|(i, _)| i);
+            match lowest {
+                Some(i) if pending.requests[i].priority < request.priority => {
+                    pending
+                        .requests
+                        .swap_remove(i)
+                        .reject("dropped for a higher priority camera");
+                }
+                _ => {
+                    drop(pending);
+                    request.reject("inference queue full");
+                    return;
+                }
+            }
+        }
+
+        pending.requests.push(request);
+        self.ready.notify_one();
+    }
+
+    /// Wait for requests, then give them `wait` to fill a batch and take up
+    /// to `max` of the highest priority. `None` once every client is gone.
+    fn next_batch(self: &Arc<Self>, max: usize, wait: Duration) -> Option<Vec<Request>> {
+        let mut pending = self.pending.lock().unwrap();
+        while pending.requests.is_empty() {
+            // Only the worker holds the queue after the service and its
+            // clients are dropped
+            if Arc::strong_count(self) == 1 {
+                return None;
+            }
+            pending = self.ready.wait_timeout(pending, IDLE_CHECK).unwrap().0;
+        }
+
+        let deadline = Instant::now() + wait;
+        while pending.requests.len() < max {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            pending = self.ready.wait_timeout(pending, deadline - now).unwrap().0;
+        }
+
+        pending
+            .requests
+            .sort_by_key(|r| (Reverse(r.priority), r.order));
+        let take = pending.requests.len().min(max);
+        Some(pending.requests.drain(..take).collect())
+    }
+}
+
+/// One model's session with the queue feeding it
+pub struct InferenceService {
+    queue: Arc<Queue>,
+}
+
+impl InferenceService {
+    /// Load the model in `config` and start its worker thread
+    pub fn start(
+        config: &ObjectDetectionConfig,
+        inference: &InferenceConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let detector = object::load_detector(config, inference)?;
+        let queue = Arc::new(Queue {
+            pending: Mutex::new(Pending::default()),
+            ready: Condvar::new(),
+            capacity: inference.queue_size.max(1),
+        });
+
+        let worker_queue = Arc::clone(&queue);
+        let max_batch = inference.max_batch.max(1);
+        let wait = Duration::from_millis(inference.batch_wait_ms);
+        thread::Builder::new()
+            .name("inference".to_string())
+            .spawn(move || run_worker(detector, worker_queue, max_batch, wait))?;
+
+        Ok(Self { queue })
+    }
+
+    /// Handle for one camera, with its own priority and class filter
+    pub fn client(&self, camera_id: &str, config: &ObjectDetectionConfig) -> InferenceClient {
+        InferenceClient {
+            queue: Arc::clone(&self.queue),
+            camera_id: camera_id.to_string(),
+            priority: config.priority,
+            confidence_threshold: config.confidence_threshold,
+            classes: Arc::new(config.classes.clone()),
+        }
+    }
+}
+
+fn run_worker(
+    mut detector: Box<dyn Detector>,
+    queue: Arc<Queue>,
+    max_batch: usize,
+    wait: Duration,
+) {
+    tracing::info!(max_batch, "inference worker started");
+
+    while let Some(requests) = queue.next_batch(max_batch, wait) {
+        let inputs: Vec<_> = requests.iter().map(|r| r.input.view()).collect();
+        let result = ndarray::concatenate(Axis(0), &inputs)
+            .map_err(|e| e.into())
+            .and_then(|batch| {
+                let items: Vec<BatchItem> = requests
+                    .iter()
+                    .map(|r| BatchItem {
+                        letterbox: r.letterbox,
+                        confidence_threshold: r.confidence_threshold,
+                        classes: &r.classes,
+                    })
+                    .collect();
+                detector.detect_batch(&batch, &items)
+            });
+
+        tracing::trace!(batch = requests.len(), "inference batch done");
+        match result {
+            Ok(detections) => {
+                for (request, detections) in requests.iter().zip(detections) {
+                    let _ = request.reply.send(Ok(detections));
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                for request in &requests {
+                    let _ = request.reply.send(Err(error.clone()));
+                }
+            }
+        }
+    }
+
+    tracing::info!("inference worker stopped");
+}
+
+/// A camera's way into a shared [`InferenceService`]
+pub struct InferenceClient {
+    queue: Arc<Queue>,
+    camera_id: String,
+    priority: u8,
+    confidence_threshold: f32,
+    classes: Arc<Vec<String>>,
+}
+
+impl InferenceClient {
+    /// Detect objects in one frame, waiting for its batch to run
+    pub fn detect(
+        &self,
+        frame: &opencv::core::Mat,
+    ) -> Result<Vec<Detection>, Box<dyn std::error::Error + Send + Sync>> {
+        use opencv::prelude::*;
+
+        if frame.empty() {
+            return Ok(Vec::new());
+        }
+        let (input, letterbox) = object::prepare_input(frame)?;
+
+        let (reply, result) = mpsc::channel();
+        self.queue.submit(Request {
+            camera_id: self.camera_id.clone(),
+            priority: self.priority,
+            order: 0,
+            input,
+            letterbox,
+            confidence_threshold: self.confidence_threshold,
+            classes: Arc::clone(&self.classes),
+            reply,
+        });
+        let detections = result.recv().map_err(|_| "inference worker stopped")??;
+        Ok(detections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        camera: &str,
+        priority: u8,
+    ) -> (Request, mpsc::Receiver<Result<Vec<Detection>, String>>) {
+        let (reply, result) = mpsc::channel();
+        let request = Request {
+            camera_id: camera.to_string(),
+            priority,
+            order: 0,
+            input: Array4::zeros((1, 3, 1, 1)),
+            letterbox: Letterbox::new(640.0, 640.0),
+            confidence_threshold: 0.5,
+            classes: Arc::new(Vec::new()),
+            reply,
+        };
+        (request, result)
+    }
+
+    #[test]
+    fn test_queue_priority_and_back_pressure() {
+        let queue = Arc::new(Queue {
+            pending: Mutex::new(Pending::default()),
+            ready: Condvar::new(),
+            capacity: 2,
+        });
+
+        let (low, low_result) = request("garden", 0);
+        let (high, _high_result) = request("door", 2);
+        queue.submit(low);
+        queue.submit(high);
+
+        // Full: an equal or lower priority request is turned away...
+        let (late, late_result) = request("garden", 0);
+        queue.submit(late);
+        assert!(late_result.recv().unwrap().is_err());
+
+        // ...while a higher one pushes out the lowest waiting
+        let (urgent, _urgent_result) = request("drive", 1);
+        queue.submit(urgent);
+        assert!(low_result.recv().unwrap().is_err());
+
+        let batch = queue.next_batch(8, Duration::ZERO).unwrap();
+        let cameras: Vec<&str> = batch.iter().map(|r| r.camera_id.as_str()).collect();
+        assert_eq!(cameras, vec!["door", "drive"]);
+    }
+}
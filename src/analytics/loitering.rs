@@ -0,0 +1,232 @@
+//! Loitering: tracked objects that stay in a zone, or anywhere in view, for
+//! longer than a configured time. Each stay becomes one alert with a start,
+//! an end once the object leaves, and the frame it was confirmed on.
+
+use std::collections::HashMap;
+
+use crate::config::{LoiteringConfig, ZoneConfig};
+use crate::storage::{AlertEntry, AlertKind, AlertStore};
+
+use super::tracker::{Track, TRACK_TIMEOUT_NS};
+use super::zone;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// How often to run detection without motion while a stay is in progress.
+/// Still objects stop producing motion but still need to be seen to keep
+/// their track alive.
+const CHECK_INTERVAL_NS: u64 = 5 * NANOS_PER_SEC;
+
+struct Watch {
+    name: String,
+    classes: Vec<String>,
+    /// Whole frame when `None`
+    polygon: Option<Vec<[f32; 2]>>,
+    min_ns: u64,
+}
+
+/// One track's stay under one watch
+struct Stay {
+    start_ns: u64,
+    last_seen_ns: u64,
+    alert_id: Option<u64>,
+}
+
+pub struct LoiteringAnalyzer {
+    camera_id: String,
+    watches: Vec<Watch>,
+    stays: HashMap<(usize, u64), Stay>,
+    alert_store: AlertStore,
+    last_check_ns: u64,
+}
+
+impl LoiteringAnalyzer {
+    /// `None` when the camera has nothing to watch
+    pub fn new(
+        camera_id: &str,
+        configs: &[LoiteringConfig],
+        zones: &[ZoneConfig],
+        alert_store: AlertStore,
+    ) -> Option<Self> {
+        let mut watches = Vec::new();
+        for config in configs {
+            let polygon = match &config.zone {
+                Some(name) => match zones.iter().find(|z| &z.name == name) {
+                    Some(z) => Some(z.points.clone()),
+                    None => {
+                        tracing::warn!(
+                            camera = %camera_id,
+                            loitering = %config.name,
+                            zone = %name,
+                            "loitering watch refers to an unknown zone, ignoring it"
+                        );
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            watches.push(Watch {
+                name: config.name.clone(),
+                classes: config.classes.clone(),
+                polygon,
+                min_ns: config.min_secs * NANOS_PER_SEC,
+            });
+        }
+
+        if watches.is_empty() {
+            return None;
+        }
+        Some(Self {
+            camera_id: camera_id.to_string(),
+            watches,
+            stays: HashMap::new(),
+            alert_store,
+            last_check_ns: 0,
+        })
+    }
+
+    /// Whether a segment at `time_ns` should get object detection even
+    /// without motion, to follow objects that may be loitering
+    pub fn wants_check(&mut self, time_ns: u64) -> bool {
+        if self.stays.is_empty() || time_ns < self.last_check_ns + CHECK_INTERVAL_NS {
+            return false;
+        }
+        self.last_check_ns = time_ns;
+        true
+    }
+
+    /// Follow a track that just got a new observation. `frame_jpeg` is the
+    /// detector input it was seen in, kept when a stay is confirmed.
+    pub fn observe(&mut self, track: &Track, frame_jpeg: &[u8]) {
+        let Some(&(time_ns, bbox)) = track.trajectory.last() else {
+            return;
+        };
+        // Bottom centre, where the object stands
+        let x = bbox.x + bbox.width / 2.0;
+        let y = bbox.y + bbox.height;
+
+        for (index, watch) in self.watches.iter().enumerate() {
+            if !watch.classes.is_empty() && !watch.classes.contains(&track.object_class) {
+                continue;
+            }
+            let key = (index, track.id);
+            let inside = watch
+                .polygon
+                .as_ref()
+                .is_none_or(|polygon| zone::contains(polygon, x, y));
+            if !inside {
+                if let Some(stay) = self.stays.remove(&key) {
+                    self.finish(stay);
+                }
+                continue;
+            }
+
+            let stay = self.stays.entry(key).or_insert(Stay {
+                start_ns: time_ns,
+                last_seen_ns: time_ns,
+                alert_id: None,
+            });
+            stay.last_seen_ns = time_ns;
+            if stay.alert_id.is_none() && time_ns - stay.start_ns >= watch.min_ns {
+                let id = self.alert_store.insert(
+                    &self.camera_id,
+                    AlertEntry {
+                        id: 0,
+                        segment_sequence: track.last_sequence,
+                        time_ns: stay.start_ns,
+                        end_ns: None,
+                        kind: AlertKind::Loitering,
+                        rule: watch.name.clone(),
+                        track_id: Some(track.id),
+                        object_class: Some(track.object_class.clone()),
+                        frame_jpeg: Some(frame_jpeg.to_vec()),
+                        before_jpeg: None,
+                    },
+                );
+                stay.alert_id = Some(id);
+                tracing::info!(
+                    camera = %self.camera_id,
+                    loitering = %watch.name,
+                    track = track.id,
+                    class = %track.object_class,
+                    secs = (time_ns - stay.start_ns) / NANOS_PER_SEC,
+                    "loitering detected"
+                );
+            }
+        }
+    }
+
+    /// End stays whose tracks have not been seen for a while
+    pub fn expire(&mut self, now_ns: u64) {
+        let expired: Vec<(usize, u64)> = self
+            .stays
+            .iter()
+            .filter(|(_, s)| now_ns.saturating_sub(s.last_seen_ns) > TRACK_TIMEOUT_NS)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            if let Some(stay) = self.stays.remove(&key) {
+                self.finish(stay);
+            }
+        }
+    }
+
+    fn finish(&self, stay: Stay) {
+        if let Some(id) = stay.alert_id {
+            self.alert_store
+                .set_end(&self.camera_id, id, stay.last_seen_ns);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::object::Detection;
+    use crate::analytics::tracker::Tracker;
+    use crate::storage::BoundingBox;
+
+    #[test]
+    fn test_loitering_stay() {
+        let store = AlertStore::new(&["cam".to_string()]);
+        let config = LoiteringConfig {
+            name: "parked".to_string(),
+            classes: vec!["car".to_string()],
+            zone: None,
+            min_secs: 60,
+        };
+        let mut analyzer = LoiteringAnalyzer::new("cam", &[config], &[], store.clone()).unwrap();
+        let mut tracker = Tracker::default();
+        let car = Detection {
+            class_name: "car".to_string(),
+            confidence: 0.9,
+            bbox: BoundingBox {
+                x: 0.2,
+                y: 0.5,
+                width: 0.3,
+                height: 0.2,
+            },
+        };
+
+        assert!(!analyzer.wants_check(0));
+        for step in 0..=20u64 {
+            let time = step * CHECK_INTERVAL_NS;
+            for track in tracker.update(time, step, std::slice::from_ref(&car)) {
+                analyzer.observe(track, b"jpeg");
+            }
+            assert!(!analyzer.wants_check(time));
+            assert!(analyzer.wants_check(time + CHECK_INTERVAL_NS));
+        }
+
+        let alerts = store.get_alerts("cam");
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Loitering);
+        assert_eq!(alerts[0].time_ns, 0);
+        assert_eq!(alerts[0].end_ns, None);
+
+        // Gone: the stay ends at the last sighting
+        analyzer.expire(200 * NANOS_PER_SEC);
+        let alerts = store.get_alerts("cam");
+        assert_eq!(alerts[0].end_ns, Some(100 * NANOS_PER_SEC));
+        assert!(!analyzer.wants_check(300 * NANOS_PER_SEC));
+    }
+}
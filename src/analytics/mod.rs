@@ -1,7 +1,13 @@
 mod decoder;
+mod inference;
+mod loitering;
 mod motion;
 mod object;
 mod pipeline;
+mod rules;
+mod tamper;
+mod tracker;
+mod zone;
 
-pub use object::ObjectDetector;
+pub use inference::InferenceService;
 pub use pipeline::spawn_analyzer;
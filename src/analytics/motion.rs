@@ -8,6 +8,8 @@ use opencv::{
     Result as CvResult,
 };
 
+use super::zone::ZoneMasks;
+
 const HISTOGRAM_BUCKETS: usize = 100;
 const MIN_SAMPLES_FOR_THRESHOLD: u64 = 1000;
 const WINDOW_HOURS: usize = 3;
@@ -73,22 +75,59 @@ impl ScoreHistogram {
 
 const WARMUP_FRAMES: u32 = 100;
 const SCENE_CHANGE_RATIO: f32 = 0.8;
+/// Share of a named zone's area that has to move for it to count
+const ZONE_MIN_RATIO: f32 = 0.005;
+
+/// Zone masks as Mats matching the analysed frame size
+struct Zones {
+    active: Mat,
+    active_pixels: i32,
+    named: Vec<(String, Mat, i32)>,
+}
 
 pub struct MotionDetector {
     mog2: opencv::core::Ptr<video::BackgroundSubtractorMOG2>,
     fg_mask: Mat,
+    zones: Option<Zones>,
     learning_rate: f64,
     frames_since_stable: u32,
 }
 
 impl MotionDetector {
-    pub fn new() -> CvResult<Self> {
+    /// Frames passed to `process_frame` must match the size `zones` were
+    /// rasterised at
+    pub fn new(zones: Option<ZoneMasks>) -> CvResult<Self> {
         let mog2 = video::create_background_subtractor_mog2(500, 16.0, true)?;
         let fg_mask = Mat::default();
 
+        let zones = match zones {
+            Some(masks) => {
+                let to_mat = |mask: &[u8]| -> CvResult<(Mat, i32)> {
+                    let mat = Mat::from_slice(mask)?
+                        .reshape(1, masks.height as i32)?
+                        .try_clone()?;
+                    let pixels = opencv::core::count_non_zero(&mat)?;
+                    Ok((mat, pixels))
+                };
+                let (active, active_pixels) = to_mat(&masks.active)?;
+                let mut named = Vec::new();
+                for (name, mask) in &masks.named {
+                    let (mat, pixels) = to_mat(mask)?;
+                    named.push((name.clone(), mat, pixels));
+                }
+                Some(Zones {
+                    active,
+                    active_pixels,
+                    named,
+                })
+            }
+            None => None,
+        };
+
         Ok(Self {
             mog2,
             fg_mask,
+            zones,
             learning_rate: -1.0,
             frames_since_stable: 0,
         })
@@ -102,7 +141,15 @@ impl MotionDetector {
             self.learning_rate,
         )?;
 
-        let total_pixels = self.fg_mask.rows() * self.fg_mask.cols();
+        let mut total_pixels = self.fg_mask.rows() * self.fg_mask.cols();
+        if let Some(zones) = &self.zones {
+            // Everything outside the active area is dropped before scoring,
+            // so the mask JPEG and bounding box only show zone motion too
+            let mut masked = Mat::default();
+            opencv::core::bitwise_and(&self.fg_mask, &zones.active, &mut masked, &Mat::default())?;
+            self.fg_mask = masked;
+            total_pixels = zones.active_pixels;
+        }
         if total_pixels == 0 {
             return Ok(0.0);
         }
@@ -134,6 +181,30 @@ impl MotionDetector {
         Some(buf.to_vec())
     }
 
+    /// Named zones with motion in the last processed frame
+    pub fn active_zones(&self) -> Vec<String> {
+        let Some(zones) = &self.zones else {
+            return Vec::new();
+        };
+        let mut active = Vec::new();
+        for (name, mask, pixels) in &zones.named {
+            if *pixels == 0 {
+                continue;
+            }
+            let mut overlap = Mat::default();
+            if opencv::core::bitwise_and(&self.fg_mask, mask, &mut overlap, &Mat::default())
+                .is_err()
+            {
+                continue;
+            }
+            let moving = opencv::core::count_non_zero(&overlap).unwrap_or(0);
+            if moving as f32 / *pixels as f32 >= ZONE_MIN_RATIO {
+                active.push(name.clone());
+            }
+        }
+        active
+    }
+
     pub fn motion_bbox(&self) -> Option<Rect> {
         let mut points = Vector::<opencv::core::Point>::new();
         opencv::core::find_non_zero(&self.fg_mask, &mut points).ok()?;
@@ -1,8 +1,14 @@
-use ndarray::{Array4, ArrayViewD};
+use ndarray::{Array4, ArrayD, ArrayViewD, Axis};
 use ort::session::{builder::GraphOptimizationLevel, Session};
 use ort::value::TensorRef;
 
+use crate::config::{DetectorBackend, InferenceConfig, ObjectDetectionConfig};
+use crate::storage::BoundingBox;
+
+use super::tracker::iou;
+
 const YOLO_INPUT_SIZE: u32 = 640;
+const NMS_IOU_THRESHOLD: f32 = 0.45;
 const COCO_CLASSES: [&str; 80] = [
     "person",
     "bicycle",
@@ -90,196 +96,544 @@ const COCO_CLASSES: [&str; 80] = [
 pub struct Detection {
     pub class_name: String,
     pub confidence: f32,
+    /// Box in normalized coordinates of the frame passed to `detect`
+    pub bbox: BoundingBox,
 }
 
-pub struct ObjectDetector {
-    session: Session,
+/// How a frame was scaled and padded into the square model input
+#[derive(Debug, Clone, Copy)]
+pub struct Letterbox {
+    scale: f32,
+    pad_x: f32,
+    pad_y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Letterbox {
+    pub fn new(width: f32, height: f32) -> Self {
+        let input_size = YOLO_INPUT_SIZE as f32;
+        let scale = (input_size / width).min(input_size / height);
+        let new_w = (width * scale).round();
+        let new_h = (height * scale).round();
+        Self {
+            scale,
+            pad_x: ((input_size - new_w) / 2.0).floor(),
+            pad_y: ((input_size - new_h) / 2.0).floor(),
+            width,
+            height,
+        }
+    }
+
+    /// Map a box in model input pixels back to normalized frame coordinates
+    fn to_frame(self, x1: f32, y1: f32, x2: f32, y2: f32) -> BoundingBox {
+        let x1 = ((x1 - self.pad_x) / self.scale).clamp(0.0, self.width);
+        let y1 = ((y1 - self.pad_y) / self.scale).clamp(0.0, self.height);
+        let x2 = ((x2 - self.pad_x) / self.scale).clamp(0.0, self.width);
+        let y2 = ((y2 - self.pad_y) / self.scale).clamp(0.0, self.height);
+        BoundingBox {
+            x: x1 / self.width,
+            y: y1 / self.height,
+            width: (x2 - x1) / self.width,
+            height: (y2 - y1) / self.height,
+        }
+    }
+}
+
+/// One input of a batch, with the settings of the camera it came from
+pub struct BatchItem<'a> {
+    pub letterbox: Letterbox,
+    pub confidence_threshold: f32,
+    /// Classes to keep, all when empty
+    pub classes: &'a [String],
+}
+
+/// Object detection on batches of letterboxed `[N, 3, 640, 640]` inputs
+pub trait Detector: Send {
+    /// Detections for each of `items`, in order
+    fn detect_batch(
+        &mut self,
+        batch: &Array4<f32>,
+        items: &[BatchItem],
+    ) -> Result<Vec<Vec<Detection>>, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// Load the model and labels in `config` behind the backend it selects
+pub fn load_detector(
+    config: &ObjectDetectionConfig,
+    inference: &InferenceConfig,
+) -> Result<Box<dyn Detector>, Box<dyn std::error::Error + Send + Sync>> {
+    let model = YoloModel::new(config, inference)?;
+    Ok(match config.backend {
+        DetectorBackend::Yolo26 => Box::new(Yolo26Detector { model }),
+        DetectorBackend::Yolov8 => Box::new(Yolov8Detector { model }),
+    })
+}
+
+/// One class name per line, blank lines skipped
+fn load_labels(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read labels {path}: {e}"))?;
+    let labels: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect();
+    if labels.is_empty() {
+        return Err(format!("no labels in {path}").into());
+    }
+    Ok(labels)
+}
+
+/// Which raw detections are kept, and what their classes are called
+struct Classes<'a> {
     confidence_threshold: f32,
-    allowed_classes: Vec<String>,
+    allowed: &'a [String],
+    labels: &'a [String],
+}
+
+impl Classes<'_> {
+    /// Name of class `index`, unless it is filtered out
+    fn name(&self, index: usize) -> Option<String> {
+        let name = match self.labels.get(index) {
+            Some(label) => label.clone(),
+            None => format!("class_{}", index),
+        };
+        if !self.allowed.is_empty() && !self.allowed.contains(&name) {
+            return None;
+        }
+        Some(name)
+    }
+}
+
+/// Session and labels shared by the YOLO backends, which only differ in
+/// their outputs
+struct YoloModel {
+    session: Session,
+    labels: Vec<String>,
 }
 
-impl ObjectDetector {
-    pub fn new(
-        model_path: &str,
-        confidence_threshold: f32,
-        allowed_classes: Vec<String>,
+impl YoloModel {
+    fn new(
+        config: &ObjectDetectionConfig,
+        inference: &InferenceConfig,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let labels = match &config.labels_path {
+            Some(path) => load_labels(path)?,
+            None => COCO_CLASSES.iter().map(|c| c.to_string()).collect(),
+        };
+
         let builder = Session::builder()?
             .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(4)?;
+            .with_intra_threads(inference.intra_threads)?
+            .with_inter_threads(inference.inter_threads)?;
 
+        let model_path = &config.model_path;
         let session = if model_path.starts_with("http://") || model_path.starts_with("https://") {
             builder.commit_from_url(model_path)?
         } else {
             builder.commit_from_file(model_path)?
         };
 
-        Ok(Self {
-            session,
-            confidence_threshold,
-            allowed_classes,
-        })
+        Ok(Self { session, labels })
     }
 
-    pub fn detect(
-        &mut self,
-        frame: &opencv::core::Mat,
-    ) -> Result<Vec<Detection>, Box<dyn std::error::Error + Send + Sync>> {
-        use opencv::prelude::*;
-
-        let rows = frame.rows();
-        let cols = frame.cols();
-        if rows == 0 || cols == 0 {
-            return Ok(Vec::new());
+    fn classes<'a>(&'a self, item: &BatchItem<'a>) -> Classes<'a> {
+        Classes {
+            confidence_threshold: item.confidence_threshold,
+            allowed: item.classes,
+            labels: &self.labels,
         }
+    }
+}
 
-        let input_tensor = self.preprocess(frame)?;
+/// Item `index` of a batched output, keeping the leading batch axis
+fn batch_entry(output: &ArrayD<f32>, index: usize) -> ArrayViewD<'_, f32> {
+    output.index_axis(Axis(0), index).insert_axis(Axis(0))
+}
+
+struct Yolo26Detector {
+    model: YoloModel,
+}
 
-        let tensor_ref = TensorRef::from_array_view(input_tensor.view())?.into_dyn();
-        let outputs = self.session.run(ort::inputs![tensor_ref])?;
+impl Detector for Yolo26Detector {
+    fn detect_batch(
+        &mut self,
+        batch: &Array4<f32>,
+        items: &[BatchItem],
+    ) -> Result<Vec<Vec<Detection>>, Box<dyn std::error::Error + Send + Sync>> {
+        let tensor_ref = TensorRef::from_array_view(batch.view())?.into_dyn();
+        let outputs = self.model.session.run(ort::inputs![tensor_ref])?;
 
         // YOLO26 format: separate "logits" and "pred_boxes" outputs
         let Some(logits_val) = outputs.get("logits") else {
             return Err("Unsupported model format: expected YOLO26 with 'logits' output".into());
         };
 
-        let logits = logits_val.try_extract_array::<f32>()?;
-        let logits_owned = logits.to_owned();
+        let Some(boxes_val) = outputs.get("pred_boxes") else {
+            return Err(
+                "Unsupported model format: expected YOLO26 with 'pred_boxes' output".into(),
+            );
+        };
+
+        let logits_owned = logits_val.try_extract_array::<f32>()?.to_owned();
+        let boxes_owned = boxes_val.try_extract_array::<f32>()?.to_owned();
         drop(outputs);
 
-        let detections = Self::postprocess_yolo26(
-            &logits_owned.view(),
-            self.confidence_threshold,
-            &self.allowed_classes,
-        )?;
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                postprocess_yolo26(
+                    &batch_entry(&logits_owned, i),
+                    &batch_entry(&boxes_owned, i),
+                    item.letterbox,
+                    &self.model.classes(item),
+                )
+            })
+            .collect()
+    }
+}
+
+struct Yolov8Detector {
+    model: YoloModel,
+}
+
+impl Detector for Yolov8Detector {
+    fn detect_batch(
+        &mut self,
+        batch: &Array4<f32>,
+        items: &[BatchItem],
+    ) -> Result<Vec<Vec<Detection>>, Box<dyn std::error::Error + Send + Sync>> {
+        let tensor_ref = TensorRef::from_array_view(batch.view())?.into_dyn();
+        let outputs = self.model.session.run(ort::inputs![tensor_ref])?;
+        if outputs.len() == 0 {
+            return Err("Unsupported model format: expected a YOLOv5/v8 output".into());
+        }
+        let output_owned = outputs[0].try_extract_array::<f32>()?.to_owned();
+        drop(outputs);
 
-        Ok(detections)
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                postprocess_yolov8(
+                    &batch_entry(&output_owned, i),
+                    item.letterbox,
+                    &self.model.classes(item),
+                )
+            })
+            .collect()
     }
+}
 
-    fn preprocess(
-        &self,
-        frame: &opencv::core::Mat,
-    ) -> Result<Array4<f32>, Box<dyn std::error::Error + Send + Sync>> {
-        use opencv::core::{Mat, Size, BORDER_CONSTANT};
-        use opencv::imgproc;
-        use opencv::prelude::*;
+/// Letterbox a non-empty frame into a `[1, 3, 640, 640]` model input
+pub fn prepare_input(
+    frame: &opencv::core::Mat,
+) -> Result<(Array4<f32>, Letterbox), Box<dyn std::error::Error + Send + Sync>> {
+    use opencv::prelude::*;
 
-        let rows = frame.rows() as f32;
-        let cols = frame.cols() as f32;
-        let input_size = YOLO_INPUT_SIZE as f32;
+    let letterbox = Letterbox::new(frame.cols() as f32, frame.rows() as f32);
+    Ok((preprocess(frame, letterbox)?, letterbox))
+}
 
-        let scale = (input_size / cols).min(input_size / rows);
-        let new_w = (cols * scale).round() as i32;
-        let new_h = (rows * scale).round() as i32;
-
-        let mut resized = Mat::default();
-        imgproc::resize(
-            frame,
-            &mut resized,
-            Size::new(new_w, new_h),
-            0.0,
-            0.0,
-            imgproc::INTER_LINEAR,
-        )?;
-
-        let pad_x = (input_size as i32 - new_w) / 2;
-        let pad_y = (input_size as i32 - new_h) / 2;
-
-        let mut padded = Mat::default();
-        opencv::core::copy_make_border(
-            &resized,
-            &mut padded,
-            pad_y,
-            input_size as i32 - new_h - pad_y,
-            pad_x,
-            input_size as i32 - new_w - pad_x,
-            BORDER_CONSTANT,
-            opencv::core::Scalar::new(114.0, 114.0, 114.0, 0.0),
-        )?;
-
-        let mut rgb = Mat::default();
-        imgproc::cvt_color(&padded, &mut rgb, imgproc::COLOR_BGR2RGB, 0)?;
-
-        let data = rgb.data_bytes()?;
-        let total_size = (YOLO_INPUT_SIZE * YOLO_INPUT_SIZE * 3) as usize;
-        if data.len() < total_size {
-            return Err("Frame data too small".into());
-        }
+fn preprocess(
+    frame: &opencv::core::Mat,
+    letterbox: Letterbox,
+) -> Result<Array4<f32>, Box<dyn std::error::Error + Send + Sync>> {
+    use opencv::core::{Mat, Size, BORDER_CONSTANT};
+    use opencv::imgproc;
+    use opencv::prelude::*;
+
+    let input_size = YOLO_INPUT_SIZE as f32;
+    let new_w = (letterbox.width * letterbox.scale).round() as i32;
+    let new_h = (letterbox.height * letterbox.scale).round() as i32;
+
+    let mut resized = Mat::default();
+    imgproc::resize(
+        frame,
+        &mut resized,
+        Size::new(new_w, new_h),
+        0.0,
+        0.0,
+        imgproc::INTER_LINEAR,
+    )?;
+
+    let pad_x = letterbox.pad_x as i32;
+    let pad_y = letterbox.pad_y as i32;
+
+    let mut padded = Mat::default();
+    opencv::core::copy_make_border(
+        &resized,
+        &mut padded,
+        pad_y,
+        input_size as i32 - new_h - pad_y,
+        pad_x,
+        input_size as i32 - new_w - pad_x,
+        BORDER_CONSTANT,
+        opencv::core::Scalar::new(114.0, 114.0, 114.0, 0.0),
+    )?;
+
+    let mut rgb = Mat::default();
+    imgproc::cvt_color(&padded, &mut rgb, imgproc::COLOR_BGR2RGB, 0)?;
+
+    let data = rgb.data_bytes()?;
+    let total_size = (YOLO_INPUT_SIZE * YOLO_INPUT_SIZE * 3) as usize;
+    if data.len() < total_size {
+        return Err("Frame data too small".into());
+    }
 
-        let mut tensor =
-            Array4::<f32>::zeros((1, 3, YOLO_INPUT_SIZE as usize, YOLO_INPUT_SIZE as usize));
-        for y in 0..YOLO_INPUT_SIZE as usize {
-            for x in 0..YOLO_INPUT_SIZE as usize {
-                let idx = (y * YOLO_INPUT_SIZE as usize + x) * 3;
-                tensor[[0, 0, y, x]] = data[idx] as f32 / 255.0;
-                tensor[[0, 1, y, x]] = data[idx + 1] as f32 / 255.0;
-                tensor[[0, 2, y, x]] = data[idx + 2] as f32 / 255.0;
-            }
+    let mut tensor =
+        Array4::<f32>::zeros((1, 3, YOLO_INPUT_SIZE as usize, YOLO_INPUT_SIZE as usize));
+    for y in 0..YOLO_INPUT_SIZE as usize {
+        for x in 0..YOLO_INPUT_SIZE as usize {
+            let idx = (y * YOLO_INPUT_SIZE as usize + x) * 3;
+            tensor[[0, 0, y, x]] = data[idx] as f32 / 255.0;
+            tensor[[0, 1, y, x]] = data[idx + 1] as f32 / 255.0;
+            tensor[[0, 2, y, x]] = data[idx + 2] as f32 / 255.0;
         }
+    }
 
-        Ok(tensor)
+    Ok(tensor)
+}
+
+/// `boxes` are `[1, 300, 4]` centre/size boxes normalized to the model
+/// input, mapped back through `letterbox` to the detected frame
+fn postprocess_yolo26(
+    logits: &ArrayViewD<f32>,
+    boxes: &ArrayViewD<f32>,
+    letterbox: Letterbox,
+    classes: &Classes<'_>,
+) -> Result<Vec<Detection>, Box<dyn std::error::Error + Send + Sync>> {
+    let logits_shape = logits.shape();
+
+    // Expected shape: logits [1, 300, 80]
+    if logits_shape.len() < 2 {
+        return Ok(Vec::new());
     }
 
-    fn postprocess_yolo26(
-        logits: &ArrayViewD<f32>,
-        confidence_threshold: f32,
-        allowed_classes: &[String],
-    ) -> Result<Vec<Detection>, Box<dyn std::error::Error + Send + Sync>> {
-        let logits_shape = logits.shape();
+    let num_detections = if logits_shape.len() == 3 {
+        logits_shape[1]
+    } else {
+        logits_shape[0]
+    };
+    let num_classes = if logits_shape.len() == 3 {
+        logits_shape[2]
+    } else {
+        logits_shape[1]
+    };
+
+    let logits_flat = logits.as_slice().ok_or("Cannot get logits slice")?;
+    let boxes_flat = boxes.as_slice().ok_or("Cannot get pred_boxes slice")?;
+    if boxes_flat.len() < num_detections * 4 {
+        return Err("pred_boxes shorter than logits".into());
+    }
+    let mut detections = Vec::new();
+
+    for i in 0..num_detections {
+        // Find max class score (apply sigmoid)
+        let mut max_score = 0.0f32;
+        let mut max_class = 0usize;
+
+        for j in 0..num_classes {
+            let logit = logits_flat[i * num_classes + j];
+            let score = 1.0 / (1.0 + (-logit).exp()); // sigmoid
+            if score > max_score {
+                max_score = score;
+                max_class = j;
+            }
+        }
 
-        // Expected shape: logits [1, 300, 80]
-        if logits_shape.len() < 2 {
-            return Ok(Vec::new());
+        if max_score < classes.confidence_threshold {
+            continue;
         }
 
-        let num_detections = if logits_shape.len() == 3 {
-            logits_shape[1]
-        } else {
-            logits_shape[0]
+        let Some(class_name) = classes.name(max_class) else {
+            continue;
         };
-        let num_classes = if logits_shape.len() == 3 {
-            logits_shape[2]
+
+        let input_size = YOLO_INPUT_SIZE as f32;
+        let [cx, cy, w, h] = [0, 1, 2, 3].map(|k| boxes_flat[i * 4 + k] * input_size);
+        let bbox = letterbox.to_frame(cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0);
+
+        detections.push(Detection {
+            class_name,
+            confidence: max_score,
+            bbox,
+        });
+    }
+
+    Ok(detections)
+}
+
+/// `output` is `[1, 4 + classes, anchors]`, or transposed, with centre/size
+/// boxes in model input pixels followed by class scores. Overlapping boxes of
+/// the same class are suppressed, keeping the most confident.
+fn postprocess_yolov8(
+    output: &ArrayViewD<f32>,
+    letterbox: Letterbox,
+    classes: &Classes<'_>,
+) -> Result<Vec<Detection>, Box<dyn std::error::Error + Send + Sync>> {
+    let shape = output.shape();
+    if shape.len() != 3 {
+        return Err(format!("Unsupported YOLOv5/v8 output shape {:?}", shape).into());
+    }
+    // Anchors far outnumber classes, which tells the layouts apart
+    let transposed = shape[1] > shape[2];
+    let (channels, anchors) = if transposed {
+        (shape[2], shape[1])
+    } else {
+        (shape[1], shape[2])
+    };
+    if channels <= 4 {
+        return Err(format!("Unsupported YOLOv5/v8 output shape {:?}", shape).into());
+    }
+    let flat = output.as_slice().ok_or("Cannot get output slice")?;
+    let value = |channel: usize, anchor: usize| {
+        if transposed {
+            flat[anchor * channels + channel]
         } else {
-            logits_shape[1]
+            flat[channel * anchors + anchor]
+        }
+    };
+
+    let mut candidates = Vec::new();
+    for anchor in 0..anchors {
+        let mut max_score = 0.0f32;
+        let mut max_class = 0usize;
+        for class in 0..channels - 4 {
+            let score = value(4 + class, anchor);
+            if score > max_score {
+                max_score = score;
+                max_class = class;
+            }
+        }
+
+        if max_score < classes.confidence_threshold {
+            continue;
+        }
+
+        let Some(class_name) = classes.name(max_class) else {
+            continue;
         };
 
-        let logits_flat = logits.as_slice().ok_or("Cannot get logits slice")?;
-        let mut detections = Vec::new();
-
-        for i in 0..num_detections {
-            // Find max class score (apply sigmoid)
-            let mut max_score = 0.0f32;
-            let mut max_class = 0usize;
-
-            for j in 0..num_classes {
-                let logit = logits_flat[i * num_classes + j];
-                let score = 1.0 / (1.0 + (-logit).exp()); // sigmoid
-                if score > max_score {
-                    max_score = score;
-                    max_class = j;
-                }
-            }
+        let [cx, cy, w, h] = [0, 1, 2, 3].map(|k| value(k, anchor));
+        let bbox = letterbox.to_frame(cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0);
 
-            if max_score < confidence_threshold {
-                continue;
-            }
+        candidates.push(Detection {
+            class_name,
+            confidence: max_score,
+            bbox,
+        });
+    }
 
-            let class_name = if max_class < COCO_CLASSES.len() {
-                COCO_CLASSES[max_class].to_string()
-            } else {
-                format!("class_{}", max_class)
-            };
+    Ok(non_max_suppression(candidates, NMS_IOU_THRESHOLD))
+}
 
-            if !allowed_classes.is_empty() && !allowed_classes.contains(&class_name) {
-                continue;
-            }
+/// Greedy per-class NMS
+fn non_max_suppression(mut candidates: Vec<Detection>, iou_threshold: f32) -> Vec<Detection> {
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<Detection> = Vec::new();
+    for candidate in candidates {
+        let suppressed = kept.iter().any(|k| {
+            k.class_name == candidate.class_name && iou(k.bbox, candidate.bbox) > iou_threshold
+        });
+        if !suppressed {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    fn coco_labels() -> Vec<String> {
+        COCO_CLASSES.iter().map(|c| c.to_string()).collect()
+    }
+
+    fn coco<'a>(allowed: &'a [String], labels: &'a [String]) -> Classes<'a> {
+        Classes {
+            confidence_threshold: 0.5,
+            allowed,
+            labels,
+        }
+    }
+
+    #[test]
+    fn test_yolo26_boxes_undo_letterbox() {
+        // 1280x720 scales by 0.5 to 640x360 with 140px bars top and bottom
+        let letterbox = Letterbox::new(1280.0, 720.0);
+        let mut logits = Array3::<f32>::from_elem((1, 2, 80), -10.0);
+        logits[[0, 0, 2]] = 5.0; // car
+        let mut boxes = Array3::<f32>::zeros((1, 2, 4));
+        // Centre of the frame, a quarter of its width and half its height
+        for (k, v) in [0.5, 0.5, 0.25, 180.0 / 640.0].into_iter().enumerate() {
+            boxes[[0, 0, k]] = v;
+        }
 
-            detections.push(Detection {
-                class_name,
-                confidence: max_score,
-            });
+        let detections = postprocess_yolo26(
+            &logits.view().into_dyn(),
+            &boxes.view().into_dyn(),
+            letterbox,
+            &coco(&[], &coco_labels()),
+        )
+        .unwrap();
+
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].class_name, "car");
+        let bbox = detections[0].bbox;
+        assert!((bbox.x - 0.375).abs() < 1e-4);
+        assert!((bbox.y - 0.25).abs() < 1e-4);
+        assert!((bbox.width - 0.25).abs() < 1e-4);
+        assert!((bbox.height - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_yolov8_nms() {
+        // Square 640x640 frame, so boxes are only normalized
+        let letterbox = Letterbox::new(640.0, 640.0);
+        let labels = coco_labels();
+        let cars = vec!["car".to_string()];
+        let mut output = Array3::<f32>::zeros((1, 84, 100));
+        let anchors = [
+            // Two overlapping people, a car on top of them and a weak dog
+            ([100.0, 100.0, 50.0, 100.0], 0, 0.9),
+            ([105.0, 100.0, 50.0, 100.0], 0, 0.8),
+            ([100.0, 100.0, 50.0, 100.0], 2, 0.7),
+            ([400.0, 400.0, 50.0, 50.0], 16, 0.3),
+        ];
+        for (anchor, (bbox, class, score)) in anchors.into_iter().enumerate() {
+            for (k, v) in bbox.into_iter().enumerate() {
+                output[[0, k, anchor]] = v;
+            }
+            output[[0, 4 + class, anchor]] = score;
         }
 
-        Ok(detections)
+        let detections =
+            postprocess_yolov8(&output.view().into_dyn(), letterbox, &coco(&[], &labels)).unwrap();
+        let found: Vec<(&str, f32)> = detections
+            .iter()
+            .map(|d| (d.class_name.as_str(), d.confidence))
+            .collect();
+        assert_eq!(found, vec![("person", 0.9), ("car", 0.7)]);
+        assert!((detections[0].bbox.x - 75.0 / 640.0).abs() < 1e-4);
+
+        // The transposed layout reads the same
+        let transposed = output
+            .permuted_axes([0, 2, 1])
+            .as_standard_layout()
+            .to_owned();
+        let detections = postprocess_yolov8(
+            &transposed.view().into_dyn(),
+            letterbox,
+            &coco(&cars, &labels),
+        )
+        .unwrap();
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].class_name, "car");
     }
 }
@@ -9,75 +9,191 @@ use opencv::imgcodecs;
 use opencv::imgproc;
 use opencv::prelude::*;
 
-use crate::buffer::HotBuffer;
+use crate::buffer::{fmp4, HotBuffer};
 use crate::config::AnalyticsConfig;
-use crate::storage::{DetectionStore, MotionEntry, MotionStore};
+use crate::storage::{
+    AlertEntry, AlertStore, BoundingBox, DetectionStore, MotionEntry, MotionStore, TrackEntry,
+};
 
-use super::decoder::{CropDecoder, FrameDecoder};
+use super::decoder::FrameDecoder;
+use super::inference::InferenceClient;
+use super::loitering::LoiteringAnalyzer;
 use super::motion::{MotionDetector, ScoreHistogram};
-use super::object::ObjectDetector;
+use super::object::Detection;
+use super::rules::RuleEngine;
+use super::tamper::{TamperChange, TamperDetector};
+use super::tracker::Tracker;
+use super::zone::ZoneMasks;
 
 const DETECTION_WIDTH: i32 = 640;
 const DETECTION_HEIGHT: i32 = 480;
 const ANALYSIS_WIDTH: i32 = 320;
 const ANALYSIS_HEIGHT: i32 = 240;
-const CROP_DECODE_WIDTH: i32 = 1920;
-const CROP_DECODE_HEIGHT: i32 = 1080;
 
 const MOTION_PERCENTILE: f32 = 0.90;
 const DEFAULT_MOTION_THRESHOLD: f32 = 0.05;
 
 const POLL_INTERVAL: Duration = Duration::from_millis(200);
 
-struct MotionSegment {
+/// A segment of the analysed stream and the main-stream segments covering
+/// the same time, which results are stored against
+struct PendingSegment {
     seq: u64,
     data: Vec<u8>,
     duration_ns: u64,
+    targets: Vec<TargetSegment>,
+}
+
+#[derive(Clone, Copy)]
+struct TargetSegment {
+    seq: u64,
+    start_pts: u64,
+    duration_ns: u64,
+}
+
+/// Sampled BGR frames of one segment
+struct DecodedFrames {
+    width: i32,
+    height: i32,
+    frames: Vec<Vec<u8>>,
+}
+
+struct MotionSegment {
+    seq: u64,
+    /// Main-stream timing the inputs are spread over, for tracking
+    start_pts: u64,
+    duration_ns: u64,
+    /// Detector inputs cut from the segment's frames: the motion crop, or
+    /// the whole frame resized
+    inputs: DetectionInputs,
+}
+
+/// Detections in one detector input, in full-frame coordinates
+struct Observation {
+    time_ns: u64,
+    seq: u64,
+    detections: Vec<Detection>,
+    frame_jpeg: Vec<u8>,
+}
+
+struct DetectionInputs {
+    frames: Vec<Mat>,
+    /// Where the inputs were cut from, when they are a crop
+    crop: Option<Rect>,
+    frame_width: i32,
+    frame_height: i32,
+}
+
+impl DetectionInputs {
+    /// Map a box normalized to a detector input back to the full frame
+    fn to_frame(&self, bbox: BoundingBox) -> BoundingBox {
+        let Some(crop) = self.crop else {
+            // Whole-frame inputs are only resized
+            return bbox;
+        };
+        let width = self.frame_width as f32;
+        let height = self.frame_height as f32;
+        BoundingBox {
+            x: (crop.x as f32 + bbox.x * crop.width as f32) / width,
+            y: (crop.y as f32 + bbox.y * crop.height as f32) / height,
+            width: bbox.width * crop.width as f32 / width,
+            height: bbox.height * crop.height as f32 / height,
+        }
+    }
+}
+
+/// Tamper detection with the snapshots and alert it keeps
+struct TamperWatch {
+    detector: TamperDetector,
+    /// Latest snapshot of the normal view
+    reference_jpeg: Option<Vec<u8>>,
+    /// Changes not yet recorded as alerts, with the frame each was seen on.
+    /// They wait here until a main-stream segment is known to attach them to.
+    changes: Vec<(TamperChange, Option<Vec<u8>>)>,
+    alert_id: Option<u64>,
 }
 
 struct SegmentDetectionResult {
     classes: Vec<String>,
     confidences: Vec<f32>,
+    /// Full-frame normalized box per class entry
+    boxes: Vec<BoundingBox>,
     frame_jpeg: Vec<u8>,
 }
 
 pub struct MotionAnalyzer {
     camera_id: String,
     buffer: Arc<RwLock<HotBuffer>>,
+    /// Substream buffer motion is analysed on, when the camera has a `detect_url`
+    detect_buffer: Option<Arc<RwLock<HotBuffer>>>,
     motion_store: MotionStore,
     detection_store: Option<DetectionStore>,
     config: AnalyticsConfig,
     detector: MotionDetector,
-    decoder: FrameDecoder,
-    crop_decoder: Option<CropDecoder>,
-    object_detector: Option<ObjectDetector>,
+    /// Decoder for the analysed stream, started at its resolution on first use
+    decoder: Option<FrameDecoder>,
+    /// Decoder for main-stream segments to detect objects in, when motion is
+    /// analysed on a substream
+    main_decoder: Option<FrameDecoder>,
+    object_detector: Option<InferenceClient>,
     last_processed: u64,
+    /// Newest main-stream sequence results were stored against
+    last_target: Option<u64>,
     last_motion_bbox: Option<Rect>,
+    /// Named zones that saw motion in the last analysed segment
+    last_zones: Vec<String>,
     score_histogram: ScoreHistogram,
+    tracker: Tracker,
+    rule_engine: Option<RuleEngine>,
+    loitering: Option<LoiteringAnalyzer>,
+    tamper: Option<TamperWatch>,
+    alert_store: AlertStore,
+    /// Detector results of the current pass, waiting to be tracked
+    observations: Vec<Observation>,
 }
 
 impl MotionAnalyzer {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         camera_id: String,
         buffer: Arc<RwLock<HotBuffer>>,
+        detect_buffer: Option<Arc<RwLock<HotBuffer>>>,
         motion_store: MotionStore,
         detection_store: Option<DetectionStore>,
-        object_detector: Option<ObjectDetector>,
+        alert_store: AlertStore,
+        object_detector: Option<InferenceClient>,
         config: AnalyticsConfig,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let detector = MotionDetector::new()?;
-        let decoder = FrameDecoder::new(config.sample_fps)?;
+        let zones = ZoneMasks::new(
+            &config.zones,
+            ANALYSIS_WIDTH as usize,
+            ANALYSIS_HEIGHT as usize,
+        );
+        let detector = MotionDetector::new(zones)?;
+        let rule_engine = RuleEngine::new(&camera_id, &config.rules, &config.zones);
+        let loitering = LoiteringAnalyzer::new(
+            &camera_id,
+            &config.loitering,
+            &config.zones,
+            alert_store.clone(),
+        );
 
-        let crop_decoder = if object_detector.is_some() {
-            Some(CropDecoder::new(config.sample_fps)?)
-        } else {
-            None
+        // Motion store sequences are main-stream ones, so they only say where
+        // to resume when the main stream is what gets analysed
+        let last_processed = match detect_buffer {
+            Some(_) => 0,
+            None => motion_store
+                .last_sequence(&camera_id)
+                .map(|s| s + 1)
+                .unwrap_or(0),
         };
 
-        let last_processed = motion_store
-            .last_sequence(&camera_id)
-            .map(|s| s + 1)
-            .unwrap_or(0);
+        let tamper = config.tamper_detection.then(|| TamperWatch {
+            detector: TamperDetector::new(config.sample_fps),
+            reference_jpeg: None,
+            changes: Vec::new(),
+            alert_id: None,
+        });
 
         let score_histogram = ScoreHistogram::new(
             MOTION_PERCENTILE,
@@ -88,16 +204,25 @@ impl MotionAnalyzer {
         Ok(Self {
             camera_id,
             buffer,
+            detect_buffer,
             motion_store,
             detection_store,
+            alert_store,
             config,
             detector,
-            decoder,
-            crop_decoder,
+            decoder: None,
+            main_decoder: None,
             object_detector,
             last_processed,
+            last_target: None,
             last_motion_bbox: None,
+            last_zones: Vec::new(),
             score_histogram,
+            tracker: Tracker::default(),
+            rule_engine,
+            loitering,
+            tamper,
+            observations: Vec::new(),
         })
     }
 
@@ -105,27 +230,11 @@ impl MotionAnalyzer {
         tracing::info!(camera = %self.camera_id, "motion analyzer started");
 
         while !shutdown.load(Ordering::Relaxed) {
-            if !self.decoder.is_alive() {
-                tracing::warn!(camera = %self.camera_id, "decoder process died, restarting");
-                match FrameDecoder::new(self.config.sample_fps) {
-                    Ok(d) => self.decoder = d,
-                    Err(e) => {
-                        tracing::error!(camera = %self.camera_id, error = %e, "failed to restart decoder");
-                        thread::sleep(Duration::from_secs(5));
-                        continue;
-                    }
-                }
-            }
-
-            if let Some(ref mut dd) = self.crop_decoder {
-                if !dd.is_alive() {
-                    tracing::warn!(camera = %self.camera_id, "crop decoder died, restarting");
-                    match CropDecoder::new(self.config.sample_fps) {
-                        Ok(d) => self.crop_decoder = Some(d),
-                        Err(e) => {
-                            tracing::error!(camera = %self.camera_id, error = %e, "failed to restart crop decoder");
-                        }
-                    }
+            // Dead decoders are restarted by the next decode
+            for decoder in [&mut self.decoder, &mut self.main_decoder] {
+                if decoder.as_mut().is_some_and(|d| !d.is_alive()) {
+                    tracing::warn!(camera = %self.camera_id, "decoder process died, restarting");
+                    *decoder = None;
                 }
             }
 
@@ -144,115 +253,297 @@ impl MotionAnalyzer {
     }
 
     fn process_new_segments(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let segments_to_process = {
-            let buffer = self.buffer.read().map_err(|_| "buffer lock poisoned")?;
-            let first_seq = buffer.first_sequence();
-            let last_seq = buffer.last_sequence();
-
-            if first_seq > 0 {
-                self.motion_store.cleanup(&self.camera_id, first_seq);
-                if let Some(ref ds) = self.detection_store {
-                    ds.cleanup(&self.camera_id, first_seq);
-                }
-            }
-
-            if self.last_processed < first_seq {
-                self.last_processed = first_seq;
-            }
-
-            let mut segments = Vec::new();
-            for seq in self.last_processed..last_seq {
-                if let Some(segment) = buffer.get_segment_by_sequence(seq) {
-                    segments.push((
-                        seq,
-                        segment.data.clone(),
-                        segment.start_pts,
-                        segment.duration_ns,
-                    ));
-                }
-            }
-            segments
-        };
+        let segments_to_process = self.pending_segments()?;
 
         let has_detection = self.object_detector.is_some() && self.detection_store.is_some();
         let mut motion_segments = Vec::new();
+        let mut latest_pts = None;
 
         // Phase 1: Motion analysis
-        for (seq, data, start_pts, duration_ns) in segments_to_process {
-            let score = self.analyze_segment(&data, duration_ns)?;
+        for pending in segments_to_process {
+            let decoded = self.decode(&pending.data, pending.duration_ns, false)?;
+            let score = self.analyze_frames(&decoded)?;
+            self.last_processed = pending.seq + 1;
 
             self.score_histogram.record(score);
             let threshold = self.score_histogram.threshold();
 
-            if score >= threshold {
-                let mask_jpeg = self.detector.fg_mask_jpeg();
+            // A main-stream segment can span several substream segments; the
+            // first one over the threshold marks it
+            let targets: Vec<TargetSegment> = pending
+                .targets
+                .iter()
+                .filter(|t| self.last_target.is_none_or(|last| t.seq > last))
+                .copied()
+                .collect();
+            self.record_tamper(pending.targets.first());
+            if let Some(target) = pending.targets.last() {
+                latest_pts = Some(target.start_pts);
+            }
+            let motion = score >= threshold;
+            // Still objects stop causing motion, so while one may be
+            // loitering detection keeps running every few seconds without it
+            let loitering_check = !motion
+                && has_detection
+                && targets.first().is_some_and(|t| {
+                    self.loitering
+                        .as_mut()
+                        .is_some_and(|l| l.wants_check(t.start_pts))
+                });
+            if !(motion || loitering_check) || targets.is_empty() {
+                continue;
+            }
+            self.last_target = targets.last().map(|t| t.seq);
+
+            let mask_jpeg = self.detector.fg_mask_jpeg();
+            let bbox = self.last_motion_bbox.map(|r| BoundingBox {
+                x: r.x as f32 / ANALYSIS_WIDTH as f32,
+                y: r.y as f32 / ANALYSIS_HEIGHT as f32,
+                width: r.width as f32 / ANALYSIS_WIDTH as f32,
+                height: r.height as f32 / ANALYSIS_HEIGHT as f32,
+            });
+            for target in targets.iter().filter(|_| motion) {
                 self.motion_store.insert(
                     &self.camera_id,
                     MotionEntry {
-                        segment_sequence: seq,
-                        start_time_ns: start_pts,
-                        end_time_ns: start_pts + duration_ns,
+                        segment_sequence: target.seq,
+                        start_time_ns: target.start_pts,
+                        end_time_ns: target.start_pts + target.duration_ns,
                         motion_score: score,
-                        mask_jpeg,
+                        mask_jpeg: mask_jpeg.clone(),
+                        bbox,
+                        zones: self.last_zones.clone(),
                     },
                 );
 
                 tracing::debug!(
                     camera = %self.camera_id,
-                    sequence = seq,
+                    sequence = target.seq,
                     score = format!("{:.3}", score),
                     threshold = format!("{:.3}", threshold),
                     samples = self.score_histogram.samples(),
                     "motion detected"
                 );
+            }
 
-                if has_detection {
+            if has_detection {
+                if self.detect_buffer.is_none() {
                     motion_segments.push(MotionSegment {
-                        seq,
-                        data,
-                        duration_ns,
+                        seq: pending.seq,
+                        start_pts: targets[0].start_pts,
+                        duration_ns: pending.duration_ns,
+                        inputs: self.detection_inputs(&decoded, motion),
                     });
+                } else {
+                    // Objects are detected on the full-resolution main stream
+                    for target in &targets {
+                        let segment = {
+                            let buffer = self.buffer.read().map_err(|_| "buffer lock poisoned")?;
+                            buffer
+                                .get_segment_by_sequence(target.seq)
+                                .map(|s| (s.data.clone(), s.duration_ns))
+                        };
+                        let Some((data, duration_ns)) = segment else {
+                            continue;
+                        };
+                        let decoded = self.decode(&data, duration_ns, true)?;
+                        motion_segments.push(MotionSegment {
+                            seq: target.seq,
+                            start_pts: target.start_pts,
+                            duration_ns,
+                            inputs: self.detection_inputs(&decoded, motion),
+                        });
+                    }
                 }
             }
-
-            self.last_processed = seq + 1;
         }
 
         // Phase 2: Sampled object detection
         if !motion_segments.is_empty() {
             self.run_sampled_detections(motion_segments);
+            self.update_tracks();
+        }
+        if let (Some(loitering), Some(now)) = (self.loitering.as_mut(), latest_pts) {
+            loitering.expire(now);
         }
 
         Ok(())
     }
 
-    fn analyze_segment(
+    /// Segments not yet analysed, from the substream when there is one. A
+    /// substream segment waits until the main stream has caught up with its
+    /// end, so that every main-stream segment it overlaps is known.
+    fn pending_segments(
+        &mut self,
+    ) -> Result<Vec<PendingSegment>, Box<dyn std::error::Error + Send + Sync>> {
+        let buffer = self.buffer.read().map_err(|_| "buffer lock poisoned")?;
+        let first_seq = buffer.first_sequence();
+        if first_seq > 0 {
+            self.motion_store.cleanup(&self.camera_id, first_seq);
+            if let Some(ref ds) = self.detection_store {
+                ds.cleanup(&self.camera_id, first_seq);
+            }
+            self.alert_store.cleanup(&self.camera_id, first_seq);
+        }
+
+        let Some(detect_buffer) = &self.detect_buffer else {
+            self.last_processed = self.last_processed.max(first_seq);
+            let mut segments = Vec::new();
+            for seq in self.last_processed..buffer.last_sequence() {
+                if let Some(segment) = buffer.get_segment_by_sequence(seq) {
+                    segments.push(PendingSegment {
+                        seq,
+                        data: segment.data.clone(),
+                        duration_ns: segment.duration_ns,
+                        targets: vec![TargetSegment {
+                            seq,
+                            start_pts: segment.start_pts,
+                            duration_ns: segment.duration_ns,
+                        }],
+                    });
+                }
+            }
+            return Ok(segments);
+        };
+
+        let detect = detect_buffer
+            .read()
+            .map_err(|_| "detect buffer lock poisoned")?;
+        self.last_processed = self.last_processed.max(detect.first_sequence());
+        let covered_until = buffer.end_pts().unwrap_or(0);
+
+        let mut segments = Vec::new();
+        for seq in self.last_processed..detect.last_sequence() {
+            let Some(segment) = detect.get_segment_by_sequence(seq) else {
+                continue;
+            };
+            let end_pts = segment.start_pts + segment.duration_ns;
+            if end_pts > covered_until {
+                break;
+            }
+            let targets = buffer
+                .segments_in_range(segment.start_pts, end_pts)
+                .map(|(seq, main)| TargetSegment {
+                    seq,
+                    start_pts: main.start_pts,
+                    duration_ns: main.duration_ns,
+                })
+                .collect();
+            segments.push(PendingSegment {
+                seq,
+                data: segment.data.clone(),
+                duration_ns: segment.duration_ns,
+                targets,
+            });
+        }
+        Ok(segments)
+    }
+
+    /// Decode a segment of the analysed stream, or with `main` of the main
+    /// stream, starting that stream's decoder at the segment's resolution
+    fn decode(
         &mut self,
         data: &[u8],
         duration_ns: u64,
-    ) -> Result<f32, Box<dyn std::error::Error + Send + Sync>> {
-        let raw_frames = self.decoder.decode_segment(data, duration_ns);
+        main: bool,
+    ) -> Result<DecodedFrames, Box<dyn std::error::Error + Send + Sync>> {
+        let sample_fps = self.config.sample_fps;
+        let slot = if main {
+            &mut self.main_decoder
+        } else {
+            &mut self.decoder
+        };
 
-        if raw_frames.is_empty() {
+        if slot.is_none() {
+            let Some(params) = fmp4::demux(data).params else {
+                tracing::trace!(camera = %self.camera_id, "segment without parameter sets, skipping");
+                return Ok(DecodedFrames {
+                    width: 0,
+                    height: 0,
+                    frames: Vec::new(),
+                });
+            };
+            match FrameDecoder::new(
+                &self.camera_id,
+                sample_fps,
+                params.width as u32,
+                params.height as u32,
+            ) {
+                Ok(d) => *slot = Some(d),
+                Err(e) => {
+                    thread::sleep(Duration::from_secs(5));
+                    return Err(format!("failed to start decoder: {e}").into());
+                }
+            }
+        }
+
+        let decoder = slot.as_ref().ok_or("decoder not running")?;
+        Ok(DecodedFrames {
+            width: decoder.width() as i32,
+            height: decoder.height() as i32,
+            frames: decoder.decode_segment(data, duration_ns),
+        })
+    }
+
+    fn analyze_frames(
+        &mut self,
+        decoded: &DecodedFrames,
+    ) -> Result<f32, Box<dyn std::error::Error + Send + Sync>> {
+        if decoded.frames.is_empty() {
             return Ok(0.0);
         }
 
-        let height = self.decoder.height() as i32;
         let mut total_score = 0.0f32;
         let mut frame_count = 0u32;
         let mut last_bbox = None;
+        let mut zones: Vec<String> = Vec::new();
 
-        for frame_data in &raw_frames {
+        for frame_data in &decoded.frames {
             let mat = Mat::from_slice(frame_data)?;
-            let mat = mat.reshape(1, height)?;
+            let frame = mat.reshape(3, decoded.height)?;
+
+            // Motion works on a small grayscale copy of the decoded frame
+            let mut small = Mat::default();
+            imgproc::resize(
+                &frame,
+                &mut small,
+                Size::new(ANALYSIS_WIDTH, ANALYSIS_HEIGHT),
+                0.0,
+                0.0,
+                imgproc::INTER_AREA,
+            )?;
+            let mut gray = Mat::default();
+            imgproc::cvt_color(&small, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
+
+            if let Some(tamper) = self.tamper.as_mut() {
+                let change = tamper.detector.process(
+                    gray.data_bytes()?,
+                    ANALYSIS_WIDTH as usize,
+                    ANALYSIS_HEIGHT as usize,
+                );
+                match change {
+                    Some(change) => tamper.changes.push((change, encode_jpeg(&frame))),
+                    None if tamper.detector.wants_reference() => {
+                        tamper.reference_jpeg = encode_jpeg(&frame);
+                    }
+                    None => {}
+                }
+            }
 
-            match self.detector.process_frame(&mat) {
+            match self.detector.process_frame(&gray) {
                 Ok(score) => {
                     total_score += score;
                     frame_count += 1;
                     if let Some(bbox) = self.detector.motion_bbox() {
                         last_bbox = Some(bbox);
                     }
+                    if score > 0.0 {
+                        for zone in self.detector.active_zones() {
+                            if !zones.contains(&zone) {
+                                zones.push(zone);
+                            }
+                        }
+                    }
                 }
                 Err(e) => {
                     tracing::trace!(error = %e, "frame processing error");
@@ -261,6 +552,7 @@ impl MotionAnalyzer {
         }
 
         self.last_motion_bbox = last_bbox;
+        self.last_zones = zones;
 
         if frame_count == 0 {
             return Ok(0.0);
@@ -269,23 +561,69 @@ impl MotionAnalyzer {
         Ok(total_score / frame_count as f32)
     }
 
-    fn detect_segment(&mut self, data: &[u8], duration_ns: u64) -> Option<SegmentDetectionResult> {
-        let crop_decoder = self.crop_decoder.as_ref()?;
-
-        let raw_frames = crop_decoder.decode_segment(data, duration_ns);
-        if raw_frames.is_empty() {
-            return None;
+    /// Turn queued tamper changes into alerts against the main-stream
+    /// segment the last analysed segment started in, keeping them queued
+    /// while that isn't known yet
+    fn record_tamper(&mut self, target: Option<&TargetSegment>) {
+        let Some(tamper) = self.tamper.as_mut() else {
+            return;
+        };
+        let Some(target) = target else {
+            return;
+        };
+        let changes = std::mem::take(&mut tamper.changes);
+
+        for (change, frame_jpeg) in changes {
+            match change {
+                TamperChange::Started(kind) => {
+                    tracing::warn!(camera = %self.camera_id, kind = ?kind, "camera tamper detected");
+                    let id = self.alert_store.insert(
+                        &self.camera_id,
+                        AlertEntry {
+                            id: 0,
+                            segment_sequence: target.seq,
+                            time_ns: target.start_pts,
+                            end_ns: None,
+                            kind,
+                            rule: "tamper".to_string(),
+                            track_id: None,
+                            object_class: None,
+                            frame_jpeg,
+                            before_jpeg: tamper.reference_jpeg.clone(),
+                        },
+                    );
+                    tamper.alert_id = Some(id);
+                }
+                TamperChange::Ended => {
+                    tracing::info!(camera = %self.camera_id, "camera tamper cleared");
+                    if let Some(id) = tamper.alert_id.take() {
+                        self.alert_store.set_end(
+                            &self.camera_id,
+                            id,
+                            target.start_pts + target.duration_ns,
+                        );
+                    }
+                }
+            }
         }
+    }
 
-        let height = crop_decoder.height() as i32;
-        let crop_rect = self.crop_region();
+    /// Cut detector inputs from decoded frames, around the latest motion
+    /// with `crop_to_motion`
+    fn detection_inputs(&self, decoded: &DecodedFrames, crop_to_motion: bool) -> DetectionInputs {
+        let crop_rect = if crop_to_motion {
+            self.crop_region(decoded.width, decoded.height)
+        } else {
+            None
+        };
+        let mut inputs = Vec::with_capacity(decoded.frames.len());
 
-        for frame_data in raw_frames.iter() {
+        for frame_data in &decoded.frames {
             let mat = match Mat::from_slice(frame_data) {
                 Ok(m) => m,
                 Err(_) => continue,
             };
-            let reshaped = match mat.reshape(3, height) {
+            let reshaped = match mat.reshape(3, decoded.height) {
                 Ok(m) => m,
                 Err(_) => continue,
             };
@@ -315,13 +653,26 @@ impl MotionAnalyzer {
                     resized
                 }
             };
+            inputs.push(detection_input);
+        }
+
+        DetectionInputs {
+            frames: inputs,
+            crop: crop_rect,
+            frame_width: decoded.width,
+            frame_height: decoded.height,
+        }
+    }
 
-            let object_detector = match &mut self.object_detector {
+    fn detect_segment(&mut self, segment: &MotionSegment) -> Option<SegmentDetectionResult> {
+        let frame_count = segment.inputs.frames.len() as u64;
+        for (index, detection_input) in segment.inputs.frames.iter().enumerate() {
+            let object_detector = match &self.object_detector {
                 Some(d) => d,
                 None => return None,
             };
 
-            let detections = match object_detector.detect(&detection_input) {
+            let detections = match object_detector.detect(detection_input) {
                 Ok(d) => d,
                 Err(e) => {
                     tracing::trace!(error = %e, "object detection error");
@@ -333,21 +684,38 @@ impl MotionAnalyzer {
                 continue;
             }
 
-            let frame_jpeg = match encode_jpeg(&detection_input) {
+            let frame_jpeg = match encode_jpeg(detection_input) {
                 Some(j) => j,
                 None => continue,
             };
 
+            let detections: Vec<Detection> = detections
+                .into_iter()
+                .map(|det| Detection {
+                    bbox: segment.inputs.to_frame(det.bbox),
+                    ..det
+                })
+                .collect();
+            self.observations.push(Observation {
+                time_ns: segment.start_pts + segment.duration_ns * index as u64 / frame_count,
+                seq: segment.seq,
+                detections: detections.clone(),
+                frame_jpeg: frame_jpeg.clone(),
+            });
+
             let mut classes = Vec::with_capacity(detections.len());
             let mut confidences = Vec::with_capacity(detections.len());
+            let mut boxes = Vec::with_capacity(detections.len());
             for det in detections {
                 classes.push(det.class_name);
                 confidences.push(det.confidence);
+                boxes.push(det.bbox);
             }
 
             return Some(SegmentDetectionResult {
                 classes,
                 confidences,
+                boxes,
                 frame_jpeg,
             });
         }
@@ -361,12 +729,18 @@ impl MotionAnalyzer {
             None => return,
         };
 
-        for (class, &confidence) in result.classes.iter().zip(&result.confidences) {
+        for ((class, &confidence), &bbox) in result
+            .classes
+            .iter()
+            .zip(&result.confidences)
+            .zip(&result.boxes)
+        {
             detection_store.insert(
                 &self.camera_id,
                 seq,
                 class.clone(),
                 confidence,
+                Some(bbox),
                 result.frame_jpeg.clone(),
             );
 
@@ -380,6 +754,67 @@ impl MotionAnalyzer {
         }
     }
 
+    /// Feed this pass's detections to the tracker in time order; `detect_run`
+    /// visits segments out of order
+    fn update_tracks(&mut self) {
+        let mut observations = std::mem::take(&mut self.observations);
+        observations.sort_by_key(|o| o.time_ns);
+        let Some(detection_store) = &self.detection_store else {
+            return;
+        };
+
+        for observation in observations {
+            let updated = self.tracker.update(
+                observation.time_ns,
+                observation.seq,
+                &observation.detections,
+            );
+            for track in updated {
+                if let Some(engine) = self.rule_engine.as_mut() {
+                    for hit in engine.evaluate(track) {
+                        tracing::debug!(
+                            camera = %self.camera_id,
+                            rule = %hit.rule,
+                            track = hit.track_id,
+                            class = %hit.object_class,
+                            "rule fired"
+                        );
+                        self.alert_store.insert(
+                            &self.camera_id,
+                            AlertEntry {
+                                id: 0,
+                                segment_sequence: hit.sequence,
+                                time_ns: hit.time_ns,
+                                end_ns: None,
+                                kind: hit.kind,
+                                rule: hit.rule,
+                                track_id: Some(hit.track_id),
+                                object_class: Some(hit.object_class),
+                                frame_jpeg: None,
+                                before_jpeg: None,
+                            },
+                        );
+                    }
+                }
+                if let Some(loitering) = self.loitering.as_mut() {
+                    loitering.observe(track, &observation.frame_jpeg);
+                }
+                detection_store.update_track(
+                    &self.camera_id,
+                    TrackEntry {
+                        id: track.id,
+                        object_class: track.object_class.clone(),
+                        confidence: track.confidence,
+                        start_ns: track.start_ns,
+                        end_ns: track.end_ns,
+                        last_sequence: track.last_sequence,
+                        trajectory: track.trajectory.clone(),
+                    },
+                );
+            }
+        }
+    }
+
     fn run_sampled_detections(&mut self, segments: Vec<MotionSegment>) {
         let runs = group_contiguous_runs(segments);
         for run in runs {
@@ -391,15 +826,15 @@ impl MotionAnalyzer {
         let len = run.len();
         if len <= 2 {
             for seg in &run {
-                if let Some(result) = self.detect_segment(&seg.data, seg.duration_ns) {
+                if let Some(result) = self.detect_segment(seg) {
                     self.store_detection_result(seg.seq, &result);
                 }
             }
             return;
         }
 
-        let first_result = self.detect_segment(&run[0].data, run[0].duration_ns);
-        let last_result = self.detect_segment(&run[len - 1].data, run[len - 1].duration_ns);
+        let first_result = self.detect_segment(&run[0]);
+        let last_result = self.detect_segment(&run[len - 1]);
 
         let boundaries_agree = match (&first_result, &last_result) {
             (Some(first), Some(last)) => {
@@ -434,9 +869,17 @@ impl MotionAnalyzer {
                     &last_result
                 };
 
+                // Boxes follow the frame; the nearest boundary's only line up
+                // with the first's classes when both list them in order
+                let boxes = if nearest.classes == first_result.classes {
+                    nearest.boxes.clone()
+                } else {
+                    first_result.boxes.clone()
+                };
                 let propagated = SegmentDetectionResult {
                     classes: first_result.classes.clone(),
                     confidences: min_confidences.clone(),
+                    boxes,
                     frame_jpeg: nearest.frame_jpeg.clone(),
                 };
 
@@ -469,11 +912,16 @@ impl MotionAnalyzer {
         }
     }
 
-    fn crop_region(&self) -> Option<Rect> {
+    /// Detection-sized window centred on the motion, in a `width` x `height`
+    /// frame. `None` when the motion doesn't fit or the frame is too small.
+    fn crop_region(&self, width: i32, height: i32) -> Option<Rect> {
         let bbox = self.last_motion_bbox?;
+        if width < DETECTION_WIDTH || height < DETECTION_HEIGHT {
+            return None;
+        }
 
-        let scale_x = CROP_DECODE_WIDTH as f32 / ANALYSIS_WIDTH as f32;
-        let scale_y = CROP_DECODE_HEIGHT as f32 / ANALYSIS_HEIGHT as f32;
+        let scale_x = width as f32 / ANALYSIS_WIDTH as f32;
+        let scale_y = height as f32 / ANALYSIS_HEIGHT as f32;
 
         let center_x = ((bbox.x as f32 + bbox.width as f32 / 2.0) * scale_x) as i32;
         let center_y = ((bbox.y as f32 + bbox.height as f32 / 2.0) * scale_y) as i32;
@@ -485,8 +933,8 @@ impl MotionAnalyzer {
             return None;
         }
 
-        let x = (center_x - DETECTION_WIDTH / 2).clamp(0, CROP_DECODE_WIDTH - DETECTION_WIDTH);
-        let y = (center_y - DETECTION_HEIGHT / 2).clamp(0, CROP_DECODE_HEIGHT - DETECTION_HEIGHT);
+        let x = (center_x - DETECTION_WIDTH / 2).clamp(0, width - DETECTION_WIDTH);
+        let y = (center_y - DETECTION_HEIGHT / 2).clamp(0, height - DETECTION_HEIGHT);
 
         Some(Rect::new(x, y, DETECTION_WIDTH, DETECTION_HEIGHT))
     }
@@ -521,12 +969,15 @@ fn encode_jpeg(mat: &Mat) -> Option<Vec<u8>> {
     Some(buf.to_vec())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_analyzer(
     camera_id: String,
     buffer: Arc<RwLock<HotBuffer>>,
+    detect_buffer: Option<Arc<RwLock<HotBuffer>>>,
     motion_store: MotionStore,
     detection_store: Option<DetectionStore>,
-    object_detector: Option<ObjectDetector>,
+    alert_store: AlertStore,
+    object_detector: Option<InferenceClient>,
     config: AnalyticsConfig,
     shutdown: Arc<AtomicBool>,
 ) -> tokio::task::JoinHandle<()> {
@@ -534,8 +985,10 @@ pub fn spawn_analyzer(
         match MotionAnalyzer::new(
             camera_id.clone(),
             buffer,
+            detect_buffer,
             motion_store,
             detection_store,
+            alert_store,
             object_detector,
             config,
         ) {
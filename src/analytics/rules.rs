@@ -0,0 +1,335 @@
+//! Per-camera rules on tracked objects: directed line crossings and zone
+//! enter, exit and dwell. A track's position is the bottom centre of its box,
+//! roughly where the object touches the ground.
+
+use std::collections::HashMap;
+
+use crate::config::{LineDirection, RuleConfig, RuleKind, ZoneConfig, ZoneTrigger};
+use crate::storage::{AlertKind, BoundingBox};
+
+use super::tracker::{Track, TRACK_TIMEOUT_NS};
+use super::zone;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+pub struct RuleHit {
+    pub rule: String,
+    pub kind: AlertKind,
+    pub track_id: u64,
+    pub object_class: String,
+    pub time_ns: u64,
+    pub sequence: u64,
+}
+
+enum Geometry {
+    Line {
+        from: [f32; 2],
+        to: [f32; 2],
+        direction: LineDirection,
+    },
+    Zone {
+        polygon: Vec<[f32; 2]>,
+        trigger: ZoneTrigger,
+        dwell_ns: u64,
+    },
+}
+
+struct Rule {
+    name: String,
+    classes: Vec<String>,
+    geometry: Geometry,
+}
+
+/// Where a track is relative to one zone rule. A track first seen inside
+/// the zone is taken to have been there already: it doesn't fire Enter, and
+/// its dwell time counts from that first sighting.
+struct ZoneState {
+    inside: bool,
+    entered_ns: u64,
+    dwell_fired: bool,
+    last_seen_ns: u64,
+}
+
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    zone_states: HashMap<(usize, u64), ZoneState>,
+}
+
+impl RuleEngine {
+    /// `None` when the camera has no usable rules
+    pub fn new(camera_id: &str, rules: &[RuleConfig], zones: &[ZoneConfig]) -> Option<Self> {
+        let mut compiled = Vec::new();
+        for rule in rules {
+            let geometry = match &rule.kind {
+                RuleKind::Line { points, direction } => Geometry::Line {
+                    from: points[0],
+                    to: points[1],
+                    direction: *direction,
+                },
+                RuleKind::Zone {
+                    zone,
+                    trigger,
+                    dwell_secs,
+                } => match zones.iter().find(|z| &z.name == zone) {
+                    Some(z) => Geometry::Zone {
+                        polygon: z.points.clone(),
+                        trigger: *trigger,
+                        dwell_ns: dwell_secs * NANOS_PER_SEC,
+                    },
+                    None => {
+                        tracing::warn!(
+                            camera = %camera_id,
+                            rule = %rule.name,
+                            zone = %zone,
+                            "rule refers to an unknown zone, ignoring it"
+                        );
+                        continue;
+                    }
+                },
+            };
+            compiled.push(Rule {
+                name: rule.name.clone(),
+                classes: rule.classes.clone(),
+                geometry,
+            });
+        }
+
+        if compiled.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rules: compiled,
+            zone_states: HashMap::new(),
+        })
+    }
+
+    /// Check a track that just got a new observation
+    pub fn evaluate(&mut self, track: &Track) -> Vec<RuleHit> {
+        let mut hits = Vec::new();
+        let Some(&(time_ns, bbox)) = track.trajectory.last() else {
+            return hits;
+        };
+        let position = anchor(bbox);
+        let previous = track
+            .trajectory
+            .len()
+            .checked_sub(2)
+            .map(|i| anchor(track.trajectory[i].1));
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.classes.is_empty() && !rule.classes.contains(&track.object_class) {
+                continue;
+            }
+            let kind = match &rule.geometry {
+                Geometry::Line {
+                    from,
+                    to,
+                    direction,
+                } => previous.and_then(|previous| {
+                    crosses(*from, *to, previous, position, *direction)
+                        .then_some(AlertKind::LineCrossed)
+                }),
+                Geometry::Zone {
+                    polygon,
+                    trigger,
+                    dwell_ns,
+                } => {
+                    let inside = zone::contains(polygon, position[0], position[1]);
+                    let state = self
+                        .zone_states
+                        .entry((index, track.id))
+                        .or_insert_with(|| {
+                            let (first_ns, first_bbox) = track.trajectory[0];
+                            let first = anchor(first_bbox);
+                            ZoneState {
+                                inside: zone::contains(polygon, first[0], first[1]),
+                                entered_ns: first_ns,
+                                dwell_fired: false,
+                                last_seen_ns: time_ns,
+                            }
+                        });
+                    state.last_seen_ns = time_ns;
+                    let entered = inside && !state.inside;
+                    let exited = !inside && state.inside;
+                    if entered {
+                        state.entered_ns = time_ns;
+                        state.dwell_fired = false;
+                    }
+                    state.inside = inside;
+
+                    match trigger {
+                        ZoneTrigger::Enter if entered => Some(AlertKind::ZoneEnter),
+                        ZoneTrigger::Exit if exited => Some(AlertKind::ZoneExit),
+                        ZoneTrigger::Dwell
+                            if inside
+                                && !state.dwell_fired
+                                && time_ns.saturating_sub(state.entered_ns) >= *dwell_ns =>
+                        {
+                            state.dwell_fired = true;
+                            Some(AlertKind::ZoneDwell)
+                        }
+                        _ => None,
+                    }
+                }
+            };
+
+            if let Some(kind) = kind {
+                hits.push(RuleHit {
+                    rule: rule.name.clone(),
+                    kind,
+                    track_id: track.id,
+                    object_class: track.object_class.clone(),
+                    time_ns,
+                    sequence: track.last_sequence,
+                });
+            }
+        }
+
+        self.zone_states
+            .retain(|_, s| time_ns.saturating_sub(s.last_seen_ns) <= TRACK_TIMEOUT_NS);
+        hits
+    }
+}
+
+/// Bottom centre of a box
+fn anchor(bbox: BoundingBox) -> [f32; 2] {
+    [bbox.x + bbox.width / 2.0, bbox.y + bbox.height]
+}
+
+/// Which side of the line `from`→`to` a point is on: positive is the right
+/// hand side looking along the line, with y pointing down
+fn side(from: [f32; 2], to: [f32; 2], point: [f32; 2]) -> f32 {
+    (to[0] - from[0]) * (point[1] - from[1]) - (to[1] - from[1]) * (point[0] - from[0])
+}
+
+/// Whether moving from `a` to `b` crosses the segment `from`–`to` in the
+/// wanted direction
+fn crosses(
+    from: [f32; 2],
+    to: [f32; 2],
+    a: [f32; 2],
+    b: [f32; 2],
+    direction: LineDirection,
+) -> bool {
+    let side_a = side(from, to, a);
+    let side_b = side(from, to, b);
+    // Points on the line count as right of it, so touching it and moving
+    // on is one crossing
+    if (side_a < 0.0) == (side_b < 0.0) {
+        return false;
+    }
+    // The path must also pass within the segment's extent
+    if side(a, b, from) * side(a, b, to) > 0.0 {
+        return false;
+    }
+    match direction {
+        LineDirection::Both => true,
+        LineDirection::Right => side_b >= 0.0,
+        LineDirection::Left => side_b < 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analytics::object::Detection;
+    use crate::analytics::tracker::Tracker;
+    use crate::config::ZoneKind;
+
+    fn person(x: f32) -> Detection {
+        Detection {
+            class_name: "person".to_string(),
+            confidence: 0.9,
+            bbox: BoundingBox {
+                x,
+                y: 0.3,
+                width: 0.3,
+                height: 0.3,
+            },
+        }
+    }
+
+    #[test]
+    fn test_line_and_zone_rules() {
+        let zones = [ZoneConfig {
+            name: "porch".to_string(),
+            kind: ZoneKind::Include,
+            points: vec![[0.58, 0.0], [1.0, 0.0], [1.0, 1.0], [0.58, 1.0]],
+        }];
+        let rule = |name: &str, kind| RuleConfig {
+            name: name.to_string(),
+            classes: vec!["person".to_string()],
+            kind,
+        };
+        let rules = [
+            // Vertical line at x = 0.4 drawn downwards; walking right ends
+            // up on its left
+            rule(
+                "inbound",
+                RuleKind::Line {
+                    points: [[0.4, 0.0], [0.4, 1.0]],
+                    direction: LineDirection::Left,
+                },
+            ),
+            rule(
+                "outbound",
+                RuleKind::Line {
+                    points: [[0.4, 0.0], [0.4, 1.0]],
+                    direction: LineDirection::Right,
+                },
+            ),
+            rule(
+                "on porch",
+                RuleKind::Zone {
+                    zone: "porch".to_string(),
+                    trigger: ZoneTrigger::Enter,
+                    dwell_secs: 0,
+                },
+            ),
+            rule(
+                "lingering",
+                RuleKind::Zone {
+                    zone: "porch".to_string(),
+                    trigger: ZoneTrigger::Dwell,
+                    dwell_secs: 2,
+                },
+            ),
+        ];
+        let mut engine = RuleEngine::new("test", &rules, &zones).unwrap();
+        let mut tracker = Tracker::default();
+
+        let mut fired = Vec::new();
+        let steps = [0.0, 0.1, 0.2, 0.3, 0.4, 0.45, 0.47, 0.48];
+        for (step, x) in steps.into_iter().enumerate() {
+            let time = step as u64 * NANOS_PER_SEC;
+            for track in tracker.update(time, step as u64, &[person(x)]) {
+                for hit in engine.evaluate(track) {
+                    fired.push((hit.rule, step));
+                }
+            }
+        }
+
+        assert_eq!(
+            fired,
+            vec![
+                ("inbound".to_string(), 3),
+                ("on porch".to_string(), 5),
+                ("lingering".to_string(), 7),
+            ]
+        );
+
+        // First seen already on the porch: no enter, dwell from that sighting
+        let mut engine = RuleEngine::new("test", &rules, &zones).unwrap();
+        let mut tracker = Tracker::default();
+        let mut fired = Vec::new();
+        for step in 0..4u64 {
+            let time = step * NANOS_PER_SEC;
+            for track in tracker.update(time, step, &[person(0.7)]) {
+                for hit in engine.evaluate(track) {
+                    fired.push((hit.rule, step));
+                }
+            }
+        }
+        assert_eq!(fired, vec![("lingering".to_string(), 2)]);
+    }
+}
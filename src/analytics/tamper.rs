@@ -0,0 +1,318 @@
+//! Camera tamper detection on the grayscale analysis frames: lens covered or
+//! blacked out, camera redirected, defocused, or a sudden drop in brightness.
+//!
+//! Each frame is reduced to a few statistics that are compared against a
+//! slowly adapting baseline of the normal view. A condition has to last a
+//! few seconds to start an alert, and has to be gone as long to end it.
+
+use crate::storage::AlertKind;
+
+/// Baseline adaption per frame while the view is normal
+const BASELINE_RATE: f32 = 0.02;
+/// Seconds of frames before the baseline is trusted
+const WARMUP_SECS: u32 = 10;
+/// Seconds a condition has to hold, or be gone, to start or end an alert
+const HOLD_SECS: u32 = 3;
+/// A view that stays changed this long becomes the new normal
+const REBASELINE_SECS: u32 = 300;
+/// Seconds between reference snapshots of the normal view
+const REFERENCE_SECS: u32 = 10;
+
+/// Below this contrast the frame is close to a single colour
+const COVERED_STDDEV: f32 = 10.0;
+const DARK_RATIO: f32 = 0.5;
+const DARK_MIN_DROP: f32 = 30.0;
+/// Coarse layout correlation with the baseline below which the camera is
+/// looking somewhere else. Correlation ignores overall brightness changes.
+const REDIRECTED_CORRELATION: f32 = 0.4;
+const DEFOCUS_RATIO: f32 = 0.25;
+/// Views with little detail to begin with can't measurably lose focus
+const DEFOCUS_MIN_SHARPNESS: f32 = 20.0;
+
+const THUMB_WIDTH: usize = 16;
+const THUMB_HEIGHT: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TamperChange {
+    Started(AlertKind),
+    Ended,
+}
+
+#[derive(Clone)]
+struct FrameStats {
+    mean: f32,
+    stddev: f32,
+    /// Variance of the Laplacian, high for sharp edges
+    sharpness: f32,
+    /// Block averages over a coarse grid
+    thumb: Vec<f32>,
+}
+
+impl FrameStats {
+    fn new(gray: &[u8], width: usize, height: usize) -> Option<Self> {
+        if width < THUMB_WIDTH || height < THUMB_HEIGHT || gray.len() < width * height {
+            return None;
+        }
+        let pixels = &gray[..width * height];
+
+        let (mean, stddev) = mean_stddev(pixels.iter().map(|&p| p as f32));
+
+        let laplacian = (1..height - 1).flat_map(|y| {
+            (1..width - 1).map(move |x| {
+                let at = |x: usize, y: usize| pixels[y * width + x] as f32;
+                4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1)
+            })
+        });
+        let (_, laplacian_stddev) = mean_stddev(laplacian);
+
+        let block_w = width / THUMB_WIDTH;
+        let block_h = height / THUMB_HEIGHT;
+        let mut thumb = Vec::with_capacity(THUMB_WIDTH * THUMB_HEIGHT);
+        for by in 0..THUMB_HEIGHT {
+            for bx in 0..THUMB_WIDTH {
+                let mut sum = 0u32;
+                for y in by * block_h..(by + 1) * block_h {
+                    let row = &pixels[y * width + bx * block_w..y * width + (bx + 1) * block_w];
+                    sum += row.iter().map(|&p| p as u32).sum::<u32>();
+                }
+                thumb.push(sum as f32 / (block_w * block_h) as f32);
+            }
+        }
+
+        Some(Self {
+            mean,
+            stddev,
+            sharpness: laplacian_stddev * laplacian_stddev,
+            thumb,
+        })
+    }
+
+    fn adapt(&mut self, frame: &FrameStats) {
+        let blend = |base: &mut f32, value: f32| *base += (value - *base) * BASELINE_RATE;
+        blend(&mut self.mean, frame.mean);
+        blend(&mut self.stddev, frame.stddev);
+        blend(&mut self.sharpness, frame.sharpness);
+        for (base, &value) in self.thumb.iter_mut().zip(&frame.thumb) {
+            blend(base, value);
+        }
+    }
+
+    /// What looks wrong with `frame` compared to this baseline
+    fn check(&self, frame: &FrameStats) -> Option<AlertKind> {
+        if frame.stddev < COVERED_STDDEV && self.stddev >= 2.0 * COVERED_STDDEV {
+            return Some(AlertKind::TamperCovered);
+        }
+        if frame.mean < self.mean * DARK_RATIO && self.mean - frame.mean >= DARK_MIN_DROP {
+            return Some(AlertKind::TamperDark);
+        }
+        if correlation(&self.thumb, &frame.thumb) < REDIRECTED_CORRELATION {
+            return Some(AlertKind::TamperRedirected);
+        }
+        if self.sharpness >= DEFOCUS_MIN_SHARPNESS
+            && frame.sharpness < self.sharpness * DEFOCUS_RATIO
+        {
+            return Some(AlertKind::TamperDefocused);
+        }
+        None
+    }
+}
+
+fn mean_stddev(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    let (mut count, mut sum, mut sum_sq) = (0u64, 0f64, 0f64);
+    for v in values {
+        count += 1;
+        sum += v as f64;
+        sum_sq += (v as f64) * (v as f64);
+    }
+    if count == 0 {
+        return (0.0, 0.0);
+    }
+    let mean = sum / count as f64;
+    let variance = (sum_sq / count as f64 - mean * mean).max(0.0);
+    (mean as f32, variance.sqrt() as f32)
+}
+
+/// Pearson correlation, 0.0 when either side is flat
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len()) as f32;
+    let mean_a = a.iter().sum::<f32>() / n;
+    let mean_b = b.iter().sum::<f32>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (&x, &y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a) * (x - mean_a);
+        var_b += (y - mean_b) * (y - mean_b);
+    }
+    if var_a <= f32::EPSILON || var_b <= f32::EPSILON {
+        return 0.0;
+    }
+    cov / (var_a.sqrt() * var_b.sqrt())
+}
+
+pub struct TamperDetector {
+    baseline: Option<FrameStats>,
+    frames: u32,
+    warmup_frames: u32,
+    hold_frames: u32,
+    rebaseline_frames: u32,
+    reference_frames: u32,
+    /// Condition seen in the latest frames, and for how many in a row
+    candidate: Option<(AlertKind, u32)>,
+    /// Ongoing alert with how long it has lasted and how long the view has
+    /// looked normal again
+    active: Option<(u32, u32)>,
+    /// Normal frames since the last reference snapshot, if there is one
+    since_reference: Option<u32>,
+}
+
+impl TamperDetector {
+    pub fn new(sample_fps: u32) -> Self {
+        let fps = sample_fps.max(1);
+        Self {
+            baseline: None,
+            frames: 0,
+            warmup_frames: WARMUP_SECS * fps,
+            hold_frames: HOLD_SECS * fps,
+            rebaseline_frames: REBASELINE_SECS * fps,
+            reference_frames: REFERENCE_SECS * fps,
+            candidate: None,
+            active: None,
+            since_reference: None,
+        }
+    }
+
+    /// Check the next sampled frame
+    pub fn process(&mut self, gray: &[u8], width: usize, height: usize) -> Option<TamperChange> {
+        let stats = FrameStats::new(gray, width, height)?;
+        let Some(baseline) = self.baseline.as_mut() else {
+            self.baseline = Some(stats);
+            return None;
+        };
+
+        self.frames = self.frames.saturating_add(1);
+        if self.frames < self.warmup_frames {
+            baseline.adapt(&stats);
+            return None;
+        }
+        let condition = baseline.check(&stats);
+
+        if let Some((lasted, normal)) = self.active.as_mut() {
+            *lasted += 1;
+            *normal = if condition.is_none() { *normal + 1 } else { 0 };
+            if *lasted >= self.rebaseline_frames {
+                *baseline = stats;
+            } else if *normal < self.hold_frames {
+                return None;
+            }
+            self.active = None;
+            self.candidate = None;
+            self.since_reference = None;
+            return Some(TamperChange::Ended);
+        }
+
+        match condition {
+            Some(kind) => {
+                let seen = match self.candidate {
+                    Some((candidate, seen)) if candidate == kind => seen + 1,
+                    _ => 1,
+                };
+                self.candidate = Some((kind, seen));
+                if seen >= self.hold_frames {
+                    self.active = Some((0, 0));
+                    return Some(TamperChange::Started(kind));
+                }
+            }
+            None => {
+                self.candidate = None;
+                baseline.adapt(&stats);
+                if let Some(frames) = self.since_reference.as_mut() {
+                    *frames += 1;
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the last frame should be kept as the "before" snapshot: the
+    /// view is normal and the previous snapshot is getting old
+    pub fn wants_reference(&mut self) -> bool {
+        if self.active.is_some() || self.candidate.is_some() || self.frames < self.warmup_frames {
+            return false;
+        }
+        if self
+            .since_reference
+            .is_some_and(|frames| frames < self.reference_frames)
+        {
+            return false;
+        }
+        self.since_reference = Some(0);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 320;
+    const HEIGHT: usize = 240;
+
+    /// Horizontal gradient with fine checkerboard texture, scaled by `gain`
+    fn scene(gain: f32, texture: bool) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(WIDTH * HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let mut v = 40.0 + 160.0 * x as f32 / WIDTH as f32;
+                if texture && (x / 2 + y / 2) % 2 == 0 {
+                    v += 40.0;
+                }
+                frame.push((v * gain).min(255.0) as u8);
+            }
+        }
+        frame
+    }
+
+    /// Feed `frame` `count` times and return the changes seen
+    fn feed(detector: &mut TamperDetector, frame: &[u8], count: usize) -> Vec<TamperChange> {
+        (0..count)
+            .filter_map(|_| detector.process(frame, WIDTH, HEIGHT))
+            .collect()
+    }
+
+    #[test]
+    fn test_tamper_conditions() {
+        let mut detector = TamperDetector::new(5);
+        let normal = scene(1.0, true);
+        assert!(feed(&mut detector, &normal, 60).is_empty());
+        assert!(detector.wants_reference());
+        assert!(!detector.wants_reference());
+
+        let covered = vec![0u8; WIDTH * HEIGHT];
+        assert_eq!(
+            feed(&mut detector, &covered, 15),
+            vec![TamperChange::Started(AlertKind::TamperCovered)]
+        );
+        assert!(!detector.wants_reference());
+        assert_eq!(feed(&mut detector, &normal, 15), vec![TamperChange::Ended]);
+
+        let cases = [
+            (scene(0.25, true), AlertKind::TamperDark),
+            (scene(1.0, false), AlertKind::TamperDefocused),
+            (
+                scene(1.0, true).into_iter().rev().collect(),
+                AlertKind::TamperRedirected,
+            ),
+        ];
+        for (frame, kind) in cases {
+            assert_eq!(
+                feed(&mut detector, &frame, 15),
+                vec![TamperChange::Started(kind)]
+            );
+            assert_eq!(feed(&mut detector, &normal, 15), vec![TamperChange::Ended]);
+        }
+
+        // A brief glitch is not enough
+        assert!(feed(&mut detector, &covered, 5).is_empty());
+        assert!(feed(&mut detector, &normal, 30).is_empty());
+    }
+}
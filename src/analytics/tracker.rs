@@ -0,0 +1,283 @@
+//! SORT/ByteTrack-style multi-object tracking over sampled detections.
+//!
+//! Each track carries a constant-velocity Kalman filter per box coordinate.
+//! Detections are matched to predicted boxes greedily by IoU, confident ones
+//! first, and only confident detections start new tracks.
+
+use crate::storage::BoundingBox;
+
+use super::object::Detection;
+
+/// Detections below this only extend existing tracks
+const HIGH_CONFIDENCE: f32 = 0.5;
+const MIN_IOU: f32 = 0.2;
+/// Tracks unseen for this long are finished. Detection is sampled, so gaps
+/// of several segments are normal.
+pub const TRACK_TIMEOUT_NS: u64 = 10_000_000_000;
+const PROCESS_NOISE: f32 = 0.05;
+const MEASUREMENT_NOISE: f32 = 0.01;
+
+/// One coordinate with its velocity (per second) and covariance
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    pos: f32,
+    vel: f32,
+    p: [[f32; 2]; 2],
+}
+
+impl Axis {
+    fn new(pos: f32) -> Self {
+        Self {
+            pos,
+            vel: 0.0,
+            p: [[MEASUREMENT_NOISE, 0.0], [0.0, 1.0]],
+        }
+    }
+
+    fn predict(&mut self, dt: f32) {
+        self.pos += self.vel * dt;
+        let [[p00, p01], [p10, p11]] = self.p;
+        let q = PROCESS_NOISE * dt;
+        self.p = [
+            [p00 + dt * (p10 + p01) + dt * dt * p11 + q, p01 + dt * p11],
+            [p10 + dt * p11, p11 + q],
+        ];
+    }
+
+    fn update(&mut self, measured: f32) {
+        let [[p00, p01], [p10, p11]] = self.p;
+        let s = p00 + MEASUREMENT_NOISE;
+        let (k0, k1) = (p00 / s, p10 / s);
+        let residual = measured - self.pos;
+        self.pos += k0 * residual;
+        self.vel += k1 * residual;
+        self.p = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+/// Box as centre and size, each filtered independently
+#[derive(Debug, Clone, Copy)]
+struct BoxFilter {
+    axes: [Axis; 4],
+}
+
+impl BoxFilter {
+    fn new(bbox: BoundingBox) -> Self {
+        let [cx, cy, w, h] = centre_size(bbox);
+        Self {
+            axes: [Axis::new(cx), Axis::new(cy), Axis::new(w), Axis::new(h)],
+        }
+    }
+
+    fn predicted(&self, dt: f32) -> BoundingBox {
+        let mut filter = *self;
+        filter.predict(dt);
+        filter.bbox()
+    }
+
+    fn predict(&mut self, dt: f32) {
+        for axis in &mut self.axes {
+            axis.predict(dt);
+        }
+    }
+
+    fn update(&mut self, bbox: BoundingBox) {
+        for (axis, measured) in self.axes.iter_mut().zip(centre_size(bbox)) {
+            axis.update(measured);
+        }
+    }
+
+    fn bbox(&self) -> BoundingBox {
+        let [cx, cy, w, h] = self.axes.map(|a| a.pos);
+        let (w, h) = (w.max(0.0), h.max(0.0));
+        BoundingBox {
+            x: cx - w / 2.0,
+            y: cy - h / 2.0,
+            width: w,
+            height: h,
+        }
+    }
+}
+
+fn centre_size(bbox: BoundingBox) -> [f32; 4] {
+    [
+        bbox.x + bbox.width / 2.0,
+        bbox.y + bbox.height / 2.0,
+        bbox.width,
+        bbox.height,
+    ]
+}
+
+pub fn iou(a: BoundingBox, b: BoundingBox) -> f32 {
+    let x1 = a.x.max(b.x);
+    let y1 = a.y.max(b.y);
+    let x2 = (a.x + a.width).min(b.x + b.width);
+    let y2 = (a.y + a.height).min(b.y + b.height);
+    let intersection = (x2 - x1).max(0.0) * (y2 - y1).max(0.0);
+    let union = a.width * a.height + b.width * b.height - intersection;
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub id: u64,
+    pub object_class: String,
+    /// Highest confidence seen
+    pub confidence: f32,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Newest main-stream segment the track was seen in
+    pub last_sequence: u64,
+    /// Observed boxes with their stream timestamps
+    pub trajectory: Vec<(u64, BoundingBox)>,
+    filter: BoxFilter,
+}
+
+impl Track {
+    /// Best guess of where the track is at `time_ns`. The last observation
+    /// also counts since the prediction drifts over long sampling gaps.
+    fn overlap(&self, time_ns: u64, bbox: BoundingBox) -> f32 {
+        let dt = time_ns.saturating_sub(self.end_ns) as f32 / 1e9;
+        let last = self.trajectory.last().map(|(_, b)| *b);
+        iou(self.filter.predicted(dt), bbox).max(last.map(|l| iou(l, bbox)).unwrap_or(0.0))
+    }
+
+    fn observe(&mut self, time_ns: u64, sequence: u64, detection: &Detection) {
+        let dt = time_ns.saturating_sub(self.end_ns) as f32 / 1e9;
+        self.filter.predict(dt);
+        self.filter.update(detection.bbox);
+        self.confidence = self.confidence.max(detection.confidence);
+        self.end_ns = time_ns;
+        self.last_sequence = sequence;
+        self.trajectory.push((time_ns, detection.bbox));
+    }
+}
+
+#[derive(Default)]
+pub struct Tracker {
+    next_id: u64,
+    tracks: Vec<Track>,
+}
+
+impl Tracker {
+    /// Associate one frame's detections with tracks, starting new tracks for
+    /// unmatched confident ones. Frames must arrive in time order. Returns
+    /// the tracks that were updated or started.
+    pub fn update(&mut self, time_ns: u64, sequence: u64, detections: &[Detection]) -> Vec<&Track> {
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        let mut updated = Vec::new();
+
+        let (high, low): (Vec<&Detection>, Vec<&Detection>) = detections
+            .iter()
+            .partition(|d| d.confidence >= HIGH_CONFIDENCE);
+
+        for (pass, candidates) in [high, low].into_iter().enumerate() {
+            let mut pairs = Vec::new();
+            for (di, det) in candidates.iter().enumerate() {
+                for (ti, track) in self.tracks.iter().enumerate() {
+                    if matched_tracks[ti] || track.object_class != det.class_name {
+                        continue;
+                    }
+                    let overlap = track.overlap(time_ns, det.bbox);
+                    if overlap >= MIN_IOU {
+                        pairs.push((overlap, di, ti));
+                    }
+                }
+            }
+            pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            let mut matched_detections = vec![false; candidates.len()];
+            for (_, di, ti) in pairs {
+                if matched_detections[di] || matched_tracks[ti] {
+                    continue;
+                }
+                matched_detections[di] = true;
+                matched_tracks[ti] = true;
+                self.tracks[ti].observe(time_ns, sequence, candidates[di]);
+                updated.push(ti);
+            }
+
+            if pass == 0 {
+                for (det, _) in candidates
+                    .iter()
+                    .zip(&matched_detections)
+                    .filter(|(_, &m)| !m)
+                {
+                    self.next_id += 1;
+                    self.tracks.push(Track {
+                        id: self.next_id,
+                        object_class: det.class_name.clone(),
+                        confidence: det.confidence,
+                        start_ns: time_ns,
+                        end_ns: time_ns,
+                        last_sequence: sequence,
+                        trajectory: vec![(time_ns, det.bbox)],
+                        filter: BoxFilter::new(det.bbox),
+                    });
+                    matched_tracks.push(true);
+                    updated.push(self.tracks.len() - 1);
+                }
+            }
+        }
+
+        let ids: Vec<u64> = updated.into_iter().map(|i| self.tracks[i].id).collect();
+        self.tracks
+            .retain(|t| time_ns.saturating_sub(t.end_ns) <= TRACK_TIMEOUT_NS);
+        self.tracks.iter().filter(|t| ids.contains(&t.id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn person(x: f32, confidence: f32) -> Detection {
+        Detection {
+            class_name: "person".to_string(),
+            confidence,
+            bbox: BoundingBox {
+                x,
+                y: 0.4,
+                width: 0.1,
+                height: 0.3,
+            },
+        }
+    }
+
+    #[test]
+    fn test_tracks_follow_moving_object() {
+        let mut tracker = Tracker::default();
+        let second = 1_000_000_000;
+
+        // A person walking right, and a second one appearing later
+        for step in 0..6u64 {
+            let mut detections = vec![person(0.1 + 0.04 * step as f32, 0.8)];
+            if step >= 3 {
+                let confidence = if step == 3 { 0.6 } else { 0.3 };
+                detections.push(person(0.8, confidence));
+            }
+            let updated = tracker.update(step * second, step, &detections);
+            assert_eq!(updated.len(), detections.len());
+        }
+
+        assert_eq!(tracker.tracks.len(), 2);
+        let walker = &tracker.tracks[0];
+        assert_eq!(walker.trajectory.len(), 6);
+        assert_eq!(walker.start_ns, 0);
+        assert_eq!(walker.end_ns, 5 * second);
+        // The second person started confident and kept its id at low confidence
+        assert_eq!(tracker.tracks[1].trajectory.len(), 3);
+        assert_eq!(tracker.tracks[1].confidence, 0.6);
+
+        // Unseen tracks expire
+        tracker.update(20 * second, 20, &[]);
+        assert!(tracker.tracks.is_empty());
+    }
+}
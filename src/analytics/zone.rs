@@ -0,0 +1,160 @@
+use crate::config::{ZoneConfig, ZoneKind};
+
+/// Camera zones rasterised at analysis resolution, one byte per pixel with
+/// 255 inside and 0 outside
+pub struct ZoneMasks {
+    pub height: usize,
+    /// Where motion counts: the union of include zones (or the whole frame
+    /// without any) minus exclude zones
+    pub active: Vec<u8>,
+    /// Named include zones, already clipped by the exclude zones
+    pub named: Vec<(String, Vec<u8>)>,
+}
+
+impl ZoneMasks {
+    /// `None` when the camera has no include or exclude zones and the whole
+    /// frame counts. Area zones only exist for rules and loitering.
+    pub fn new(zones: &[ZoneConfig], width: usize, height: usize) -> Option<Self> {
+        if zones.iter().all(|z| z.kind == ZoneKind::Area) {
+            return None;
+        }
+
+        let includes: Vec<&ZoneConfig> = zones
+            .iter()
+            .filter(|z| z.kind == ZoneKind::Include)
+            .collect();
+        let mut active = vec![if includes.is_empty() { 255 } else { 0 }; width * height];
+        for zone in &includes {
+            union(&mut active, &rasterize(&zone.points, width, height));
+        }
+        for zone in zones.iter().filter(|z| z.kind == ZoneKind::Exclude) {
+            let mask = rasterize(&zone.points, width, height);
+            for (a, m) in active.iter_mut().zip(mask) {
+                if m != 0 {
+                    *a = 0;
+                }
+            }
+        }
+
+        let named = includes
+            .iter()
+            .filter(|z| !z.name.is_empty())
+            .map(|z| {
+                let mut mask = rasterize(&z.points, width, height);
+                for (m, a) in mask.iter_mut().zip(&active) {
+                    *m &= a;
+                }
+                (z.name.clone(), mask)
+            })
+            .collect();
+
+        Some(Self {
+            height,
+            active,
+            named,
+        })
+    }
+}
+
+fn union(into: &mut [u8], mask: &[u8]) {
+    for (a, m) in into.iter_mut().zip(mask) {
+        *a |= m;
+    }
+}
+
+/// Even-odd test of a normalized point against a normalized polygon
+pub fn contains(points: &[[f32; 2]], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    for (i, &[x0, y0]) in points.iter().enumerate() {
+        let [x1, y1] = points[(i + 1) % points.len()];
+        if (y0 <= y) != (y1 <= y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Even-odd fill of a normalized polygon, sampled at pixel centres
+pub fn rasterize(points: &[[f32; 2]], width: usize, height: usize) -> Vec<u8> {
+    let mut mask = vec![0u8; width * height];
+    if points.len() < 3 {
+        return mask;
+    }
+
+    let scaled: Vec<(f32, f32)> = points
+        .iter()
+        .map(|[x, y]| (x * width as f32, y * height as f32))
+        .collect();
+    let mut crossings = Vec::new();
+    for row in 0..height {
+        let y = row as f32 + 0.5;
+        crossings.clear();
+        for (i, &(x0, y0)) in scaled.iter().enumerate() {
+            let (x1, y1) = scaled[(i + 1) % scaled.len()];
+            if (y0 <= y) != (y1 <= y) {
+                crossings.push(x0 + (y - y0) / (y1 - y0) * (x1 - x0));
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            let start = (span[0] - 0.5).ceil().max(0.0) as usize;
+            let end = ((span[1] - 0.5).ceil().max(0.0) as usize).min(width);
+            for px in &mut mask[row * width + start.min(end)..row * width + end] {
+                *px = 255;
+            }
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(name: &str, kind: ZoneKind, points: &[[f32; 2]]) -> ZoneConfig {
+        ZoneConfig {
+            name: name.to_string(),
+            kind,
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_zone_masks() {
+        let square = [[0.0, 0.0], [0.5, 0.0], [0.5, 0.5], [0.0, 0.5]];
+        assert_eq!(
+            rasterize(&square, 10, 10)
+                .iter()
+                .filter(|&&p| p != 0)
+                .count(),
+            25
+        );
+
+        let zones = [
+            zone(
+                "yard",
+                ZoneKind::Include,
+                &[[0.0, 0.0], [1.0, 0.0], [1.0, 0.5], [0.0, 0.5]],
+            ),
+            zone("", ZoneKind::Exclude, &square),
+        ];
+        let masks = ZoneMasks::new(&zones, 10, 10).unwrap();
+        // Top half minus its top-left quarter
+        assert_eq!(masks.active.iter().filter(|&&p| p != 0).count(), 25);
+        assert_eq!(masks.active[0], 0);
+        assert_eq!(masks.active[9], 255);
+        assert_eq!(masks.active[99], 0);
+        assert_eq!(masks.named.len(), 1);
+        assert_eq!(masks.named[0].1, masks.active);
+
+        assert!(ZoneMasks::new(&[], 10, 10).is_none());
+
+        // Area zones leave the motion mask alone
+        let gate = zone("gate", ZoneKind::Area, &square);
+        assert!(ZoneMasks::new(std::slice::from_ref(&gate), 10, 10).is_none());
+        let with_area = [zones[0].clone(), zones[1].clone(), gate];
+        let area_masks = ZoneMasks::new(&with_area, 10, 10).unwrap();
+        assert_eq!(area_masks.active, masks.active);
+        assert_eq!(area_masks.named.len(), 1);
+    }
+}
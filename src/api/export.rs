@@ -0,0 +1,421 @@
+use std::path::PathBuf;
+
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_util::io::ReaderStream;
+
+use super::AppState;
+use crate::buffer::fmp4::{self, Gop, TsDemuxer, VideoParams};
+use crate::buffer::GopSegment;
+use crate::config::StorageFormat;
+
+const NANOS_PER_MS: u64 = 1_000_000;
+/// Gaps between sources shorter than this are not reported as missing
+const GAP_TOLERANCE_NS: u64 = 500 * NANOS_PER_MS;
+const READ_CHUNK_BYTES: usize = 1 << 20;
+/// Enough of a file's head to hold its first parameter sets
+const PROBE_BYTES: u64 = 2 << 20;
+const PIPE_BYTES: usize = 4 << 20;
+const MISSING_RANGES_HEADER: &str = "x-missing-ranges";
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    from: u64,
+    to: u64,
+    format: Option<String>,
+}
+
+enum Source {
+    Hot(GopSegment),
+    File {
+        path: PathBuf,
+        format: StorageFormat,
+    },
+}
+
+/// A stretch of footage from one tier. Lower `priority` wins where tiers overlap.
+struct Piece {
+    start_ns: u64,
+    end_ns: u64,
+    priority: u8,
+    source: Source,
+}
+
+/// `GET /api/cameras/{id}/export?from=&to=&format=mp4`
+///
+/// Stitches the GOPs covering `[from, to]` from the hot buffer, warm events,
+/// continuous recordings and the cold archive into one fragmented MP4,
+/// streamed without re-encoding.
+pub async fn export_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    if !matches!(query.format.as_deref(), None | Some("mp4")) {
+        return (StatusCode::BAD_REQUEST, "unsupported format").into_response();
+    }
+    if query.to <= query.from {
+        return (StatusCode::BAD_REQUEST, "to must be after from").into_response();
+    }
+    let pieces = match plan(&state, &id, query.from, query.to) {
+        Some(pieces) => pieces,
+        None => return (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    };
+    let mut probed = Vec::with_capacity(pieces.len());
+    for piece in pieces {
+        let params = probe_params(&piece.source).await;
+        probed.push((piece, params));
+    }
+    let pieces = drop_mismatched(probed);
+
+    let missing = missing_ranges(&pieces, query.from, query.to);
+    let missing_header = missing
+        .iter()
+        .map(|(start, end)| format!("{start}-{end}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if pieces.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            [(MISSING_RANGES_HEADER, missing_header)],
+            "no footage in range",
+        )
+            .into_response();
+    }
+
+    let (reader, writer) = tokio::io::duplex(PIPE_BYTES);
+    let camera_id = id.clone();
+    tokio::spawn(async move {
+        let mut export = ExportWriter::new(writer, query.from, query.to);
+        if let Err(e) = export.write_pieces(pieces).await {
+            tracing::debug!(camera = %camera_id, error = %e, "clip export stopped");
+        }
+    });
+
+    let mut response = Body::from_stream(ReaderStream::new(reader)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("video/mp4"));
+    let disposition = format!(
+        "attachment; filename=\"{id}_{}_{}.mp4\"",
+        query.from, query.to
+    );
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    if !missing.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&missing_header) {
+            headers.insert(MISSING_RANGES_HEADER, value);
+        }
+    }
+    response
+}
+
+/// Every source overlapping the range, ordered by start time and tier
+fn plan(state: &AppState, camera_id: &str, from: u64, to: u64) -> Option<Vec<Piece>> {
+    let buffer = state.buffers.get(camera_id)?;
+    let mut pieces = Vec::new();
+
+    if let Ok(buf) = buffer.read() {
+        for segment in buf.segments() {
+            let end = segment.start_pts + segment.duration_ns;
+            if segment.start_pts < to && end > from {
+                pieces.push(Piece {
+                    start_ns: segment.start_pts,
+                    end_ns: end,
+                    priority: 0,
+                    source: Source::Hot(segment.clone()),
+                });
+            }
+        }
+    }
+
+    let event_tiers = [
+        (1, state.warm_index.as_ref()),
+        (3, state.cold_index.as_ref()),
+    ];
+    for (priority, index) in event_tiers {
+        let Some(index) = index else {
+            continue;
+        };
+        for entry in index.query(camera_id, from, to) {
+            pieces.push(Piece {
+                start_ns: entry.start_pts_ns,
+                end_ns: entry.start_pts_ns + entry.duration_ms as u64 * NANOS_PER_MS,
+                priority,
+                source: Source::File {
+                    path: index.resolve_file_path(camera_id, &entry),
+                    format: entry.format,
+                },
+            });
+        }
+    }
+
+    if let Some(index) = &state.recording_index {
+        for entry in index.query(camera_id, from, to) {
+            pieces.push(Piece {
+                start_ns: entry.start_pts_ns,
+                end_ns: entry.end_ns(),
+                priority: 2,
+                source: Source::File {
+                    path: index.resolve_file_path(camera_id, &entry),
+                    format: StorageFormat::Ts,
+                },
+            });
+        }
+    }
+
+    pieces.sort_by_key(|p| (p.start_ns, p.priority));
+    Some(pieces)
+}
+
+/// Parameter sets a piece starts with
+async fn probe_params(source: &Source) -> Option<VideoParams> {
+    let (path, format) = match source {
+        Source::Hot(segment) => return fmp4::segment_params(segment),
+        Source::File { path, format } => (path, *format),
+    };
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut head = Vec::new();
+    file.take(PROBE_BYTES).read_to_end(&mut head).await.ok()?;
+    match format {
+        StorageFormat::Fmp4 => fmp4::demux_mp4(&head).params,
+        StorageFormat::Ts => {
+            let mut demuxer = TsDemuxer::default();
+            demuxer.feed(&head);
+            demuxer.params().cloned()
+        }
+    }
+}
+
+/// Drop pieces whose parameter sets differ from the first piece's, such as
+/// a resolution change into the cold archive: one MP4 has one init segment.
+/// The footage they held is then reported as missing. A change inside a
+/// single file can't be seen up front, and those GOPs are skipped silently.
+fn drop_mismatched(pieces: Vec<(Piece, Option<VideoParams>)>) -> Vec<Piece> {
+    let mut first: Option<VideoParams> = None;
+    pieces
+        .into_iter()
+        .filter_map(|(piece, params)| match (&first, params) {
+            (None, Some(params)) => {
+                first = Some(params);
+                Some(piece)
+            }
+            (Some(first), Some(params)) if *first != params => None,
+            _ => Some(piece),
+        })
+        .collect()
+}
+
+/// Parts of `[from, to]` not covered by any piece
+fn missing_ranges(pieces: &[Piece], from: u64, to: u64) -> Vec<(u64, u64)> {
+    let mut missing = Vec::new();
+    let mut cursor = from;
+    for piece in pieces {
+        if cursor >= to {
+            break;
+        }
+        if piece.start_ns > cursor + GAP_TOLERANCE_NS {
+            missing.push((cursor, piece.start_ns.min(to)));
+        }
+        cursor = cursor.max(piece.end_ns);
+    }
+    if cursor + GAP_TOLERANCE_NS < to {
+        missing.push((cursor, to));
+    }
+    missing
+}
+
+struct ExportWriter {
+    out: DuplexStream,
+    from: u64,
+    to: u64,
+    params: Option<VideoParams>,
+    origin_ns: Option<u64>,
+    emitted_until: u64,
+    sequence: u32,
+}
+
+impl ExportWriter {
+    fn new(out: DuplexStream, from: u64, to: u64) -> Self {
+        Self {
+            out,
+            from,
+            to,
+            params: None,
+            origin_ns: None,
+            emitted_until: 0,
+            sequence: 0,
+        }
+    }
+
+    async fn write_pieces(&mut self, pieces: Vec<Piece>) -> Result<(), std::io::Error> {
+        for piece in pieces {
+            if piece.start_ns >= self.to {
+                break;
+            }
+            if piece.end_ns <= self.emitted_until + GAP_TOLERANCE_NS {
+                continue;
+            }
+            match piece.source {
+                Source::Hot(segment) => {
+                    let demuxed = fmp4::demux(&segment.data);
+                    let params = demuxed.params.clone();
+                    let gops = demuxed.into_gops(segment.start_pts);
+                    self.write_gops(params.as_ref(), gops).await?;
+                }
+                Source::File {
+                    path,
+                    format: StorageFormat::Fmp4,
+                } => {
+                    let data = tokio::fs::read(&path).await?;
+                    let demuxed = fmp4::demux_mp4(&data);
+                    let params = demuxed.params.clone();
+                    let gops = demuxed.into_gops(piece.start_ns);
+                    self.write_gops(params.as_ref(), gops).await?;
+                }
+                Source::File {
+                    path,
+                    format: StorageFormat::Ts,
+                } => {
+                    self.write_ts_file(&path, piece.start_ns).await?;
+                }
+            }
+        }
+        self.out.shutdown().await
+    }
+
+    /// Stream a possibly hour-long MPEG-TS file through the demuxer, stopping
+    /// once past the end of the range
+    async fn write_ts_file(
+        &mut self,
+        path: &std::path::Path,
+        start_ns: u64,
+    ) -> Result<(), std::io::Error> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut demuxer = TsDemuxer::default();
+        let mut buf = vec![0u8; READ_CHUNK_BYTES];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            demuxer.feed(&buf[..n]);
+            let params = demuxer.params().cloned();
+            let gops = demuxer.take_gops(start_ns, false);
+            let past_end = gops.last().is_some_and(|g| g.end_ns >= self.to);
+            self.write_gops(params.as_ref(), gops).await?;
+            if past_end {
+                return Ok(());
+            }
+        }
+        demuxer.finish();
+        let params = demuxer.params().cloned();
+        let gops = demuxer.take_gops(start_ns, true);
+        self.write_gops(params.as_ref(), gops).await
+    }
+
+    async fn write_gops(
+        &mut self,
+        params: Option<&VideoParams>,
+        gops: Vec<Gop>,
+    ) -> Result<(), std::io::Error> {
+        for gop in gops {
+            if gop.end_ns <= self.from || gop.start_ns >= self.to {
+                continue;
+            }
+            // Already covered by an earlier GOP from another tier
+            if gop.start_ns + GAP_TOLERANCE_NS < self.emitted_until {
+                continue;
+            }
+
+            match (&self.params, params) {
+                (None, Some(params)) => {
+                    self.out.write_all(&fmp4::init_segment(params)).await?;
+                    self.params = Some(params.clone());
+                }
+                (Some(current), Some(params)) if current == params => {}
+                // Can't start without parameter sets, and a resolution change
+                // (e.g. into the cold archive) can't share the init segment
+                _ => continue,
+            }
+
+            let origin = *self.origin_ns.get_or_insert(gop.start_ns);
+            self.sequence += 1;
+            let fragment = fmp4::fragment(
+                self.sequence,
+                fmp4::ns_to_ticks(gop.start_ns.saturating_sub(origin)),
+                &gop.samples,
+                gop.end_dts,
+            );
+            self.out.write_all(&fragment).await?;
+            self.emitted_until = gop.end_ns;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::nal::VideoCodec;
+
+    fn piece(start_ns: u64, end_ns: u64) -> Piece {
+        Piece {
+            start_ns,
+            end_ns,
+            priority: 1,
+            source: Source::File {
+                path: PathBuf::new(),
+                format: StorageFormat::Ts,
+            },
+        }
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        let s = 1_000_000_000;
+        let pieces = [
+            piece(0, 10 * s),
+            piece(5 * s, 12 * s),
+            piece(20 * s, 30 * s),
+        ];
+        assert_eq!(
+            missing_ranges(&pieces, 2 * s, 40 * s),
+            [(12 * s, 20 * s), (30 * s, 40 * s)]
+        );
+        assert_eq!(missing_ranges(&[], 0, 5 * s), [(0, 5 * s)]);
+        assert!(missing_ranges(&pieces, 0, 12 * s).is_empty());
+    }
+
+    #[test]
+    fn test_mixed_params_reported_missing() {
+        // 1920x1080 High profile SPS, with two different PPS
+        let sps = [
+            0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0x84, 0x00, 0x00,
+            0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x60, 0xC6, 0x58,
+        ];
+        let params = |pps: &[u8]| VideoParams::new(VideoCodec::H264, &[], &sps, pps);
+        let main = params(&[0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0]);
+        let other = params(&[0x68, 0xEE, 0x3C, 0x80]);
+        assert!(main.is_some() && main != other);
+
+        let s = 1_000_000_000;
+        let pieces = drop_mismatched(vec![
+            (piece(0, 10 * s), None),
+            (piece(0, 10 * s), main.clone()),
+            (piece(10 * s, 20 * s), other.clone()),
+            (piece(20 * s, 30 * s), main.clone()),
+            (piece(25 * s, 40 * s), other),
+        ]);
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(
+            missing_ranges(&pieces, 0, 40 * s),
+            [(10 * s, 20 * s), (30 * s, 40 * s)]
+        );
+    }
+}
@@ -1,6 +1,33 @@
-use crate::buffer::HotBuffer;
+use crate::buffer::{fmp4, HotBuffer};
+use crate::storage::ChunkGop;
 
 const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+/// Advertised when the buffer is too short to measure the bitrate
+const DEFAULT_BANDWIDTH: u64 = 4_000_000;
+
+/// Master playlist with a single variant pointing at `playlist.m3u8` next to
+/// it. `CODECS` and `RESOLUTION` come from the newest parameter sets, so
+/// players can tell H.264 from HEVC before fetching media.
+pub fn generate_master_playlist(buffer: &HotBuffer, version: u8) -> String {
+    let segments = buffer.segments();
+    let bytes: usize = segments.iter().map(|s| s.data.len()).sum();
+    let secs = buffer.total_duration_ns() as f64 / NANOS_PER_SEC;
+    let bandwidth = if secs > 0.0 {
+        (bytes as f64 * 8.0 / secs) as u64
+    } else {
+        DEFAULT_BANDWIDTH
+    };
+
+    let mut attributes = format!("BANDWIDTH={bandwidth}");
+    if let Some(params) = segments.iter().rev().find_map(fmp4::segment_params) {
+        attributes.push_str(&format!(",RESOLUTION={}x{}", params.width, params.height));
+        if let Some(codec) = params.codec_string() {
+            attributes.push_str(&format!(",CODECS=\"{codec}\""));
+        }
+    }
+
+    format!("#EXTM3U\n#EXT-X-VERSION:{version}\n#EXT-X-STREAM-INF:{attributes}\nplaylist.m3u8\n")
+}
 
 pub fn generate_playlist(buffer: &HotBuffer) -> String {
     let segments = buffer.segments();
@@ -42,3 +69,72 @@ pub fn generate_segment(buffer: &HotBuffer, sequence: u64) -> Option<Vec<u8>> {
     // Return raw MPEG-TS data directly - already properly formatted with PAT/PMT
     Some(segment.data.clone())
 }
+
+/// VOD playlist of a continuous recording chunk with one segment per GOP,
+/// served from `segment/{n}`
+pub fn generate_recording_playlist(gops: &[ChunkGop]) -> String {
+    let max_duration = gops
+        .iter()
+        .map(|g| (g.duration_ns as f64 / NANOS_PER_SEC).ceil() as u64)
+        .max()
+        .unwrap_or(2);
+
+    let mut playlist = String::new();
+    playlist.push_str("#EXTM3U\n");
+    playlist.push_str("#EXT-X-VERSION:3\n");
+    playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", max_duration));
+    playlist.push_str("#EXT-X-MEDIA-SEQUENCE:0\n");
+    playlist.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n");
+
+    for (i, gop) in gops.iter().enumerate() {
+        let duration = gop.duration_ns as f64 / NANOS_PER_SEC;
+        playlist.push_str(&format!("#EXTINF:{:.3},\n", duration));
+        playlist.push_str(&format!("segment/{}\n", i));
+    }
+
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+/// HLS v7 playlist of CMAF fragments sharing one `init.mp4`. Fragments are
+/// placed on the wall-clock timeline, so no discontinuities are needed.
+pub fn generate_fmp4_playlist(buffer: &HotBuffer) -> String {
+    let segments = buffer.segments();
+    let first_sequence = buffer.first_sequence();
+
+    let max_duration = segments
+        .iter()
+        .map(|s| (s.duration_ns as f64 / NANOS_PER_SEC).ceil() as u64)
+        .max()
+        .unwrap_or(2);
+
+    let mut playlist = String::new();
+    playlist.push_str("#EXTM3U\n");
+    playlist.push_str("#EXT-X-VERSION:7\n");
+    playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", max_duration));
+    playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", first_sequence));
+    playlist.push_str("#EXT-X-INDEPENDENT-SEGMENTS\n");
+    if segments.is_empty() {
+        return playlist;
+    }
+    playlist.push_str("#EXT-X-MAP:URI=\"init.mp4\"\n");
+
+    for (i, segment) in segments.iter().enumerate() {
+        let sequence = first_sequence + i as u64;
+        let duration = segment.duration_ns as f64 / NANOS_PER_SEC;
+        playlist.push_str(&format!("#EXTINF:{:.3},\n", duration));
+        playlist.push_str(&format!("segment/{}\n", sequence));
+    }
+
+    playlist
+}
+
+/// Init segment from the newest GOP carrying parameter sets
+pub fn generate_init(buffer: &HotBuffer) -> Option<Vec<u8>> {
+    buffer.segments().iter().rev().find_map(fmp4::segment_init)
+}
+
+pub fn generate_fmp4_segment(buffer: &HotBuffer, sequence: u64) -> Option<Vec<u8>> {
+    let segment = buffer.get_segment_by_sequence(sequence)?;
+    fmp4::segment_fragment(segment, (sequence as u32).wrapping_add(1))
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+use axum::body::Body;
 use axum::extract::{Path, Query, State};
 use axum::http::{header, StatusCode};
 use axum::response::{Html, IntoResponse, Response};
@@ -8,11 +9,18 @@ use axum::routing::get;
 use axum::Router;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
 
-use crate::buffer::HotBuffer;
-use crate::storage::{DetectionStore, MotionStore, WarmEventIndex};
+use crate::buffer::{fmp4, HotBuffer};
+use crate::camera::{CameraStatus, HealthStore};
+use crate::config::StorageFormat;
+use crate::storage::{
+    AlertKind, AlertStore, BoundingBox, DetectionStore, EventMetadataStore, MotionStore,
+    RecordingIndex, WarmEventEntry, WarmEventIndex,
+};
 
-use super::hls;
+use super::{export, hls};
 
 #[derive(Embed)]
 #[folder = "src/assets/"]
@@ -23,23 +31,55 @@ pub struct AppState {
     pub buffers: Arc<HashMap<String, Arc<RwLock<HotBuffer>>>>,
     pub motion_store: MotionStore,
     pub detection_store: DetectionStore,
+    pub alert_store: AlertStore,
     pub warm_index: Option<WarmEventIndex>,
+    pub cold_index: Option<WarmEventIndex>,
+    pub recording_index: Option<RecordingIndex>,
+    pub metadata_store: EventMetadataStore,
+    pub health_store: HealthStore,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         buffers: HashMap<String, Arc<RwLock<HotBuffer>>>,
         motion_store: MotionStore,
         detection_store: DetectionStore,
+        alert_store: AlertStore,
         warm_index: Option<WarmEventIndex>,
+        cold_index: Option<WarmEventIndex>,
+        recording_index: Option<RecordingIndex>,
+        metadata_store: EventMetadataStore,
+        health_store: HealthStore,
     ) -> Self {
         Self {
             buffers: Arc::new(buffers),
             motion_store,
             detection_store,
+            alert_store,
             warm_index,
+            cold_index,
+            recording_index,
+            metadata_store,
+            health_store,
         }
     }
+
+    /// Look up an event in the warm tier first, then the cold archive
+    fn find_event(
+        &self,
+        camera_id: &str,
+        start_pts: u64,
+    ) -> Option<(&WarmEventIndex, WarmEventEntry)> {
+        [self.warm_index.as_ref(), self.cold_index.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|index| {
+                index
+                    .find_event(camera_id, start_pts)
+                    .map(|entry| (index, entry))
+            })
+    }
 }
 
 #[derive(Serialize)]
@@ -48,6 +88,13 @@ struct MotionSegmentResponse {
     start: f64,
     end: f64,
     intensity: f32,
+    zones: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct MotionQuery {
+    /// Only segments with motion in this named zone
+    zone: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -62,6 +109,7 @@ struct DetectionItem {
     timestamp: f64,
     object_class: String,
     confidence: f32,
+    bbox: Option<BoundingBox>,
 }
 
 #[derive(Serialize)]
@@ -75,12 +123,22 @@ pub async fn start_server(state: AppState, port: u16) -> Result<(), std::io::Err
         .route("/", get(index_handler))
         .route("/assets/{*path}", get(static_handler))
         .route("/api/cameras", get(cameras_handler))
+        .route("/api/cameras/{id}/status", get(camera_status_handler))
         .route("/api/cameras/{id}/motion", get(motion_handler))
         .route(
             "/api/cameras/{id}/motion/{seq}/mask",
             get(motion_mask_handler),
         )
         .route("/api/cameras/{id}/detections", get(detections_handler))
+        .route("/api/cameras/{id}/alerts", get(alerts_handler))
+        .route(
+            "/api/cameras/{id}/alerts/{alert_id}/frame",
+            get(alert_frame_handler),
+        )
+        .route(
+            "/api/cameras/{id}/alerts/{alert_id}/before",
+            get(alert_before_handler),
+        )
         .route(
             "/api/cameras/{id}/detections/{detection_id}/frame",
             get(detection_frame_handler),
@@ -94,8 +152,52 @@ pub async fn start_server(state: AppState, port: u16) -> Result<(), std::io::Err
             "/api/cameras/{id}/events/{start_pts}/segment",
             get(warm_segment_handler),
         )
+        .route(
+            "/api/cameras/{id}/events/{start_pts}/init.mp4",
+            get(warm_init_handler),
+        )
+        .route(
+            "/api/cameras/{id}/events/{start_pts}/download",
+            get(warm_download_handler),
+        )
+        .route(
+            "/api/cameras/{id}/events/{start_pts}/thumbnail",
+            get(warm_thumbnail_handler),
+        )
+        .route(
+            "/api/cameras/{id}/events/{start_pts}/alerts/{index}/{which}",
+            get(alert_snapshot_handler),
+        )
+        .route("/api/cameras/{id}/recordings", get(recordings_handler))
+        .route(
+            "/api/cameras/{id}/recordings/{start_pts}/playlist.m3u8",
+            get(recording_playlist_handler),
+        )
+        .route(
+            "/api/cameras/{id}/recordings/{start_pts}/segment",
+            get(recording_segment_handler),
+        )
+        .route(
+            "/api/cameras/{id}/recordings/{start_pts}/segment/{n}",
+            get(recording_gop_handler),
+        )
+        .route("/api/cameras/{id}/export", get(export::export_handler))
+        .route("/api/stream/{id}/master.m3u8", get(master_playlist_handler))
         .route("/api/stream/{id}/playlist.m3u8", get(playlist_handler))
         .route("/api/stream/{id}/segment/{n}", get(segment_handler))
+        .route(
+            "/api/stream/{id}/fmp4/master.m3u8",
+            get(fmp4_master_playlist_handler),
+        )
+        .route(
+            "/api/stream/{id}/fmp4/playlist.m3u8",
+            get(fmp4_playlist_handler),
+        )
+        .route("/api/stream/{id}/fmp4/init.mp4", get(fmp4_init_handler))
+        .route(
+            "/api/stream/{id}/fmp4/segment/{n}",
+            get(fmp4_segment_handler),
+        )
         .with_state(state);
 
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
@@ -126,11 +228,64 @@ async fn static_handler(Path(path): Path<String>) -> impl IntoResponse {
     }
 }
 
+#[derive(Serialize)]
+struct CameraItem {
+    id: String,
+    #[serde(flatten)]
+    status: Option<CameraStatus>,
+}
+
 async fn cameras_handler(State(state): State<AppState>) -> impl IntoResponse {
-    let cameras: Vec<String> = state.buffers.keys().cloned().collect();
+    let mut cameras: Vec<CameraItem> = state
+        .buffers
+        .keys()
+        .map(|id| CameraItem {
+            id: id.clone(),
+            status: state.health_store.status(id),
+        })
+        .collect();
+    cameras.sort_by(|a, b| a.id.cmp(&b.id));
     axum::Json(cameras)
 }
 
+async fn camera_status_handler(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.health_store.status(&id) {
+        Some(status) => axum::Json(status).into_response(),
+        None => (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    }
+}
+
+async fn master_playlist_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    master_playlist(&state, &id, 3)
+}
+
+async fn fmp4_master_playlist_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    master_playlist(&state, &id, 7)
+}
+
+fn master_playlist(state: &AppState, id: &str, version: u8) -> Response {
+    match state.buffers.get(id) {
+        Some(buffer) => match buffer.read() {
+            Ok(buf) => {
+                let playlist = hls::generate_master_playlist(&buf, version);
+                (
+                    [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+                    playlist,
+                )
+                    .into_response()
+            }
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "buffer lock error").into_response(),
+        },
+        None => (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    }
+}
+
 async fn playlist_handler(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -167,7 +322,60 @@ async fn segment_handler(
     }
 }
 
-async fn motion_handler(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+async fn fmp4_playlist_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.buffers.get(&id) {
+        Some(buffer) => match buffer.read() {
+            Ok(buf) => {
+                let playlist = hls::generate_fmp4_playlist(&buf);
+                (
+                    [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+                    playlist,
+                )
+                    .into_response()
+            }
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "buffer lock error").into_response(),
+        },
+        None => (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    }
+}
+
+async fn fmp4_init_handler(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.buffers.get(&id) {
+        Some(buffer) => match buffer.read() {
+            Ok(buf) => match hls::generate_init(&buf) {
+                Some(data) => ([(header::CONTENT_TYPE, "video/mp4")], data).into_response(),
+                None => (StatusCode::NOT_FOUND, "no parameter sets yet").into_response(),
+            },
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "buffer lock error").into_response(),
+        },
+        None => (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    }
+}
+
+async fn fmp4_segment_handler(
+    State(state): State<AppState>,
+    Path((id, n)): Path<(String, u64)>,
+) -> Response {
+    match state.buffers.get(&id) {
+        Some(buffer) => match buffer.read() {
+            Ok(buf) => match hls::generate_fmp4_segment(&buf, n) {
+                Some(data) => ([(header::CONTENT_TYPE, "video/mp4")], data).into_response(),
+                None => (StatusCode::NOT_FOUND, "segment not found").into_response(),
+            },
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "buffer lock error").into_response(),
+        },
+        None => (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    }
+}
+
+async fn motion_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<MotionQuery>,
+) -> Response {
     let buffer = match state.buffers.get(&id) {
         Some(b) => b,
         None => return (StatusCode::NOT_FOUND, "camera not found").into_response(),
@@ -188,6 +396,7 @@ async fn motion_handler(State(state): State<AppState>, Path(id): Path<String>) -
         segments: segments
             .iter()
             .filter(|s| s.segment_sequence >= first_sequence)
+            .filter(|s| query.zone.as_ref().is_none_or(|z| s.zones.contains(z)))
             .filter_map(|s| {
                 let start_ns = buf.sequence_to_offset_ns(s.segment_sequence)?;
                 let start = start_ns as f64 / 1_000_000_000.0;
@@ -197,6 +406,7 @@ async fn motion_handler(State(state): State<AppState>, Path(id): Path<String>) -
                     start,
                     end,
                     intensity: s.motion_score,
+                    zones: s.zones.clone(),
                 })
             })
             .collect(),
@@ -233,6 +443,81 @@ async fn detections_handler(State(state): State<AppState>, Path(id): Path<String
                     timestamp: offset_ns as f64 / 1_000_000_000.0,
                     object_class: d.object_class.clone(),
                     confidence: d.confidence,
+                    bbox: d.bbox,
+                })
+            })
+            .collect(),
+    };
+
+    axum::Json(response).into_response()
+}
+
+#[derive(Serialize)]
+struct AlertItem {
+    id: u64,
+    timestamp: f64,
+    /// Seconds a lasting alert such as loitering went on for, once over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    kind: AlertKind,
+    rule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object_class: Option<String>,
+    has_frame: bool,
+    /// A view from before a tamper alert is available
+    has_before_frame: bool,
+}
+
+#[derive(Serialize)]
+struct AlertResponse {
+    total_duration: f64,
+    alerts: Vec<AlertItem>,
+}
+
+async fn alerts_handler(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let buffer = match state.buffers.get(&id) {
+        Some(b) => b,
+        None => return (StatusCode::NOT_FOUND, "camera not found").into_response(),
+    };
+
+    let buf = match buffer.read() {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "buffer lock error").into_response(),
+    };
+
+    let first_sequence = buf.first_sequence();
+    let total_duration = buf.total_duration_ns() as f64 / 1_000_000_000.0;
+
+    let mut alerts = state.alert_store.get_alerts(&id);
+    alerts.sort_by_key(|a| a.time_ns);
+
+    let response = AlertResponse {
+        total_duration,
+        alerts: alerts
+            .into_iter()
+            .filter(|a| a.segment_sequence >= first_sequence)
+            .filter_map(|a| {
+                // Alerts are stored against a segment but can start before
+                // it, like loitering
+                let segment_pts = buf.get_segment_by_sequence(a.segment_sequence)?.start_pts;
+                let offset_ns = buf
+                    .sequence_to_offset_ns(a.segment_sequence)?
+                    .saturating_add(a.time_ns)
+                    .saturating_sub(segment_pts);
+                Some(AlertItem {
+                    id: a.id,
+                    timestamp: offset_ns as f64 / 1_000_000_000.0,
+                    duration: a
+                        .end_ns
+                        .map(|end| end.saturating_sub(a.time_ns) as f64 / 1_000_000_000.0),
+                    kind: a.kind,
+                    rule: a.rule,
+                    track_id: a.track_id,
+                    object_class: a.object_class,
+                    has_frame: a.frame_jpeg.is_some(),
+                    has_before_frame: a.before_jpeg.is_some(),
                 })
             })
             .collect(),
@@ -241,6 +526,42 @@ async fn detections_handler(State(state): State<AppState>, Path(id): Path<String
     axum::Json(response).into_response()
 }
 
+async fn alert_frame_handler(
+    State(state): State<AppState>,
+    Path((id, alert_id)): Path<(String, u64)>,
+) -> Response {
+    if !state.buffers.contains_key(&id) {
+        return (StatusCode::NOT_FOUND, "camera not found").into_response();
+    }
+
+    match state
+        .alert_store
+        .get(&id, alert_id)
+        .and_then(|a| a.frame_jpeg)
+    {
+        Some(frame) => ([(header::CONTENT_TYPE, "image/jpeg")], frame).into_response(),
+        None => (StatusCode::NOT_FOUND, "alert frame not found").into_response(),
+    }
+}
+
+async fn alert_before_handler(
+    State(state): State<AppState>,
+    Path((id, alert_id)): Path<(String, u64)>,
+) -> Response {
+    if !state.buffers.contains_key(&id) {
+        return (StatusCode::NOT_FOUND, "camera not found").into_response();
+    }
+
+    match state
+        .alert_store
+        .get(&id, alert_id)
+        .and_then(|a| a.before_jpeg)
+    {
+        Some(frame) => ([(header::CONTENT_TYPE, "image/jpeg")], frame).into_response(),
+        None => (StatusCode::NOT_FOUND, "alert frame not found").into_response(),
+    }
+}
+
 async fn motion_mask_handler(
     State(state): State<AppState>,
     Path((id, seq)): Path<(String, u64)>,
@@ -275,6 +596,57 @@ async fn detection_frame_handler(
 struct EventsQuery {
     from: Option<u64>,
     to: Option<u64>,
+    /// Only events with motion in this named zone
+    zone: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EventMotionItem {
+    offset: f64,
+    duration: f64,
+    intensity: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    zones: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct EventDetectionItem {
+    offset: f64,
+    object_class: String,
+    confidence: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bbox: Option<BoundingBox>,
+}
+
+#[derive(Serialize)]
+struct EventTrackPoint {
+    offset: f64,
+    bbox: BoundingBox,
+}
+
+#[derive(Serialize)]
+struct EventTrackItem {
+    id: u64,
+    object_class: String,
+    confidence: f32,
+    start: f64,
+    end: f64,
+    trajectory: Vec<EventTrackPoint>,
+}
+
+#[derive(Serialize)]
+struct EventAlertItem {
+    offset: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<f64>,
+    kind: AlertKind,
+    rule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    track_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object_class: Option<String>,
+    /// Before and after snapshots can be fetched by the alert's index
+    has_snapshots: bool,
 }
 
 #[derive(Serialize)]
@@ -282,6 +654,15 @@ struct WarmEventResponse {
     start_pts_ns: String,
     duration_ms: u32,
     event_type: String,
+    tier: &'static str,
+    max_intensity: f32,
+    classes: Vec<String>,
+    zones: Vec<String>,
+    motion: Vec<EventMotionItem>,
+    detections: Vec<EventDetectionItem>,
+    tracks: Vec<EventTrackItem>,
+    alerts: Vec<EventAlertItem>,
+    has_thumbnail: bool,
 }
 
 async fn warm_events_handler(
@@ -300,17 +681,96 @@ async fn warm_events_handler(
 
     let from = query.from.unwrap_or(0);
     let to = query.to.unwrap_or(u64::MAX);
-    let events = index.query(&id, from, to);
+    let mut events: Vec<(&'static str, WarmEventEntry)> = index
+        .query(&id, from, to)
+        .into_iter()
+        .map(|e| ("warm", e))
+        .collect();
+    if let Some(cold) = &state.cold_index {
+        events.extend(cold.query(&id, from, to).into_iter().map(|e| ("cold", e)));
+    }
+    events.sort_by_key(|(_, e)| e.start_pts_ns);
 
     let response: Vec<WarmEventResponse> = events
         .iter()
-        .map(|e| WarmEventResponse {
-            start_pts_ns: e.start_pts_ns.to_string(),
-            duration_ms: e.duration_ms,
-            event_type: match e.event_type {
-                crate::storage::EventType::Movement => "movement".to_string(),
-                crate::storage::EventType::Object => "object".to_string(),
-            },
+        .filter_map(|(tier, e)| {
+            let metadata = state.metadata_store.get(&id, e.start_pts_ns);
+            let (motion, detections, tracks, alerts, has_thumbnail) = match (metadata, &e.sidecar) {
+                (Some(m), _) => (m.motion, m.detections, m.tracks, m.alerts, m.has_thumbnail),
+                (None, Some(sidecar)) => (
+                    sidecar.motion(),
+                    sidecar.detections.clone(),
+                    sidecar.tracks.clone(),
+                    sidecar.alerts.clone(),
+                    false,
+                ),
+                (None, None) => (Vec::new(), Vec::new(), Vec::new(), Vec::new(), false),
+            };
+            let mut zones: Vec<String> = motion.iter().flat_map(|m| m.zones.clone()).collect();
+            zones.sort();
+            zones.dedup();
+            if query.zone.as_ref().is_some_and(|z| !zones.contains(z)) {
+                return None;
+            }
+            let classes = e.sidecar.as_ref().map(|s| s.classes()).unwrap_or_default();
+            Some(WarmEventResponse {
+                start_pts_ns: e.start_pts_ns.to_string(),
+                duration_ms: e.duration_ms,
+                event_type: e.event_type.as_str().to_string(),
+                tier,
+                classes,
+                zones,
+                max_intensity: motion.iter().map(|m| m.score).fold(0.0, f32::max),
+                motion: motion
+                    .iter()
+                    .map(|m| EventMotionItem {
+                        offset: m.offset_ms as f64 / 1000.0,
+                        duration: m.duration_ms as f64 / 1000.0,
+                        intensity: m.score,
+                        zones: m.zones.clone(),
+                    })
+                    .collect(),
+                detections: detections
+                    .into_iter()
+                    .map(|d| EventDetectionItem {
+                        offset: d.offset_ms as f64 / 1000.0,
+                        object_class: d.object_class,
+                        confidence: d.confidence,
+                        bbox: d.bbox,
+                    })
+                    .collect(),
+                tracks: tracks
+                    .into_iter()
+                    .map(|t| EventTrackItem {
+                        id: t.id,
+                        object_class: t.object_class,
+                        confidence: t.confidence,
+                        start: t.start_offset_ms as f64 / 1000.0,
+                        end: t.end_offset_ms as f64 / 1000.0,
+                        trajectory: t
+                            .trajectory
+                            .into_iter()
+                            .map(|p| EventTrackPoint {
+                                offset: p.offset_ms as f64 / 1000.0,
+                                bbox: p.bbox,
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+                alerts: alerts
+                    .into_iter()
+                    .map(|a| EventAlertItem {
+                        offset: a.offset_ms as f64 / 1000.0,
+                        end: a.end_offset_ms.map(|ms| ms as f64 / 1000.0),
+                        kind: a.kind,
+                        rule: a.rule,
+                        track_id: a.track_id,
+                        object_class: a.object_class,
+                        has_snapshots: a.has_snapshots,
+                    })
+                    .collect(),
+                has_thumbnail,
+            })
         })
         .collect();
 
@@ -321,30 +781,40 @@ async fn warm_playlist_handler(
     State(state): State<AppState>,
     Path((id, start_pts_str)): Path<(String, String)>,
 ) -> Response {
-    let index = match &state.warm_index {
-        Some(idx) => idx,
-        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
-    };
+    if state.warm_index.is_none() {
+        return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response();
+    }
 
     let start_pts: u64 = match start_pts_str.parse() {
         Ok(v) => v,
         Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
     };
 
-    let entry = match index.find_event(&id, start_pts) {
-        Some(e) => e,
+    let (_, entry) = match state.find_event(&id, start_pts) {
+        Some(found) => found,
         None => return (StatusCode::NOT_FOUND, "event not found").into_response(),
     };
 
-    let duration_secs = entry.duration_ms as f64 / 1000.0;
+    single_segment_playlist(entry.duration_ms, entry.format)
+}
+
+/// VOD playlist serving a whole stored file as one `segment`. Fragmented MP4
+/// files use HLS v7 with the file's `ftyp`/`moov` served as `init.mp4`.
+fn single_segment_playlist(duration_ms: u32, format: StorageFormat) -> Response {
+    let duration_secs = duration_ms as f64 / 1000.0;
     let target_duration = duration_secs.ceil() as u64;
+    let (version, map) = match format {
+        StorageFormat::Ts => (3, ""),
+        StorageFormat::Fmp4 => (7, "#EXT-X-MAP:URI=\"init.mp4\"\n"),
+    };
 
     let playlist = format!(
         "#EXTM3U\n\
-         #EXT-X-VERSION:3\n\
+         #EXT-X-VERSION:{version}\n\
          #EXT-X-TARGETDURATION:{target_duration}\n\
          #EXT-X-MEDIA-SEQUENCE:0\n\
          #EXT-X-PLAYLIST-TYPE:VOD\n\
+         {map}\
          #EXTINF:{duration_secs:.3},\n\
          segment\n\
          #EXT-X-ENDLIST\n"
@@ -361,25 +831,327 @@ async fn warm_segment_handler(
     State(state): State<AppState>,
     Path((id, start_pts_str)): Path<(String, String)>,
 ) -> Response {
-    let index = match &state.warm_index {
-        Some(idx) => idx,
-        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
-    };
+    if state.warm_index.is_none() {
+        return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response();
+    }
 
     let start_pts: u64 = match start_pts_str.parse() {
         Ok(v) => v,
         Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
     };
 
-    let entry = match index.find_event(&id, start_pts) {
-        Some(e) => e,
+    let (index, entry) = match state.find_event(&id, start_pts) {
+        Some(found) => found,
         None => return (StatusCode::NOT_FOUND, "event not found").into_response(),
     };
 
     let file_path = index.resolve_file_path(&id, &entry);
 
     match tokio::fs::read(&file_path).await {
-        Ok(data) => ([(header::CONTENT_TYPE, "video/mp2t")], data).into_response(),
+        Ok(data) => match entry.format {
+            StorageFormat::Ts => ([(header::CONTENT_TYPE, "video/mp2t")], data).into_response(),
+            StorageFormat::Fmp4 => match fmp4::init_len(&data) {
+                Some(len) => {
+                    ([(header::CONTENT_TYPE, "video/mp4")], data[len..].to_vec()).into_response()
+                }
+                None => (StatusCode::INTERNAL_SERVER_ERROR, "invalid MP4 file").into_response(),
+            },
+        },
+        Err(_) => (StatusCode::NOT_FOUND, "event file not found").into_response(),
+    }
+}
+
+async fn warm_init_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str)): Path<(String, String)>,
+) -> Response {
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    let (index, entry) = match state.find_event(&id, start_pts) {
+        Some(found) if found.1.format == StorageFormat::Fmp4 => found,
+        _ => return (StatusCode::NOT_FOUND, "event not found").into_response(),
+    };
+
+    match tokio::fs::read(index.resolve_file_path(&id, &entry)).await {
+        Ok(mut data) => match fmp4::init_len(&data) {
+            Some(len) => {
+                data.truncate(len);
+                ([(header::CONTENT_TYPE, "video/mp4")], data).into_response()
+            }
+            None => (StatusCode::INTERNAL_SERVER_ERROR, "invalid MP4 file").into_response(),
+        },
         Err(_) => (StatusCode::NOT_FOUND, "event file not found").into_response(),
     }
 }
+
+/// Whole event as an MP4 attachment, remuxing MPEG-TS files on the fly
+async fn warm_download_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str)): Path<(String, String)>,
+) -> Response {
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    let (index, entry) = match state.find_event(&id, start_pts) {
+        Some(found) => found,
+        None => return (StatusCode::NOT_FOUND, "event not found").into_response(),
+    };
+
+    let data = match tokio::fs::read(index.resolve_file_path(&id, &entry)).await {
+        Ok(data) => data,
+        Err(_) => return (StatusCode::NOT_FOUND, "event file not found").into_response(),
+    };
+    let mp4 = match entry.format {
+        StorageFormat::Fmp4 => data,
+        StorageFormat::Ts => match tokio::task::spawn_blocking(move || fmp4::remux_ts(&data)).await
+        {
+            Ok(Some(mp4)) => mp4,
+            _ => {
+                return (StatusCode::UNPROCESSABLE_ENTITY, "event has no H.264 video")
+                    .into_response()
+            }
+        },
+    };
+
+    let disposition = format!("attachment; filename=\"{id}_{start_pts}.mp4\"");
+    (
+        [
+            (header::CONTENT_TYPE, "video/mp4".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        mp4,
+    )
+        .into_response()
+}
+
+async fn warm_thumbnail_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str)): Path<(String, String)>,
+) -> Response {
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    match state.metadata_store.get(&id, start_pts) {
+        Some(metadata) if metadata.has_thumbnail => {}
+        _ => return (StatusCode::NOT_FOUND, "thumbnail not found").into_response(),
+    }
+
+    let path = state.metadata_store.thumbnail_path(&id, start_pts);
+    match tokio::fs::read(&path).await {
+        Ok(data) => ([(header::CONTENT_TYPE, "image/jpeg")], data).into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "thumbnail not found").into_response(),
+    }
+}
+
+async fn alert_snapshot_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str, index, which)): Path<(String, String, usize, String)>,
+) -> Response {
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+    if which != "before" && which != "after" {
+        return (StatusCode::NOT_FOUND, "snapshot not found").into_response();
+    }
+
+    let has_snapshots = state
+        .metadata_store
+        .get(&id, start_pts)
+        .and_then(|m| m.alerts.get(index).map(|a| a.has_snapshots))
+        .unwrap_or(false);
+    if !has_snapshots {
+        return (StatusCode::NOT_FOUND, "snapshot not found").into_response();
+    }
+
+    let path = state
+        .metadata_store
+        .snapshot_path(&id, start_pts, index, &which);
+    match tokio::fs::read(&path).await {
+        Ok(data) => ([(header::CONTENT_TYPE, "image/jpeg")], data).into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "snapshot not found").into_response(),
+    }
+}
+
+// Continuous recording types and handlers
+
+#[derive(Serialize)]
+struct RecordingChunkItem {
+    start_pts_ns: String,
+    duration_ms: u32,
+    file_size: u64,
+}
+
+#[derive(Serialize)]
+struct RecordingMarkerItem {
+    start_pts_ns: String,
+    duration_ms: u32,
+    event_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct RecordingsResponse {
+    chunks: Vec<RecordingChunkItem>,
+    markers: Vec<RecordingMarkerItem>,
+}
+
+async fn recordings_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    let index = match &state.recording_index {
+        Some(idx) => idx,
+        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
+    };
+
+    if !state.buffers.contains_key(&id) {
+        return (StatusCode::NOT_FOUND, "camera not found").into_response();
+    }
+
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+
+    let chunks = index
+        .query(&id, from, to)
+        .into_iter()
+        .map(|c| RecordingChunkItem {
+            start_pts_ns: c.start_pts_ns.to_string(),
+            duration_ms: c.duration_ms,
+            file_size: c.file_size,
+        })
+        .collect();
+
+    // Stored events are reported as markers on the continuous timeline
+    let mut events: Vec<WarmEventEntry> = [state.warm_index.as_ref(), state.cold_index.as_ref()]
+        .into_iter()
+        .flatten()
+        .flat_map(|idx| idx.query(&id, from, to))
+        .collect();
+    events.sort_by_key(|e| e.start_pts_ns);
+    let markers = events
+        .into_iter()
+        .map(|e| RecordingMarkerItem {
+            start_pts_ns: e.start_pts_ns.to_string(),
+            duration_ms: e.duration_ms,
+            event_type: e.event_type.as_str(),
+        })
+        .collect();
+
+    axum::Json(RecordingsResponse { chunks, markers }).into_response()
+}
+
+async fn recording_playlist_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str)): Path<(String, String)>,
+) -> Response {
+    let index = match &state.recording_index {
+        Some(idx) => idx,
+        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
+    };
+
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    let entry = match index.find(&id, start_pts) {
+        Some(entry) => entry,
+        None => return (StatusCode::NOT_FOUND, "recording not found").into_response(),
+    };
+
+    match index.load_gops(&id, &entry).await {
+        Some(gops) if !gops.is_empty() => (
+            [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+            hls::generate_recording_playlist(&gops),
+        )
+            .into_response(),
+        _ => single_segment_playlist(entry.duration_ms, StorageFormat::Ts),
+    }
+}
+
+async fn recording_segment_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str)): Path<(String, String)>,
+) -> Response {
+    let index = match &state.recording_index {
+        Some(idx) => idx,
+        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
+    };
+
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    let entry = match index.find(&id, start_pts) {
+        Some(entry) => entry,
+        None => return (StatusCode::NOT_FOUND, "recording not found").into_response(),
+    };
+
+    let path = index.resolve_file_path(&id, &entry);
+    stream_file_range(&path, 0, entry.file_size).await
+}
+
+/// One GOP of a recording chunk, as listed in its playlist
+async fn recording_gop_handler(
+    State(state): State<AppState>,
+    Path((id, start_pts_str, n)): Path<(String, String, usize)>,
+) -> Response {
+    let index = match &state.recording_index {
+        Some(idx) => idx,
+        None => return (StatusCode::NOT_FOUND, "warm storage not enabled").into_response(),
+    };
+
+    let start_pts: u64 = match start_pts_str.parse() {
+        Ok(v) => v,
+        Err(_) => return (StatusCode::BAD_REQUEST, "invalid start_pts").into_response(),
+    };
+
+    let entry = match index.find(&id, start_pts) {
+        Some(entry) => entry,
+        None => return (StatusCode::NOT_FOUND, "recording not found").into_response(),
+    };
+
+    let gops = index.load_gops(&id, &entry).await;
+    let gop = match gops.as_ref().and_then(|gops| gops.get(n)) {
+        Some(gop) => gop,
+        None => return (StatusCode::NOT_FOUND, "segment not found").into_response(),
+    };
+
+    let path = index.resolve_file_path(&id, &entry);
+    stream_file_range(&path, gop.offset, gop.bytes).await
+}
+
+/// Stream `len` bytes of an MPEG-TS file from `offset` without loading
+/// them into memory
+async fn stream_file_range(path: &std::path::Path, offset: u64, len: u64) -> Response {
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(file) => file,
+        Err(_) => return (StatusCode::NOT_FOUND, "recording file not found").into_response(),
+    };
+    if let Err(e) = file.seek(std::io::SeekFrom::Start(offset)).await {
+        tracing::warn!(path = %path.display(), error = %e, "failed to seek recording file");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to read recording",
+        )
+            .into_response();
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "video/mp2t".to_string()),
+            (header::CONTENT_LENGTH, len.to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(file.take(len))),
+    )
+        .into_response()
+}
@@ -71,15 +71,18 @@ document.addEventListener('DOMContentLoaded', async () => {
     // Initialize
     try {
         const response = await fetch('/api/cameras');
-        cameras = await response.json();
+        const cameraList = await response.json();
+        cameras = cameraList.map(camera => camera.id);
 
         if (cameras.length === 0) {
             noCameras.hidden = false;
         } else {
-            cameras.forEach(cameraId => {
-                const cell = createCameraCell(cameraId);
+            cameraList.forEach(camera => {
+                const cell = createCameraCell(camera.id);
+                updateCameraState(cell, camera);
                 grid.appendChild(cell);
             });
+            setInterval(refreshCameraStates, 10000);
         }
     } catch (err) {
         console.error('Failed to fetch cameras:', err);
@@ -388,9 +391,31 @@ document.addEventListener('DOMContentLoaded', async () => {
         return cell;
     }
 
+    function updateCameraState(cell, status) {
+        const offline = status.state === 'offline';
+        cell.classList.toggle('offline', offline);
+        const label = cell.querySelector('.camera-label');
+        label.title = offline && status.last_error ? status.last_error : '';
+    }
+
+    async function refreshCameraStates() {
+        try {
+            const response = await fetch('/api/cameras');
+            const cameraList = await response.json();
+            cameraList.forEach(camera => {
+                const cell = grid.querySelector(`[data-camera-id="${camera.id}"]`);
+                if (cell) {
+                    updateCameraState(cell, camera);
+                }
+            });
+        } catch (err) {
+            console.error('Failed to refresh camera status:', err);
+        }
+    }
+
     // Grid camera loading
     function loadGridCamera(cameraId, video) {
-        const src = `/api/stream/${cameraId}/playlist.m3u8`;
+        const src = `/api/stream/${cameraId}/master.m3u8`;
         const loading = video.parentElement.querySelector('.loading');
 
         if (typeof Hls !== 'undefined' && Hls.isSupported()) {
@@ -425,7 +450,8 @@ document.addEventListener('DOMContentLoaded', async () => {
                 }
             });
         } else if (video.canPlayType('application/vnd.apple.mpegurl')) {
-            video.src = src;
+            // Native HLS (Safari/iOS) plays the fMP4 variant
+            video.src = `/api/stream/${cameraId}/fmp4/master.m3u8`;
             video.addEventListener('loadedmetadata', () => {
                 loading.hidden = true;
                 video.play().catch(e => console.error(`Play failed for ${cameraId}:`, e));
@@ -437,7 +463,7 @@ document.addEventListener('DOMContentLoaded', async () => {
 
     // Detail camera loading (live stream)
     function loadDetailCamera(cameraId) {
-        const src = `/api/stream/${cameraId}/playlist.m3u8`;
+        const src = `/api/stream/${cameraId}/master.m3u8`;
 
         if (typeof Hls !== 'undefined' && Hls.isSupported()) {
             detailHls = new Hls({
@@ -473,7 +499,7 @@ document.addEventListener('DOMContentLoaded', async () => {
                 }
             });
         } else if (detailVideo.canPlayType('application/vnd.apple.mpegurl')) {
-            detailVideo.src = src;
+            detailVideo.src = `/api/stream/${cameraId}/fmp4/master.m3u8`;
             detailVideo.addEventListener('loadedmetadata', () => {
                 detailLoading.hidden = true;
                 detailVideo.play().catch(e => console.error(`Play failed for ${cameraId}:`, e));
@@ -796,7 +822,11 @@ document.addEventListener('DOMContentLoaded', async () => {
             const width = Math.max(2, endX - startX); // minimum 2px visibility
 
             const isPlaying = isPlayingWarmEvent && currentWarmEventPts === ev.start_pts_ns;
-            if (ev.event_type === 'object') {
+            if (ev.event_type === 'tamper') {
+                eventStripCtx.fillStyle = isPlaying ? 'rgba(60, 130, 240, 1)' : 'rgba(60, 130, 240, 0.8)';
+            } else if (ev.event_type === 'rule') {
+                eventStripCtx.fillStyle = isPlaying ? 'rgba(170, 80, 220, 1)' : 'rgba(170, 80, 220, 0.8)';
+            } else if (ev.event_type === 'object') {
                 eventStripCtx.fillStyle = isPlaying ? 'rgba(220, 50, 50, 1)' : 'rgba(220, 50, 50, 0.8)';
             } else {
                 eventStripCtx.fillStyle = isPlaying ? 'rgba(255, 200, 50, 1)' : 'rgba(255, 200, 50, 0.7)';
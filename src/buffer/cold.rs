@@ -0,0 +1,200 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+use tokio::process::Command;
+
+use crate::config::{ColdConfig, StorageFormat};
+use crate::storage::atomic;
+use crate::storage::{EventMetadataStore, WarmEventEntry, WarmEventIndex};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const SECS_PER_HOUR: u64 = 60 * 60;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+/// Moves aged warm event files into the cold archive, transcoding them down
+/// to a lower resolution and frame rate, and prunes expired cold files.
+pub struct ColdArchiver {
+    warm_index: WarmEventIndex,
+    cold_index: WarmEventIndex,
+    metadata_store: EventMetadataStore,
+    archive_after_ns: u64,
+    retention_ns: u64,
+    height: u32,
+    fps: u32,
+    scan_interval: Duration,
+}
+
+impl ColdArchiver {
+    pub fn new(
+        warm_index: WarmEventIndex,
+        cold_index: WarmEventIndex,
+        metadata_store: EventMetadataStore,
+        config: &ColdConfig,
+    ) -> Self {
+        Self {
+            warm_index,
+            cold_index,
+            metadata_store,
+            archive_after_ns: config.archive_after_hours * SECS_PER_HOUR * NANOS_PER_SEC,
+            retention_ns: config.retention_days * SECS_PER_DAY * NANOS_PER_SEC,
+            height: config.height,
+            fps: config.fps,
+            scan_interval: Duration::from_secs(config.scan_interval_secs.max(1)),
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.scan_interval);
+        loop {
+            interval.tick().await;
+            for camera_id in self.warm_index.camera_ids() {
+                self.archive_camera(&camera_id).await;
+                self.prune_camera(&camera_id).await;
+            }
+        }
+    }
+
+    async fn archive_camera(&self, camera_id: &str) {
+        let cutoff = now_ns().saturating_sub(self.archive_after_ns);
+        for entry in self.warm_index.older_than(camera_id, cutoff) {
+            let src = self.warm_index.resolve_file_path(camera_id, &entry);
+            // Cold archive files are always transcoded to MPEG-TS
+            let cold_entry = WarmEventEntry {
+                format: StorageFormat::Ts,
+                ..entry.clone()
+            };
+            let dst = self.cold_index.resolve_file_path(camera_id, &cold_entry);
+
+            match self.transcode(&src, &dst).await {
+                Ok(file_size) => {
+                    tracing::info!(
+                        camera = %camera_id,
+                        path = %dst.display(),
+                        warm_bytes = entry.file_size,
+                        cold_bytes = file_size,
+                        "archived event to cold storage"
+                    );
+                    let dst_sidecar = self.cold_index.resolve_sidecar_path(camera_id, &cold_entry);
+                    self.cold_index.insert(
+                        camera_id,
+                        WarmEventEntry {
+                            file_size,
+                            ..cold_entry
+                        },
+                    );
+                    let src_sidecar = self.warm_index.resolve_sidecar_path(camera_id, &entry);
+                    if entry.sidecar.is_some() {
+                        if let Err(e) = tokio::fs::rename(&src_sidecar, &dst_sidecar).await {
+                            tracing::warn!(
+                                camera = %camera_id,
+                                path = %src_sidecar.display(),
+                                error = %e,
+                                "failed to move event sidecar to cold storage"
+                            );
+                        }
+                    }
+                    self.warm_index.remove(camera_id, entry.start_pts_ns);
+                    if let Err(e) = tokio::fs::remove_file(&src).await {
+                        tracing::warn!(
+                            camera = %camera_id,
+                            path = %src.display(),
+                            error = %e,
+                            "failed to remove archived warm event file"
+                        );
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(
+                        camera = %camera_id,
+                        path = %src.display(),
+                        error = %e,
+                        "failed to transcode event for cold storage"
+                    );
+                }
+            }
+        }
+    }
+
+    async fn prune_camera(&self, camera_id: &str) {
+        if self.retention_ns == 0 {
+            return;
+        }
+        let cutoff = now_ns().saturating_sub(self.retention_ns);
+        for entry in self.cold_index.older_than(camera_id, cutoff) {
+            let path = self.cold_index.resolve_file_path(camera_id, &entry);
+            let sidecar_path = self.cold_index.resolve_sidecar_path(camera_id, &entry);
+            self.cold_index.remove(camera_id, entry.start_pts_ns);
+            let _ = tokio::fs::remove_file(&sidecar_path).await;
+            self.metadata_store
+                .remove(camera_id, entry.start_pts_ns)
+                .await;
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {
+                    tracing::info!(
+                        camera = %camera_id,
+                        path = %path.display(),
+                        "removed expired cold event file"
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        camera = %camera_id,
+                        path = %path.display(),
+                        error = %e,
+                        "failed to remove expired cold event file"
+                    );
+                }
+            }
+        }
+    }
+
+    async fn transcode(&self, src: &Path, dst: &Path) -> Result<u64, std::io::Error> {
+        if let Some(parent) = dst.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let tmp_path = atomic::partial_path(dst);
+        let status = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+            .arg(src)
+            .args([
+                "-vf",
+                &format!("scale=-2:{},fps={}", self.height, self.fps),
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "28",
+                "-an",
+                "-f",
+                "mpegts",
+            ])
+            .arg(&tmp_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status()
+            .await?;
+
+        if !status.success() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(std::io::Error::other(format!(
+                "ffmpeg exited with {status}"
+            )));
+        }
+
+        tokio::fs::File::open(&tmp_path).await?.sync_all().await?;
+        atomic::commit(&tmp_path, dst).await?;
+        Ok(tokio::fs::metadata(dst).await?.len())
+    }
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use tokio::io::AsyncWriteExt;
+
+use super::GopSegment;
+use crate::storage::atomic;
+use crate::storage::{ChunkGop, RecordingEntry, RecordingIndex};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const NANOS_PER_MS: u64 = 1_000_000;
+/// A gap larger than this between segments (e.g. a reconnect) starts a new chunk
+const MAX_GAP_NS: u64 = 2 * NANOS_PER_SEC;
+
+struct Chunk {
+    file: tokio::fs::File,
+    partial_path: PathBuf,
+    first_pts: u64,
+    duration_ns: u64,
+    bytes: u64,
+    gops: Vec<ChunkGop>,
+}
+
+impl Chunk {
+    fn end_pts(&self) -> u64 {
+        self.first_pts + self.duration_ns
+    }
+}
+
+/// Appends every evicted segment to chunk files aligned to `chunk_secs`
+/// boundaries (hourly by default). A chunk is written as a `.partial` file
+/// and renamed to `{first_pts}_{duration_ms}.ts` once it is complete, after
+/// its GOP list is written next to it.
+pub struct ContinuousRecorder {
+    camera_id: String,
+    chunk_ns: u64,
+    index: RecordingIndex,
+    current: Option<Chunk>,
+}
+
+impl ContinuousRecorder {
+    pub fn new(camera_id: String, chunk_secs: u64, index: RecordingIndex) -> Self {
+        Self {
+            camera_id,
+            chunk_ns: chunk_secs.max(1) * NANOS_PER_SEC,
+            index,
+            current: None,
+        }
+    }
+
+    pub async fn push(&mut self, segment: &GopSegment) {
+        let rollover = self.current.as_ref().is_some_and(|chunk| {
+            segment.start_pts / self.chunk_ns != chunk.first_pts / self.chunk_ns
+                || segment.start_pts < chunk.end_pts()
+                || segment.start_pts > chunk.end_pts() + MAX_GAP_NS
+        });
+        if rollover {
+            self.finish().await;
+        }
+
+        if self.current.is_none() {
+            match self.open_chunk(segment.start_pts).await {
+                Ok(chunk) => self.current = Some(chunk),
+                Err(e) => {
+                    tracing::error!(
+                        camera = %self.camera_id,
+                        error = %e,
+                        "failed to open recording chunk"
+                    );
+                    return;
+                }
+            }
+        }
+
+        let Some(chunk) = self.current.as_mut() else {
+            return;
+        };
+        if let Err(e) = chunk.file.write_all(&segment.data).await {
+            tracing::error!(
+                camera = %self.camera_id,
+                path = %chunk.partial_path.display(),
+                error = %e,
+                "failed to append to recording chunk"
+            );
+            return;
+        }
+        chunk.duration_ns = segment.start_pts + segment.duration_ns - chunk.first_pts;
+        chunk.gops.push(ChunkGop {
+            offset: chunk.bytes,
+            bytes: segment.data.len() as u64,
+            duration_ns: segment.duration_ns,
+        });
+        chunk.bytes += segment.data.len() as u64;
+    }
+
+    /// Flush and rename the current chunk into place
+    pub async fn finish(&mut self) {
+        let Some(chunk) = self.current.take() else {
+            return;
+        };
+
+        let entry = RecordingEntry {
+            start_pts_ns: chunk.first_pts,
+            duration_ms: (chunk.duration_ns / NANOS_PER_MS) as u32,
+            file_size: chunk.bytes,
+        };
+        let path = self.index.resolve_file_path(&self.camera_id, &entry);
+
+        // Without its GOP list the chunk still plays, as a single segment
+        let gops_path = self.index.gops_path(&self.camera_id, &entry);
+        let gops = serde_json::to_vec(&chunk.gops).unwrap_or_default();
+        if let Err(e) = atomic::write_atomic(&gops_path, &gops).await {
+            tracing::warn!(
+                camera = %self.camera_id,
+                path = %gops_path.display(),
+                error = %e,
+                "failed to write recording GOP list"
+            );
+        }
+
+        let result = match chunk.file.sync_all().await {
+            Ok(()) => {
+                drop(chunk.file);
+                atomic::commit(&chunk.partial_path, &path).await
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(()) => {
+                tracing::info!(
+                    camera = %self.camera_id,
+                    path = %path.display(),
+                    bytes = entry.file_size,
+                    duration_ms = entry.duration_ms,
+                    "wrote recording chunk"
+                );
+                self.index.insert(&self.camera_id, entry);
+            }
+            Err(e) => {
+                tracing::error!(
+                    camera = %self.camera_id,
+                    path = %chunk.partial_path.display(),
+                    error = %e,
+                    "failed to finalize recording chunk"
+                );
+            }
+        }
+    }
+
+    async fn open_chunk(&self, first_pts: u64) -> Result<Chunk, std::io::Error> {
+        let dir = self.index.chunk_dir(&self.camera_id);
+        tokio::fs::create_dir_all(&dir).await?;
+        let partial_path = atomic::partial_path(&dir.join(format!("{first_pts}_0.ts")));
+        let file = tokio::fs::File::create(&partial_path).await?;
+        Ok(Chunk {
+            file,
+            partial_path,
+            first_pts,
+            duration_ns: 0,
+            bytes: 0,
+            gops: Vec::new(),
+        })
+    }
+}
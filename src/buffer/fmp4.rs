@@ -0,0 +1,1195 @@
+//! Remuxes MPEG-TS GOP segments into fragmented MP4 (CMAF) without re-encoding.
+//! Only the H.264 or HEVC video track is carried over.
+
+use super::nal::{split_annex_b, NalKind, VideoCodec};
+use super::ts::{self, PACKET_SIZE, PTS_CLOCK_HZ, PTS_WRAP, SYNC_BYTE};
+use super::GopSegment;
+
+/// Media timescale; matches the MPEG-TS 90 kHz clock so timestamps carry over as-is
+pub const TIMESCALE: u32 = PTS_CLOCK_HZ as u32;
+
+const TRACK_ID: u32 = 1;
+/// Sample duration used when there is no following frame to measure against
+const DEFAULT_SAMPLE_DURATION: u64 = PTS_CLOCK_HZ / 30;
+/// DTS jumps larger than this are treated as a stream discontinuity
+const MAX_DTS_STEP: u64 = 5 * PTS_CLOCK_HZ;
+
+const HEVC_NAL_VPS: u8 = 32;
+const HEVC_NAL_SPS: u8 = 33;
+const HEVC_NAL_PPS: u8 = 34;
+
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+/// Parameter sets and the picture size decoded from the SPS
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoParams {
+    pub codec: VideoCodec,
+    /// HEVC only
+    pub vps: Vec<u8>,
+    pub sps: Vec<u8>,
+    pub pps: Vec<u8>,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl VideoParams {
+    pub fn new(codec: VideoCodec, vps: &[u8], sps: &[u8], pps: &[u8]) -> Option<Self> {
+        let (width, height) = match codec {
+            VideoCodec::H264 => parse_sps_dimensions(sps)?,
+            VideoCodec::Hevc if vps.is_empty() => return None,
+            VideoCodec::Hevc => {
+                let info = parse_hevc_sps(sps)?;
+                (info.width, info.height)
+            }
+        };
+        Some(Self {
+            codec,
+            vps: vps.to_vec(),
+            sps: sps.to_vec(),
+            pps: pps.to_vec(),
+            width,
+            height,
+        })
+    }
+
+    /// RFC 6381 codec string for the HLS `CODECS` attribute, e.g.
+    /// `avc1.640028` or `hvc1.1.6.L120.B0`
+    pub fn codec_string(&self) -> Option<String> {
+        match self.codec {
+            VideoCodec::H264 => {
+                let profile = self.sps.get(1..4)?;
+                Some(format!(
+                    "avc1.{:02X}{:02X}{:02X}",
+                    profile[0], profile[1], profile[2]
+                ))
+            }
+            VideoCodec::Hevc => parse_hevc_sps(&self.sps).map(|info| info.codec_string()),
+        }
+    }
+}
+
+/// One access unit with length-prefixed NAL units. `dts` is in 90 kHz ticks
+/// relative to the first sample of the demuxed input.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub dts: u64,
+    pub cts_offset: i32,
+    pub keyframe: bool,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Demuxed {
+    pub params: Option<VideoParams>,
+    pub samples: Vec<Sample>,
+}
+
+impl Demuxed {
+    /// Decode time just past the last sample
+    fn end_dts(&self) -> u64 {
+        end_dts(&self.samples)
+    }
+
+    /// Split into GOPs at keyframes, placing them on the wall-clock timeline
+    /// with the first sample at `origin_ns`
+    pub fn into_gops(mut self, origin_ns: u64) -> Vec<Gop> {
+        let end = self.end_dts();
+        split_gops(&mut self.samples, origin_ns, Some(end))
+    }
+}
+
+/// A run of samples starting at a keyframe, with wall-clock bounds
+#[derive(Debug)]
+pub struct Gop {
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Decode time just past the last sample, on the samples' timeline
+    pub end_dts: u64,
+    pub samples: Vec<Sample>,
+}
+
+fn end_dts(samples: &[Sample]) -> u64 {
+    match samples {
+        [] => 0,
+        [only] => only.dts + DEFAULT_SAMPLE_DURATION,
+        [.., prev, last] => last.dts + (last.dts - prev.dts).max(1),
+    }
+}
+
+/// Drain complete GOPs from `samples`. The trailing GOP is only drained when
+/// `end` gives its closing decode time.
+fn split_gops(samples: &mut Vec<Sample>, origin_ns: u64, end: Option<u64>) -> Vec<Gop> {
+    let mut bounds: Vec<usize> = (0..samples.len())
+        .filter(|&i| i == 0 || samples[i].keyframe)
+        .collect();
+    if end.is_some() {
+        bounds.push(samples.len());
+    }
+    if bounds.len() < 2 {
+        return Vec::new();
+    }
+
+    let rest = samples.split_off(bounds[bounds.len() - 1]);
+    let done = std::mem::replace(samples, rest);
+    let final_end = end.unwrap_or_else(|| samples[0].dts);
+
+    let mut runs = Vec::with_capacity(bounds.len() - 1);
+    let mut iter = done.into_iter();
+    for w in bounds.windows(2) {
+        runs.push(iter.by_ref().take(w[1] - w[0]).collect::<Vec<_>>());
+    }
+
+    let ends: Vec<u64> = runs
+        .iter()
+        .skip(1)
+        .map(|run| run[0].dts)
+        .chain(std::iter::once(final_end))
+        .collect();
+    runs.into_iter()
+        .zip(ends)
+        .map(|(samples, end_dts)| Gop {
+            start_ns: origin_ns + ticks_to_ns(samples[0].dts),
+            end_ns: origin_ns + ticks_to_ns(end_dts),
+            end_dts,
+            samples,
+        })
+        .collect()
+}
+
+/// Extract H.264 access units from MPEG-TS data. Decode timestamps are
+/// unwrapped onto a single timeline, so concatenated GOPs from several
+/// connections still come out monotonic.
+pub fn demux(data: &[u8]) -> Demuxed {
+    let mut demuxer = TsDemuxer::default();
+    demuxer.feed(data);
+    demuxer.finish();
+    demuxer.out
+}
+
+/// Incremental MPEG-TS to H.264 sample demuxer for inputs too large to hold
+/// in memory
+#[derive(Default)]
+pub struct TsDemuxer {
+    pmt_pid: Option<u16>,
+    video_pid: Option<u16>,
+    codec: Option<VideoCodec>,
+    pes: Vec<u8>,
+    pes_timestamps: Option<(u64, u64)>,
+    last_dts: Option<u64>,
+    timeline: u64,
+    remainder: Vec<u8>,
+    out: Demuxed,
+}
+
+impl TsDemuxer {
+    pub fn feed(&mut self, mut data: &[u8]) {
+        if !self.remainder.is_empty() {
+            let needed = PACKET_SIZE - self.remainder.len();
+            let take = needed.min(data.len());
+            self.remainder.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.remainder.len() < PACKET_SIZE {
+                return;
+            }
+            let packet = std::mem::take(&mut self.remainder);
+            if packet[0] == SYNC_BYTE {
+                self.packet(&packet);
+            }
+        }
+        let mut packets = data.chunks_exact(PACKET_SIZE);
+        for packet in packets.by_ref() {
+            if packet[0] == SYNC_BYTE {
+                self.packet(packet);
+            }
+        }
+        self.remainder.extend_from_slice(packets.remainder());
+    }
+
+    /// Flush the last buffered access unit
+    pub fn finish(&mut self) {
+        self.flush();
+    }
+
+    pub fn params(&self) -> Option<&VideoParams> {
+        self.out.params.as_ref()
+    }
+
+    /// Drain GOPs that are complete, or every GOP once `finish` was called
+    /// and `last` is set
+    pub fn take_gops(&mut self, origin_ns: u64, last: bool) -> Vec<Gop> {
+        let end = last.then(|| end_dts(&self.out.samples));
+        split_gops(&mut self.out.samples, origin_ns, end)
+    }
+
+    fn packet(&mut self, packet: &[u8]) {
+        let pid = ts::pid(packet);
+        if pid == 0 {
+            if let Some(pmt_pid) = ts::psi_section(packet).and_then(ts::pat_pmt_pid) {
+                self.pmt_pid = Some(pmt_pid);
+            }
+            return;
+        }
+        if Some(pid) == self.pmt_pid {
+            if let Some(section) = ts::psi_section(packet) {
+                let video = ts::pmt_streams(section)
+                    .into_iter()
+                    .find_map(|(stream_type, pid)| {
+                        VideoCodec::from_stream_type(stream_type).map(|codec| (codec, pid))
+                    });
+                if let Some((codec, video_pid)) = video {
+                    self.video_pid = Some(video_pid);
+                    self.codec = Some(codec);
+                }
+            }
+            return;
+        }
+        if Some(pid) != self.video_pid {
+            return;
+        }
+
+        if ts::payload_unit_start(packet) {
+            self.flush();
+            self.pes_timestamps = ts::pes_timestamps(packet);
+        }
+        if let Some(offset) = ts::payload_offset(packet) {
+            self.pes.extend_from_slice(&packet[offset..]);
+        }
+    }
+
+    fn flush(&mut self) {
+        let pes = std::mem::take(&mut self.pes);
+        let Some((pts, dts)) = self.pes_timestamps.take() else {
+            return;
+        };
+        let (Some(payload), Some(codec)) = (ts::pes_payload(&pes), self.codec) else {
+            return;
+        };
+
+        // Parameter sets live in the sample entry, not in the samples
+        let mut keyframe = false;
+        let mut data = Vec::with_capacity(payload.len());
+        let mut vps: &[u8] = &[];
+        let mut sps = None;
+        let mut pps = None;
+        for nal in split_annex_b(payload) {
+            match codec.kind(nal) {
+                NalKind::Vps => vps = nal,
+                NalKind::Sps => sps = Some(nal),
+                NalKind::Pps => pps = Some(nal),
+                NalKind::Delimiter => {}
+                kind => {
+                    keyframe |= kind == NalKind::Keyframe;
+                    data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    data.extend_from_slice(nal);
+                }
+            }
+        }
+        if let (Some(sps), Some(pps)) = (sps, pps) {
+            if let Some(params) = VideoParams::new(codec, vps, sps, pps) {
+                self.out.params = Some(params);
+            }
+        }
+        if data.is_empty() {
+            return;
+        }
+
+        if let Some(last) = self.last_dts {
+            let step = ts::pts_diff(last, dts);
+            self.timeline += if step == 0 || step > MAX_DTS_STEP {
+                DEFAULT_SAMPLE_DURATION
+            } else {
+                step
+            };
+        }
+        self.last_dts = Some(dts);
+
+        let cts = ts::pts_diff(dts, pts);
+        let cts_offset = if cts < PTS_WRAP / 2 {
+            cts as i32
+        } else {
+            -((PTS_WRAP - cts) as i32)
+        };
+        self.out.samples.push(Sample {
+            dts: self.timeline,
+            cts_offset,
+            keyframe,
+            data,
+        });
+    }
+}
+
+/// Exp-Golomb bit reader over an RBSP with emulation prevention bytes removed
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(nal: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(nal.len());
+        let mut zeros = 0;
+        for &b in nal {
+            if zeros >= 2 && b == 0x03 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if b == 0 { zeros + 1 } else { 0 };
+            data.push(b);
+        }
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        (0..n).try_fold(0, |acc, _| Some((acc << 1) | self.bit()?))
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << zeros) - 1 + self.bits(zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let v = self.ue()?;
+        Some(if v % 2 == 1 {
+            v.div_ceil(2) as i32
+        } else {
+            -((v / 2) as i32)
+        })
+    }
+}
+
+/// Cropped picture size from an H.264 SPS NAL unit (including its header byte)
+fn parse_sps_dimensions(sps: &[u8]) -> Option<(u16, u16)> {
+    let mut r = BitReader::new(sps.get(1..)?);
+    let profile_idc = r.bits(8)?;
+    r.bits(16)?; // constraint flags + level_idc
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            r.bit()?; // separate_colour_plane_flag
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.bit()?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? == 1 {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.bit()?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.bit()?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = r.ue()? + 1;
+    let height_map_units = r.ue()? + 1;
+    let frame_mbs_only = r.bit()?;
+    if frame_mbs_only == 0 {
+        r.bit()?; // mb_adaptive_frame_field_flag
+    }
+    r.bit()?; // direct_8x8_inference_flag
+
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if r.bit()? == 1 {
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        let (sub_width, sub_height) = match chroma_format_idc {
+            0 => (1, 1),
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        crop_x = (left + right) * sub_width;
+        crop_y = (top + bottom) * sub_height * (2 - frame_mbs_only);
+    }
+
+    let width = (width_mbs * 16).checked_sub(crop_x)?;
+    let height = ((2 - frame_mbs_only) * height_map_units * 16).checked_sub(crop_y)?;
+    Some((u16::try_from(width).ok()?, u16::try_from(height).ok()?))
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let mut last = 8i32;
+    let mut next = 8i32;
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+/// The parts of an HEVC SPS needed for `hvcC` and the codec string
+struct HevcSps {
+    profile_space: u8,
+    tier: u8,
+    profile_idc: u8,
+    compatibility: u32,
+    constraints: [u8; 6],
+    level_idc: u8,
+    max_sub_layers: u8,
+    temporal_id_nesting: u8,
+    chroma_format_idc: u8,
+    bit_depth_luma_minus8: u8,
+    bit_depth_chroma_minus8: u8,
+    width: u16,
+    height: u16,
+}
+
+impl HevcSps {
+    fn codec_string(&self) -> String {
+        let space = ["", "A", "B", "C"][self.profile_space as usize & 0x03];
+        let tier = if self.tier == 1 { 'H' } else { 'L' };
+        let mut codec = format!(
+            "hvc1.{space}{}.{:X}.{tier}{}",
+            self.profile_idc,
+            self.compatibility.reverse_bits(),
+            self.level_idc
+        );
+        let used = self
+            .constraints
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(1, |i| i + 1);
+        for byte in &self.constraints[..used] {
+            codec.push_str(&format!(".{byte:X}"));
+        }
+        codec
+    }
+}
+
+/// Profile, format and cropped picture size from an HEVC SPS NAL unit
+/// (including its two header bytes)
+fn parse_hevc_sps(sps: &[u8]) -> Option<HevcSps> {
+    let mut r = BitReader::new(sps.get(2..)?);
+    r.bits(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.bits(3)?;
+    let temporal_id_nesting = r.bit()?;
+
+    // profile_tier_level
+    let profile_space = r.bits(2)?;
+    let tier = r.bit()?;
+    let profile_idc = r.bits(5)?;
+    let compatibility = r.bits(32)?;
+    let mut constraints = [0u8; 6];
+    for byte in constraints.iter_mut() {
+        *byte = r.bits(8)? as u8;
+    }
+    let level_idc = r.bits(8)?;
+    let mut sub_layers = Vec::new();
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.bit()?, r.bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        for _ in max_sub_layers_minus1..8 {
+            r.bits(2)?; // reserved_zero_2bits
+        }
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present == 1 {
+            r.bits(32)?;
+            r.bits(32)?;
+            r.bits(24)?;
+        }
+        if level_present == 1 {
+            r.bits(8)?;
+        }
+    }
+
+    r.ue()?; // sps_seq_parameter_set_id
+    let chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 {
+        r.bit()?; // separate_colour_plane_flag
+    }
+    let mut width = r.ue()?;
+    let mut height = r.ue()?;
+    if r.bit()? == 1 {
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        let (sub_width, sub_height) = match chroma_format_idc {
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        width = width.checked_sub((left + right) * sub_width)?;
+        height = height.checked_sub((top + bottom) * sub_height)?;
+    }
+    let bit_depth_luma_minus8 = r.ue()?;
+    let bit_depth_chroma_minus8 = r.ue()?;
+
+    Some(HevcSps {
+        profile_space: profile_space as u8,
+        tier: tier as u8,
+        profile_idc: profile_idc as u8,
+        compatibility,
+        constraints,
+        level_idc: level_idc as u8,
+        max_sub_layers: max_sub_layers_minus1 as u8 + 1,
+        temporal_id_nesting: temporal_id_nesting as u8,
+        chroma_format_idc: chroma_format_idc as u8,
+        bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+        bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+        width: u16::try_from(width).ok()?,
+        height: u16::try_from(height).ok()?,
+    })
+}
+
+// Box writing
+
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(kind);
+    body(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn write_full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(out, kind, |out| {
+        out.extend_from_slice(&((version as u32) << 24 | flags).to_be_bytes());
+        body(out);
+    });
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_matrix(out: &mut Vec<u8>) {
+    for v in [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        put_u32(out, v);
+    }
+}
+
+/// `ftyp` + `moov` describing a single video track with no samples
+pub fn init_segment(params: &VideoParams) -> Vec<u8> {
+    let codec_brand = match params.codec {
+        VideoCodec::H264 => b"avc1",
+        VideoCodec::Hevc => b"hvc1",
+    };
+    let mut out = Vec::with_capacity(1024);
+    write_box(&mut out, b"ftyp", |out| {
+        out.extend_from_slice(b"iso6");
+        put_u32(out, 0);
+        for brand in [b"iso6", b"cmfc", b"isom", codec_brand, b"mp41"] {
+            out.extend_from_slice(brand);
+        }
+    });
+    write_box(&mut out, b"moov", |out| {
+        write_full_box(out, b"mvhd", 0, 0, |out| {
+            put_u32(out, 0); // creation_time
+            put_u32(out, 0); // modification_time
+            put_u32(out, 1000);
+            put_u32(out, 0); // duration
+            put_u32(out, 0x0001_0000); // rate
+            put_u16(out, 0x0100); // volume
+            out.extend_from_slice(&[0; 10]);
+            put_matrix(out);
+            out.extend_from_slice(&[0; 24]);
+            put_u32(out, TRACK_ID + 1);
+        });
+        write_box(out, b"trak", |out| {
+            write_full_box(out, b"tkhd", 0, 0x03, |out| {
+                put_u32(out, 0);
+                put_u32(out, 0);
+                put_u32(out, TRACK_ID);
+                put_u32(out, 0);
+                put_u32(out, 0); // duration
+                out.extend_from_slice(&[0; 8]);
+                put_u16(out, 0); // layer
+                put_u16(out, 0); // alternate_group
+                put_u16(out, 0); // volume
+                put_u16(out, 0);
+                put_matrix(out);
+                put_u32(out, (params.width as u32) << 16);
+                put_u32(out, (params.height as u32) << 16);
+            });
+            write_box(out, b"mdia", |out| {
+                write_full_box(out, b"mdhd", 0, 0, |out| {
+                    put_u32(out, 0);
+                    put_u32(out, 0);
+                    put_u32(out, TIMESCALE);
+                    put_u32(out, 0);
+                    put_u16(out, 0x55C4); // "und"
+                    put_u16(out, 0);
+                });
+                write_full_box(out, b"hdlr", 0, 0, |out| {
+                    put_u32(out, 0);
+                    out.extend_from_slice(b"vide");
+                    out.extend_from_slice(&[0; 12]);
+                    out.extend_from_slice(b"VideoHandler\0");
+                });
+                write_box(out, b"minf", |out| {
+                    write_full_box(out, b"vmhd", 0, 0x01, |out| {
+                        out.extend_from_slice(&[0; 8]);
+                    });
+                    write_box(out, b"dinf", |out| {
+                        write_full_box(out, b"dref", 0, 0, |out| {
+                            put_u32(out, 1);
+                            write_full_box(out, b"url ", 0, 0x01, |_| {});
+                        });
+                    });
+                    write_box(out, b"stbl", |out| {
+                        write_full_box(out, b"stsd", 0, 0, |out| {
+                            put_u32(out, 1);
+                            write_sample_entry(out, params);
+                        });
+                        write_full_box(out, b"stts", 0, 0, |out| put_u32(out, 0));
+                        write_full_box(out, b"stsc", 0, 0, |out| put_u32(out, 0));
+                        write_full_box(out, b"stsz", 0, 0, |out| {
+                            put_u32(out, 0);
+                            put_u32(out, 0);
+                        });
+                        write_full_box(out, b"stco", 0, 0, |out| put_u32(out, 0));
+                    });
+                });
+            });
+        });
+        write_box(out, b"mvex", |out| {
+            write_full_box(out, b"trex", 0, 0, |out| {
+                put_u32(out, TRACK_ID);
+                put_u32(out, 1); // default_sample_description_index
+                put_u32(out, 0);
+                put_u32(out, 0);
+                put_u32(out, 0);
+            });
+        });
+    });
+    out
+}
+
+fn write_sample_entry(out: &mut Vec<u8>, params: &VideoParams) {
+    let kind = match params.codec {
+        VideoCodec::H264 => b"avc1",
+        VideoCodec::Hevc => b"hvc1",
+    };
+    write_box(out, kind, |out| {
+        out.extend_from_slice(&[0; 6]);
+        put_u16(out, 1); // data_reference_index
+        out.extend_from_slice(&[0; 16]);
+        put_u16(out, params.width);
+        put_u16(out, params.height);
+        put_u32(out, 0x0048_0000); // 72 dpi
+        put_u32(out, 0x0048_0000);
+        put_u32(out, 0);
+        put_u16(out, 1); // frame_count
+        out.extend_from_slice(&[0; 32]); // compressorname
+        put_u16(out, 0x0018); // depth
+        put_u16(out, 0xFFFF);
+        match params.codec {
+            VideoCodec::H264 => write_avcc(out, params),
+            VideoCodec::Hevc => write_hvcc(out, params),
+        }
+    });
+}
+
+fn write_avcc(out: &mut Vec<u8>, params: &VideoParams) {
+    write_box(out, b"avcC", |out| {
+        out.push(1);
+        out.extend_from_slice(&params.sps[1..4]);
+        out.push(0xFF); // 4-byte NAL lengths
+        out.push(0xE1); // one SPS
+        put_u16(out, params.sps.len() as u16);
+        out.extend_from_slice(&params.sps);
+        out.push(1); // one PPS
+        put_u16(out, params.pps.len() as u16);
+        out.extend_from_slice(&params.pps);
+    });
+}
+
+fn write_hvcc(out: &mut Vec<u8>, params: &VideoParams) {
+    let Some(info) = parse_hevc_sps(&params.sps) else {
+        return;
+    };
+    write_box(out, b"hvcC", |out| {
+        out.push(1);
+        out.push(info.profile_space << 6 | info.tier << 5 | info.profile_idc);
+        put_u32(out, info.compatibility);
+        out.extend_from_slice(&info.constraints);
+        out.push(info.level_idc);
+        put_u16(out, 0xF000); // min_spatial_segmentation_idc
+        out.push(0xFC); // parallelismType
+        out.push(0xFC | info.chroma_format_idc);
+        out.push(0xF8 | info.bit_depth_luma_minus8);
+        out.push(0xF8 | info.bit_depth_chroma_minus8);
+        put_u16(out, 0); // avgFrameRate
+
+        // lengthSizeMinusOne = 3: 4-byte NAL lengths
+        out.push(info.max_sub_layers << 3 | info.temporal_id_nesting << 2 | 0x03);
+        let arrays = [
+            (HEVC_NAL_VPS, &params.vps),
+            (HEVC_NAL_SPS, &params.sps),
+            (HEVC_NAL_PPS, &params.pps),
+        ];
+        out.push(arrays.len() as u8);
+        for (nal_type, nal) in arrays {
+            out.push(0x80 | nal_type); // array_completeness
+            put_u16(out, 1);
+            put_u16(out, nal.len() as u16);
+            out.extend_from_slice(nal);
+        }
+    });
+}
+
+/// `moof` + `mdat` for the given samples. Sample durations come from the
+/// next sample's decode time, or `end_dts` for the last one.
+pub fn fragment(sequence: u32, base_decode_time: u64, samples: &[Sample], end_dts: u64) -> Vec<u8> {
+    let mdat_len: usize = samples.iter().map(|s| s.data.len()).sum();
+    let mut out = Vec::with_capacity(mdat_len + 64 + samples.len() * 16);
+    let mut data_offset_pos = 0;
+
+    write_box(&mut out, b"moof", |out| {
+        write_full_box(out, b"mfhd", 0, 0, |out| put_u32(out, sequence));
+        write_box(out, b"traf", |out| {
+            // default-base-is-moof
+            write_full_box(out, b"tfhd", 0, 0x02_0000, |out| put_u32(out, TRACK_ID));
+            write_full_box(out, b"tfdt", 1, 0, |out| {
+                out.extend_from_slice(&base_decode_time.to_be_bytes());
+            });
+            // data-offset, sample duration, size, flags and composition offset present
+            write_full_box(out, b"trun", 1, 0x000F01, |out| {
+                put_u32(out, samples.len() as u32);
+                data_offset_pos = out.len();
+                put_u32(out, 0);
+                for (i, sample) in samples.iter().enumerate() {
+                    let next_dts = samples.get(i + 1).map(|s| s.dts).unwrap_or(end_dts);
+                    let duration = next_dts.saturating_sub(sample.dts).max(1);
+                    put_u32(out, duration as u32);
+                    put_u32(out, sample.data.len() as u32);
+                    put_u32(
+                        out,
+                        if sample.keyframe {
+                            SAMPLE_FLAGS_SYNC
+                        } else {
+                            SAMPLE_FLAGS_NON_SYNC
+                        },
+                    );
+                    out.extend_from_slice(&sample.cts_offset.to_be_bytes());
+                }
+            });
+        });
+    });
+
+    let data_offset = (out.len() + 8) as u32;
+    out[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+
+    write_box(&mut out, b"mdat", |out| {
+        for sample in samples {
+            out.extend_from_slice(&sample.data);
+        }
+    });
+    out
+}
+
+/// Convert a wall-clock nanosecond timestamp to 90 kHz ticks
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    (ns as u128 * PTS_CLOCK_HZ as u128 / 1_000_000_000) as u64
+}
+
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    (ticks as u128 * 1_000_000_000 / PTS_CLOCK_HZ as u128) as u64
+}
+
+/// Parameter sets carried in a GOP segment
+pub fn segment_params(segment: &GopSegment) -> Option<VideoParams> {
+    demux(&segment.data).params
+}
+
+/// Init segment built from the parameter sets in a GOP segment
+pub fn segment_init(segment: &GopSegment) -> Option<Vec<u8>> {
+    segment_params(segment).as_ref().map(init_segment)
+}
+
+/// One CMAF fragment for a GOP segment, placed on the wall-clock timeline
+pub fn segment_fragment(segment: &GopSegment, sequence: u32) -> Option<Vec<u8>> {
+    let demuxed = demux(&segment.data);
+    if demuxed.samples.is_empty() {
+        return None;
+    }
+    let end_dts = ns_to_ticks(segment.duration_ns).max(demuxed.end_dts());
+    Some(fragment(
+        sequence,
+        ns_to_ticks(segment.start_pts),
+        &demuxed.samples,
+        end_dts,
+    ))
+}
+
+/// A complete fragmented MP4 file with one fragment per GOP segment,
+/// starting at time zero. `None` without parameter sets, or when they change
+/// within the segments: one sample entry can't describe both.
+pub fn remux_segments(segments: &[GopSegment]) -> Option<Vec<u8>> {
+    let first_pts = segments.first()?.start_pts;
+    let mut params: Option<VideoParams> = None;
+    let mut fragments = Vec::new();
+    for segment in segments {
+        let mut demuxed = demux(&segment.data);
+        match (&params, demuxed.params.take()) {
+            (None, found) => params = found,
+            (Some(current), Some(found)) if *current != found => return None,
+            _ => {}
+        }
+        if demuxed.samples.is_empty() {
+            continue;
+        }
+        let end_dts = ns_to_ticks(segment.duration_ns).max(demuxed.end_dts());
+        fragments.push(fragment(
+            fragments.len() as u32 + 1,
+            ns_to_ticks(segment.start_pts.saturating_sub(first_pts)),
+            &demuxed.samples,
+            end_dts,
+        ));
+    }
+
+    let mut out = init_segment(params.as_ref()?);
+    for f in fragments {
+        out.extend_from_slice(&f);
+    }
+    Some(out)
+}
+
+/// Remux a stored MPEG-TS file into a fragmented MP4 with one fragment per GOP
+pub fn remux_ts(data: &[u8]) -> Option<Vec<u8>> {
+    let demuxed = demux(data);
+    let mut out = init_segment(demuxed.params.as_ref()?);
+    for (n, gop) in demuxed.into_gops(0).iter().enumerate() {
+        out.extend_from_slice(&fragment(
+            n as u32 + 1,
+            gop.samples[0].dts,
+            &gop.samples,
+            gop.end_dts,
+        ));
+    }
+    Some(out)
+}
+
+/// Read samples back from a fragmented MP4 file written by this module
+pub fn demux_mp4(data: &[u8]) -> Demuxed {
+    let mut out = Demuxed::default();
+    let mut first_base = None;
+    for (kind, offset, body) in child_boxes(data) {
+        match &kind {
+            b"moov" => out.params = read_sample_entry(body),
+            b"moof" => {
+                let Some(traf) = find_child(body, b"traf") else {
+                    continue;
+                };
+                let base = find_child(traf, b"tfdt").and_then(read_tfdt).unwrap_or(0);
+                let origin = *first_base.get_or_insert(base);
+                if let Some(trun) = find_child(traf, b"trun") {
+                    read_trun(trun, data, offset, base - origin, &mut out.samples);
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// `(type, offset of the box header, body)` of each box in `data`
+fn child_boxes(data: &[u8]) -> Vec<([u8; 4], usize, &[u8])> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let size = size as usize;
+        if size < 8 || pos + size > data.len() {
+            break;
+        }
+        let kind = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        boxes.push((kind, pos, &data[pos + 8..pos + size]));
+        pos += size;
+    }
+    boxes
+}
+
+fn find_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    child_boxes(data)
+        .into_iter()
+        .find(|(k, _, _)| k == kind)
+        .map(|(_, _, body)| body)
+}
+
+fn read_sample_entry(moov: &[u8]) -> Option<VideoParams> {
+    let mut body = moov;
+    for kind in [b"trak", b"mdia", b"minf", b"stbl", b"stsd"] {
+        body = find_child(body, kind)?;
+    }
+    // stsd: version/flags + entry count; sample entry: 78 bytes of visual sample entry
+    let entries = body.get(8..)?;
+    if let Some(avc1) = find_child(entries, b"avc1") {
+        parse_avcc(find_child(avc1.get(78..)?, b"avcC")?)
+    } else {
+        let hvc1 = find_child(entries, b"hvc1")?;
+        parse_hvcc(find_child(hvc1.get(78..)?, b"hvcC")?)
+    }
+}
+
+fn parse_avcc(avcc: &[u8]) -> Option<VideoParams> {
+    let sps_len = u16::from_be_bytes([*avcc.get(6)?, *avcc.get(7)?]) as usize;
+    let sps = avcc.get(8..8 + sps_len)?;
+    let pps_at = 8 + sps_len + 1;
+    let pps_len = u16::from_be_bytes([*avcc.get(pps_at)?, *avcc.get(pps_at + 1)?]) as usize;
+    let pps = avcc.get(pps_at + 2..pps_at + 2 + pps_len)?;
+    VideoParams::new(VideoCodec::H264, &[], sps, pps)
+}
+
+fn parse_hvcc(hvcc: &[u8]) -> Option<VideoParams> {
+    let (mut vps, mut sps, mut pps): (&[u8], &[u8], &[u8]) = (&[], &[], &[]);
+    let mut at = 23;
+    for _ in 0..*hvcc.get(22)? {
+        let nal_type = hvcc.get(at)? & 0x3F;
+        let count = u16::from_be_bytes([*hvcc.get(at + 1)?, *hvcc.get(at + 2)?]);
+        at += 3;
+        for _ in 0..count {
+            let len = u16::from_be_bytes([*hvcc.get(at)?, *hvcc.get(at + 1)?]) as usize;
+            let nal = hvcc.get(at + 2..at + 2 + len)?;
+            match nal_type {
+                HEVC_NAL_VPS => vps = nal,
+                HEVC_NAL_SPS => sps = nal,
+                HEVC_NAL_PPS => pps = nal,
+                _ => {}
+            }
+            at += 2 + len;
+        }
+    }
+    VideoParams::new(VideoCodec::Hevc, vps, sps, pps)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_tfdt(tfdt: &[u8]) -> Option<u64> {
+    match tfdt.first()? {
+        1 => Some(u64::from_be_bytes(tfdt.get(4..12)?.try_into().ok()?)),
+        _ => read_u32(tfdt, 4).map(u64::from),
+    }
+}
+
+fn read_trun(trun: &[u8], file: &[u8], moof_offset: usize, base: u64, out: &mut Vec<Sample>) {
+    let Some(flags) = read_u32(trun, 0).map(|v| v & 0x00FF_FFFF) else {
+        return;
+    };
+    let Some(count) = read_u32(trun, 4) else {
+        return;
+    };
+    let mut at = 8;
+    let mut data_pos = moof_offset;
+    if flags & 0x01 != 0 {
+        let Some(offset) = read_u32(trun, at) else {
+            return;
+        };
+        data_pos = (moof_offset as i64 + offset as i32 as i64) as usize;
+        at += 4;
+    }
+    if flags & 0x04 != 0 {
+        at += 4;
+    }
+
+    let mut dts = base;
+    for i in 0..count {
+        let mut field = |present: u32| -> Option<u32> {
+            if flags & present == 0 {
+                return Some(0);
+            }
+            let v = read_u32(trun, at)?;
+            at += 4;
+            Some(v)
+        };
+        let (Some(duration), Some(size), Some(sample_flags), Some(cts)) =
+            (field(0x100), field(0x200), field(0x400), field(0x800))
+        else {
+            return;
+        };
+        let Some(data) = file.get(data_pos..data_pos + size as usize) else {
+            return;
+        };
+        out.push(Sample {
+            dts,
+            cts_offset: cts as i32,
+            keyframe: if flags & 0x400 != 0 {
+                sample_flags & 0x0001_0000 == 0
+            } else {
+                i == 0
+            },
+            data: data.to_vec(),
+        });
+        dts += duration as u64;
+        data_pos += size as usize;
+    }
+}
+
+/// Length of the `ftyp` + `moov` prefix of a fragmented MP4 file
+pub fn init_len(data: &[u8]) -> Option<usize> {
+    child_boxes(data)
+        .into_iter()
+        .find(|(kind, _, _)| kind == b"moov")
+        .map(|(_, offset, body)| offset + 8 + body.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ts_mux::TsMuxer;
+
+    // 1920x1080 High profile SPS with frame cropping
+    const SPS_1080P: [u8; 26] = [
+        0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0x84, 0x00, 0x00, 0x03,
+        0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x60, 0xC6, 0x58,
+    ];
+
+    fn sample(dts: u64, keyframe: bool) -> Sample {
+        Sample {
+            dts,
+            cts_offset: 3000,
+            keyframe,
+            data: vec![0, 0, 0, 1, if keyframe { 0x65 } else { 0x41 }],
+        }
+    }
+
+    #[test]
+    fn test_parse_sps_dimensions() {
+        assert_eq!(parse_sps_dimensions(&SPS_1080P), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_parse_hevc_sps() {
+        // 1920x1080 Main profile, level 4
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xA0, 0x03, 0xC0, 0x80, 0x10, 0xE5, 0x96, 0x66, 0x69, 0x24,
+            0xCA, 0xE0, 0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xE0, 0x80,
+        ];
+        let info = parse_hevc_sps(&sps).unwrap();
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!(info.codec_string(), "hvc1.1.6.L120.90");
+
+        let params = VideoParams::new(
+            VideoCodec::Hevc,
+            &[0x40, 0x01, 0x0C],
+            &sps,
+            &[0x44, 0x01, 0xC1],
+        )
+        .unwrap();
+        let demuxed = demux_mp4(&init_segment(&params));
+        assert_eq!(demuxed.params, Some(params));
+    }
+
+    #[test]
+    fn test_demux_mp4_round_trip() {
+        let params = VideoParams::new(
+            VideoCodec::H264,
+            &[],
+            &SPS_1080P,
+            &[0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0],
+        )
+        .unwrap();
+        let mut file = init_segment(&params);
+        file.extend(fragment(
+            1,
+            0,
+            &[sample(0, true), sample(3000, false)],
+            6000,
+        ));
+        file.extend(fragment(2, 6000, &[sample(6000, true)], 9000));
+
+        let demuxed = demux_mp4(&file);
+        assert_eq!(demuxed.params, Some(params));
+        let dts: Vec<u64> = demuxed.samples.iter().map(|s| s.dts).collect();
+        assert_eq!(dts, [0, 3000, 6000]);
+
+        let gops = demuxed.into_gops(1_000_000_000);
+        assert_eq!(gops.len(), 2);
+        assert_eq!(gops[0].samples.len(), 2);
+        assert_eq!(gops[0].end_dts, 6000);
+        assert_eq!(gops[1].start_ns, 1_000_000_000 + 66_666_666);
+    }
+
+    #[test]
+    fn test_remux_segments_rejects_param_change() {
+        let segment = |start_pts: u64, pps: &[u8]| {
+            let mut muxer = TsMuxer::new(ts::STREAM_TYPE_H264);
+            let mut segment = GopSegment::new(start_pts);
+            let au = [
+                &[0, 0, 0, 1][..],
+                &SPS_1080P,
+                &[0, 0, 0, 1],
+                pps,
+                &[0, 0, 0, 1, 0x65, 0x88],
+            ]
+            .concat();
+            muxer.write_access_unit(0, true, &au, &mut segment.data);
+            segment.duration_ns = 1_000_000_000;
+            segment
+        };
+        let pps = [0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0];
+
+        // A later segment starting earlier, as after a clock re-anchor
+        let same = [segment(5_000_000_000, &pps), segment(4_000_000_000, &pps)];
+        assert!(remux_segments(&same).is_some());
+
+        let mixed = [
+            segment(0, &pps),
+            segment(1_000_000_000, &[0x68, 0xEE, 0x3C, 0x80]),
+        ];
+        assert!(remux_segments(&mixed).is_none());
+    }
+
+    #[test]
+    fn test_fragment_data_offset_points_at_mdat_payload() {
+        let out = fragment(1, 0, &[sample(0, true), sample(3000, false)], 6000);
+        let moof_len = u32::from_be_bytes(out[0..4].try_into().unwrap()) as usize;
+        assert_eq!(&out[moof_len + 4..moof_len + 8], b"mdat");
+        assert_eq!(&out[moof_len + 8..], &[0, 0, 0, 1, 0x65, 0, 0, 0, 1, 0x41]);
+    }
+}
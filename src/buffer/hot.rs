@@ -109,6 +109,23 @@ impl HotBuffer {
         self.first_sequence + self.segments.len() as u64
     }
 
+    /// Stream time just past the newest segment
+    pub fn end_pts(&self) -> Option<u64> {
+        self.segments.back().map(|s| s.start_pts + s.duration_ns)
+    }
+
+    /// Segments overlapping the stream time range `[start_ns, end_ns)`, with
+    /// their sequence numbers
+    pub fn segments_in_range(
+        &self,
+        start_ns: u64,
+        end_ns: u64,
+    ) -> impl Iterator<Item = (u64, &GopSegment)> + '_ {
+        (self.first_sequence..)
+            .zip(self.segments.iter())
+            .filter(move |(_, s)| s.start_pts < end_ns && s.start_pts + s.duration_ns > start_ns)
+    }
+
     /// Get total duration of all segments in nanoseconds
     pub fn total_duration_ns(&self) -> u64 {
         self.segments.iter().map(|s| s.duration_ns).sum()
@@ -133,3 +150,33 @@ impl HotBuffer {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_pts: u64, duration_ns: u64) -> GopSegment {
+        let mut segment = GopSegment::new(start_pts);
+        segment.finalize(start_pts + duration_ns);
+        segment
+    }
+
+    #[test]
+    fn test_segments_in_range() {
+        let buffer = HotBuffer::new("test".to_string(), 600);
+        let mut buf = buffer.write().unwrap();
+        for i in 0..4 {
+            buf.push(segment(10_000 + i * 2_000, 2_000));
+        }
+
+        let seqs = |start, end| -> Vec<u64> {
+            buf.segments_in_range(start, end)
+                .map(|(seq, _)| seq)
+                .collect()
+        };
+        assert_eq!(seqs(11_000, 13_500), vec![0, 1]);
+        assert_eq!(seqs(12_000, 14_000), vec![1]);
+        assert_eq!(seqs(0, 10_000), Vec::<u64>::new());
+        assert_eq!(buf.end_pts(), Some(18_000));
+    }
+}
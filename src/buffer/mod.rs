@@ -1,5 +1,11 @@
+pub mod cold;
+pub mod continuous;
+pub mod fmp4;
 mod hot;
+pub mod nal;
 mod segment;
+pub mod ts;
+pub mod ts_mux;
 pub mod warm;
 
 pub use hot::{EvictedSegment, HotBuffer};
@@ -0,0 +1,134 @@
+//! H.264 and H.265 NAL unit helpers shared by the segmenter and the fMP4 remuxer
+
+use super::ts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+}
+
+/// What a NAL unit means to the remuxer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NalKind {
+    Vps,
+    Sps,
+    Pps,
+    Delimiter,
+    /// IDR slice (H.264) or IRAP picture (HEVC)
+    Keyframe,
+    /// Any other coded slice
+    Slice,
+    Other,
+}
+
+impl VideoCodec {
+    pub fn from_stream_type(stream_type: u8) -> Option<Self> {
+        match stream_type {
+            ts::STREAM_TYPE_H264 => Some(Self::H264),
+            ts::STREAM_TYPE_HEVC => Some(Self::Hevc),
+            _ => None,
+        }
+    }
+
+    pub fn kind(self, nal: &[u8]) -> NalKind {
+        let Some(&header) = nal.first() else {
+            return NalKind::Other;
+        };
+        match self {
+            Self::H264 => match header & 0x1F {
+                5 => NalKind::Keyframe,
+                1..=4 => NalKind::Slice,
+                7 => NalKind::Sps,
+                8 => NalKind::Pps,
+                9 => NalKind::Delimiter,
+                _ => NalKind::Other,
+            },
+            Self::Hevc => match (header >> 1) & 0x3F {
+                16..=23 => NalKind::Keyframe,
+                0..=15 | 24..=31 => NalKind::Slice,
+                32 => NalKind::Vps,
+                33 => NalKind::Sps,
+                34 => NalKind::Pps,
+                35 => NalKind::Delimiter,
+                _ => NalKind::Other,
+            },
+        }
+    }
+
+    /// Whether an access unit starts a GOP, judged by its first coded slice.
+    /// `None` until `data` contains a slice header.
+    pub fn access_unit_is_keyframe(self, data: &[u8]) -> Option<bool> {
+        split_annex_b(data)
+            .into_iter()
+            .find_map(|nal| match self.kind(nal) {
+                NalKind::Keyframe => Some(true),
+                NalKind::Slice => Some(false),
+                _ => None,
+            })
+    }
+}
+
+/// Split an Annex B byte stream on 3- and 4-byte start codes
+pub fn split_annex_b(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(s) = start {
+                let mut end = i;
+                while end > s && data[end - 1] == 0 {
+                    end -= 1;
+                }
+                nals.push(&data[s..end]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        if s < data.len() {
+            nals.push(&data[s..]);
+        }
+    }
+    nals.retain(|nal| !nal.is_empty());
+    nals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_annex_b() {
+        let data = [
+            0, 0, 0, 1, 0x09, 0xF0, 0, 0, 1, 0x65, 0xAA, 0, 0, 0, 1, 0x41, 0xBB,
+        ];
+        let nals = split_annex_b(&data);
+        assert_eq!(nals, vec![&[0x09, 0xF0][..], &[0x65, 0xAA], &[0x41, 0xBB]]);
+    }
+
+    #[test]
+    fn test_access_unit_is_keyframe() {
+        // AUD, VPS, then a CRA picture
+        let cra = [
+            0, 0, 0, 1, 0x46, 0x01, 0x50, 0, 0, 1, 0x40, 0x01, 0x0C, 0, 0, 1, 0x2A, 0x01,
+        ];
+        assert_eq!(VideoCodec::Hevc.access_unit_is_keyframe(&cra), Some(true));
+        // TRAIL_R slice
+        let trail = [0, 0, 0, 1, 0x02, 0x01, 0xD0];
+        assert_eq!(
+            VideoCodec::Hevc.access_unit_is_keyframe(&trail),
+            Some(false)
+        );
+        // Only parameter sets so far
+        assert_eq!(VideoCodec::Hevc.access_unit_is_keyframe(&cra[..13]), None);
+        assert_eq!(
+            VideoCodec::H264.access_unit_is_keyframe(&[0, 0, 1, 0x65, 0x88]),
+            Some(true)
+        );
+    }
+}
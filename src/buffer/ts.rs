@@ -0,0 +1,202 @@
+//! Minimal MPEG-TS packet and PES header helpers
+
+pub const PACKET_SIZE: usize = 188;
+pub const SYNC_BYTE: u8 = 0x47;
+
+/// 90 kHz PES timestamp clock
+pub const PTS_CLOCK_HZ: u64 = 90_000;
+/// PTS/DTS are 33-bit counters
+pub const PTS_WRAP: u64 = 1 << 33;
+
+pub const STREAM_TYPE_H264: u8 = 0x1B;
+pub const STREAM_TYPE_HEVC: u8 = 0x24;
+
+pub fn pid(packet: &[u8]) -> u16 {
+    ((packet[1] as u16 & 0x1F) << 8) | packet[2] as u16
+}
+
+pub fn payload_unit_start(packet: &[u8]) -> bool {
+    (packet[1] & 0x40) != 0
+}
+
+/// Offset of the payload within the packet, if the packet carries one
+pub fn payload_offset(packet: &[u8]) -> Option<usize> {
+    let offset = match (packet[3] >> 4) & 0x03 {
+        0x01 => 4,
+        0x03 => 5 + packet[4] as usize,
+        _ => return None,
+    };
+    if offset < PACKET_SIZE {
+        Some(offset)
+    } else {
+        None
+    }
+}
+
+/// Whether the adaptation field flags the packet as a random access point,
+/// which muxers set on the first packet of a keyframe
+pub fn random_access(packet: &[u8]) -> bool {
+    let has_adaptation = (packet[3] & 0x20) != 0;
+    let adaptation_len = packet[4] as usize;
+    has_adaptation && adaptation_len > 0 && adaptation_len < 184 && (packet[5] & 0x40) != 0
+}
+
+/// PTS and DTS from a PES header starting in this packet. DTS equals PTS
+/// when the header carries no separate decode timestamp.
+pub fn pes_timestamps(packet: &[u8]) -> Option<(u64, u64)> {
+    if !payload_unit_start(packet) {
+        return None;
+    }
+    let start = payload_offset(packet)?;
+    let pes = &packet[start..];
+    if pes.len() < 14 || pes[0] != 0x00 || pes[1] != 0x00 || pes[2] != 0x01 {
+        return None;
+    }
+
+    let pts_dts_flags = pes[7] >> 6;
+    match pts_dts_flags {
+        0x02 => {
+            let pts = read_timestamp(&pes[9..14]);
+            Some((pts, pts))
+        }
+        0x03 if pes.len() >= 19 => {
+            let pts = read_timestamp(&pes[9..14]);
+            let dts = read_timestamp(&pes[14..19]);
+            Some((pts, dts))
+        }
+        _ => None,
+    }
+}
+
+/// Elementary stream data of a reassembled PES packet
+pub fn pes_payload(pes: &[u8]) -> Option<&[u8]> {
+    if pes.len() < 9 || pes[0] != 0x00 || pes[1] != 0x00 || pes[2] != 0x01 {
+        return None;
+    }
+    pes.get(9 + pes[8] as usize..)
+}
+
+/// PSI section (PAT/PMT) starting in this packet, after the pointer field
+pub fn psi_section(packet: &[u8]) -> Option<&[u8]> {
+    if !payload_unit_start(packet) {
+        return None;
+    }
+    let offset = payload_offset(packet)?;
+    packet.get(offset + 1 + packet[offset] as usize..)
+}
+
+/// Section bytes up to but excluding the CRC
+fn section_body(section: &[u8]) -> Option<&[u8]> {
+    let section_len = ((*section.get(1)? as usize & 0x0F) << 8) | *section.get(2)? as usize;
+    let end = (3 + section_len).saturating_sub(4).min(section.len());
+    section.get(..end)
+}
+
+/// PMT PID of the first program listed in a PAT section
+pub fn pat_pmt_pid(section: &[u8]) -> Option<u16> {
+    let body = section_body(section)?;
+    body.get(8..)?
+        .chunks_exact(4)
+        .find(|entry| entry[0] != 0 || entry[1] != 0)
+        .map(|entry| ((entry[2] as u16 & 0x1F) << 8) | entry[3] as u16)
+}
+
+/// `(stream_type, pid)` of every elementary stream in a PMT section
+pub fn pmt_streams(section: &[u8]) -> Vec<(u8, u16)> {
+    let mut streams = Vec::new();
+    let Some(body) = section_body(section) else {
+        return streams;
+    };
+    if body.len() < 12 {
+        return streams;
+    }
+    let program_info_len = ((body[10] as usize & 0x0F) << 8) | body[11] as usize;
+    let mut pos = 12 + program_info_len;
+    while pos + 5 <= body.len() {
+        let stream_type = body[pos];
+        let pid = ((body[pos + 1] as u16 & 0x1F) << 8) | body[pos + 2] as u16;
+        let es_info_len = ((body[pos + 3] as usize & 0x0F) << 8) | body[pos + 4] as usize;
+        streams.push((stream_type, pid));
+        pos += 5 + es_info_len;
+    }
+    streams
+}
+
+fn read_timestamp(b: &[u8]) -> u64 {
+    (((b[0] as u64 >> 1) & 0x07) << 30)
+        | ((b[1] as u64) << 22)
+        | ((b[2] as u64 >> 1) << 15)
+        | ((b[3] as u64) << 7)
+        | (b[4] as u64 >> 1)
+}
+
+/// Forward distance between two 33-bit timestamps, accounting for wraparound
+pub fn pts_diff(from: u64, to: u64) -> u64 {
+    (to + PTS_WRAP - from) % PTS_WRAP
+}
+
+pub fn pts_to_ns(ticks: u64) -> u64 {
+    ticks * 1_000_000_000 / PTS_CLOCK_HZ
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_timestamp(marker: u8, ts: u64) -> [u8; 5] {
+        [
+            (marker << 4) | (((ts >> 30) as u8 & 0x07) << 1) | 1,
+            (ts >> 22) as u8,
+            (((ts >> 15) as u8) << 1) | 1,
+            (ts >> 7) as u8,
+            ((ts as u8) << 1) | 1,
+        ]
+    }
+
+    fn pes_packet(pts: u64, dts: Option<u64>) -> [u8; PACKET_SIZE] {
+        let mut packet = [0xFFu8; PACKET_SIZE];
+        packet[0] = SYNC_BYTE;
+        packet[1] = 0x40 | 0x01;
+        packet[2] = 0x00;
+        packet[3] = 0x10;
+        let pes = &mut packet[4..];
+        pes[..4].copy_from_slice(&[0x00, 0x00, 0x01, 0xE0]);
+        pes[4] = 0;
+        pes[5] = 0;
+        pes[6] = 0x80;
+        match dts {
+            Some(dts) => {
+                pes[7] = 0xC0;
+                pes[8] = 10;
+                pes[9..14].copy_from_slice(&encode_timestamp(0x3, pts));
+                pes[14..19].copy_from_slice(&encode_timestamp(0x1, dts));
+            }
+            None => {
+                pes[7] = 0x80;
+                pes[8] = 5;
+                pes[9..14].copy_from_slice(&encode_timestamp(0x2, pts));
+            }
+        }
+        packet
+    }
+
+    #[test]
+    fn test_pes_timestamps() {
+        let packet = pes_packet(8_589_934_000, None);
+        assert_eq!(pid(&packet), 0x100);
+        assert_eq!(
+            pes_timestamps(&packet),
+            Some((8_589_934_000, 8_589_934_000))
+        );
+
+        let packet = pes_packet(183_000, Some(180_000));
+        assert_eq!(pes_timestamps(&packet), Some((183_000, 180_000)));
+    }
+
+    #[test]
+    fn test_pts_diff_wraps() {
+        assert_eq!(pts_diff(100, 400), 300);
+        assert_eq!(pts_diff(PTS_WRAP - 100, 200), 300);
+        assert_eq!(pts_to_ns(90_000), 1_000_000_000);
+    }
+}
@@ -0,0 +1,211 @@
+//! Minimal single-program MPEG-TS muxer for one video elementary stream
+
+use super::ts::{PACKET_SIZE, PTS_WRAP, SYNC_BYTE};
+
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x0100;
+const VIDEO_STREAM_ID: u8 = 0xE0;
+const PAYLOAD_SIZE: usize = PACKET_SIZE - 4;
+
+/// Writes Annex B access units as PES packets. PAT and PMT are repeated in
+/// front of every keyframe and the first packet of a keyframe carries
+/// random_access_indicator, which is what `MpegTsSegmenter` splits on.
+pub struct TsMuxer {
+    stream_type: u8,
+    pat_cc: u8,
+    pmt_cc: u8,
+    video_cc: u8,
+    wrote_tables: bool,
+}
+
+impl TsMuxer {
+    pub fn new(stream_type: u8) -> Self {
+        Self {
+            stream_type,
+            pat_cc: 0,
+            pmt_cc: 0,
+            video_cc: 0,
+            wrote_tables: false,
+        }
+    }
+
+    /// Append the TS packets for one access unit with PTS `pts` (90 kHz)
+    pub fn write_access_unit(&mut self, pts: u64, keyframe: bool, data: &[u8], out: &mut Vec<u8>) {
+        if keyframe || !self.wrote_tables {
+            self.write_tables(out);
+        }
+
+        let pts = pts % PTS_WRAP;
+        let mut pes = Vec::with_capacity(data.len() + 14);
+        pes.extend_from_slice(&[0x00, 0x00, 0x01, VIDEO_STREAM_ID]);
+        pes.extend_from_slice(&[0x00, 0x00]); // unbounded length, allowed for video
+        pes.push(0x80); // marker bits
+        pes.push(0x80); // PTS only
+        pes.push(5);
+        pes.extend_from_slice(&encode_timestamp(0x2, pts));
+        pes.extend_from_slice(data);
+
+        // PCR on every access unit, with random_access_indicator on keyframes
+        let mut adaptation = vec![if keyframe { 0x50 } else { 0x10 }];
+        adaptation.extend_from_slice(&encode_pcr(pts));
+
+        let mut rest = &pes[..];
+        let mut first = true;
+        while !rest.is_empty() {
+            let af = if first {
+                std::mem::take(&mut adaptation)
+            } else {
+                Vec::new()
+            };
+            let taken = write_packet(out, VIDEO_PID, first, &mut self.video_cc, af, rest);
+            rest = &rest[taken..];
+            first = false;
+        }
+    }
+
+    fn write_tables(&mut self, out: &mut Vec<u8>) {
+        let mut pat = vec![0x00, 0xB0, 0x00, 0x00, 0x01, 0xC1, 0x00, 0x00];
+        pat.extend_from_slice(&[0x00, 0x01]);
+        pat.extend_from_slice(&(0xE000 | PMT_PID).to_be_bytes());
+        write_section(out, 0, &mut self.pat_cc, pat);
+
+        let mut pmt = vec![0x02, 0xB0, 0x00, 0x00, 0x01, 0xC1, 0x00, 0x00];
+        pmt.extend_from_slice(&(0xE000 | VIDEO_PID).to_be_bytes()); // PCR PID
+        pmt.extend_from_slice(&[0xF0, 0x00]);
+        pmt.push(self.stream_type);
+        pmt.extend_from_slice(&(0xE000 | VIDEO_PID).to_be_bytes());
+        pmt.extend_from_slice(&[0xF0, 0x00]);
+        write_section(out, PMT_PID, &mut self.pmt_cc, pmt);
+
+        self.wrote_tables = true;
+    }
+}
+
+/// Finish a PSI section (length and CRC) and write it in one packet
+fn write_section(out: &mut Vec<u8>, pid: u16, cc: &mut u8, mut section: Vec<u8>) {
+    let len = (section.len() - 3 + 4) as u16;
+    section[1] = 0xB0 | (len >> 8) as u8;
+    section[2] = len as u8;
+    let crc = crc32_mpeg2(&section);
+    section.extend_from_slice(&crc.to_be_bytes());
+
+    let mut payload = vec![0x00]; // pointer_field
+    payload.extend_from_slice(&section);
+    payload.resize(PAYLOAD_SIZE, 0xFF);
+    write_packet(out, pid, true, cc, Vec::new(), &payload);
+}
+
+/// Write one packet carrying as much of `payload` as fits after the
+/// adaptation field, stuffing the adaptation field when the payload runs
+/// short. Returns the number of payload bytes written.
+fn write_packet(
+    out: &mut Vec<u8>,
+    pid: u16,
+    unit_start: bool,
+    cc: &mut u8,
+    mut adaptation: Vec<u8>,
+    payload: &[u8],
+) -> usize {
+    let mut has_adaptation = !adaptation.is_empty();
+    let overhead = if has_adaptation {
+        1 + adaptation.len()
+    } else {
+        0
+    };
+    let room = PAYLOAD_SIZE - overhead;
+    let taken = payload.len().min(room);
+    let mut stuffing = room - taken;
+    if stuffing > 0 && !has_adaptation {
+        has_adaptation = true;
+        stuffing -= 1; // adaptation_field_length
+        if stuffing > 0 {
+            adaptation.push(0x00);
+            stuffing -= 1;
+        }
+    }
+    adaptation.resize(adaptation.len() + stuffing, 0xFF);
+
+    out.push(SYNC_BYTE);
+    out.push(((unit_start as u8) << 6) | ((pid >> 8) as u8 & 0x1F));
+    out.push(pid as u8);
+    out.push(if has_adaptation { 0x30 } else { 0x10 } | *cc);
+    *cc = (*cc + 1) & 0x0F;
+    if has_adaptation {
+        out.push(adaptation.len() as u8);
+        out.extend_from_slice(&adaptation);
+    }
+    out.extend_from_slice(&payload[..taken]);
+    taken
+}
+
+fn encode_timestamp(marker: u8, ts: u64) -> [u8; 5] {
+    [
+        (marker << 4) | (((ts >> 30) as u8 & 0x07) << 1) | 1,
+        (ts >> 22) as u8,
+        (((ts >> 15) as u8) << 1) | 1,
+        (ts >> 7) as u8,
+        ((ts as u8) << 1) | 1,
+    ]
+}
+
+/// 33-bit PCR base equal to the PTS, with a zero extension
+fn encode_pcr(base: u64) -> [u8; 6] {
+    [
+        (base >> 25) as u8,
+        (base >> 17) as u8,
+        (base >> 9) as u8,
+        (base >> 1) as u8,
+        ((base as u8 & 0x01) << 7) | 0x7E,
+        0x00,
+    ]
+}
+
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::{fmp4, ts};
+
+    #[test]
+    fn test_muxed_access_units_demux() {
+        let sps = [
+            0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0x84, 0x00, 0x00,
+            0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x60, 0xC6, 0x58,
+        ];
+        let mut idr = vec![0, 0, 0, 1];
+        idr.extend_from_slice(&sps);
+        idr.extend_from_slice(&[0, 0, 0, 1, 0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0]);
+        idr.extend_from_slice(&[0, 0, 0, 1, 0x65]);
+        idr.extend(std::iter::repeat_n(0xAB, 1000));
+
+        let mut muxer = TsMuxer::new(ts::STREAM_TYPE_H264);
+        let mut out = Vec::new();
+        muxer.write_access_unit(90_000, true, &idr, &mut out);
+        muxer.write_access_unit(93_000, false, &[0, 0, 0, 1, 0x41, 0x9A], &mut out);
+        assert_eq!(out.len() % PACKET_SIZE, 0);
+
+        let demuxed = fmp4::demux(&out);
+        assert_eq!(
+            demuxed.params.map(|p| (p.width, p.height)),
+            Some((1920, 1080))
+        );
+        assert_eq!(demuxed.samples.len(), 2);
+        assert!(demuxed.samples[0].keyframe);
+        assert_eq!(demuxed.samples[1].dts, 3000);
+        assert_eq!(demuxed.samples[0].data.len(), 4 + 1001);
+    }
+}
@@ -3,31 +3,79 @@ use std::path::PathBuf;
 
 use tokio::sync::mpsc;
 
-use super::GopSegment;
+use super::continuous::ContinuousRecorder;
+use super::{fmp4, GopSegment};
 use crate::buffer::EvictedSegment;
-use crate::storage::{DetectionStore, EventType, MotionStore, WarmEventEntry, WarmEventIndex};
+use crate::config::{RecordingMode, StorageFormat, WarmConfig};
+use crate::storage::atomic::write_atomic;
+use crate::storage::{
+    AlertRecord, AlertSnapshots, AlertStore, DetectionRecord, DetectionStore, EventMetadata,
+    EventMetadataStore, EventSidecar, EventType, MotionSample, MotionStore, SegmentInfo,
+    SegmentMotion, TrackPoint, TrackRecord, WarmEventEntry, WarmEventIndex,
+};
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 const NANOS_PER_MS: u64 = 1_000_000;
 
 struct WarmEvent {
     segments: Vec<GopSegment>,
+    segment_info: Vec<SegmentInfo>,
     first_pts: u64,
     last_motion_pts: u64,
     total_bytes: usize,
     has_objects: bool,
+    motion: Vec<MotionSample>,
+    detections: Vec<DetectionRecord>,
+    tracks: Vec<TrackRecord>,
+    alerts: Vec<AlertRecord>,
+    /// Alert store ids of `alerts`, to pick up end times set later
+    alert_ids: Vec<u64>,
+    /// Before and after views of tamper alerts, by index into `alerts`
+    snapshots: Vec<(usize, AlertSnapshots)>,
+    thumbnail: Option<Vec<u8>>,
 }
 
 impl WarmEvent {
     fn duration_ns(&self) -> u64 {
         self.segments.iter().map(|s| s.duration_ns).sum()
     }
+
+    fn offset_ms(&self, pts: u64) -> u64 {
+        pts.saturating_sub(self.first_pts) / NANOS_PER_MS
+    }
+
+    /// Tampering outranks rule hits, which outrank detected objects, which
+    /// outrank plain motion
+    fn event_type(&self) -> EventType {
+        if self.alerts.iter().any(|a| a.kind.is_tamper()) {
+            EventType::Tamper
+        } else if !self.alerts.is_empty() {
+            EventType::Rule
+        } else if self.has_objects {
+            EventType::Object
+        } else {
+            EventType::Movement
+        }
+    }
+
+    fn push(&mut self, segment: GopSegment, motion: Option<SegmentMotion>) {
+        self.segment_info.push(SegmentInfo {
+            start_pts_ns: segment.start_pts,
+            duration_ns: segment.duration_ns,
+            motion_score: motion.as_ref().map(|m| m.score),
+            motion_bbox: motion.as_ref().and_then(|m| m.bbox),
+            zones: motion.map(|m| m.zones).unwrap_or_default(),
+        });
+        self.total_bytes += segment.data.len();
+        self.segments.push(segment);
+    }
 }
 
 pub struct WarmWriter {
     receiver: mpsc::UnboundedReceiver<EvictedSegment>,
     motion_store: MotionStore,
     detection_store: DetectionStore,
+    alert_store: AlertStore,
     data_dir: PathBuf,
     camera_id: String,
     pre_padding_ns: u64,
@@ -36,6 +84,10 @@ pub struct WarmWriter {
     pre_buffer_duration_ns: u64,
     current_event: Option<WarmEvent>,
     warm_index: Option<WarmEventIndex>,
+    metadata_store: EventMetadataStore,
+    format: StorageFormat,
+    recording_mode: RecordingMode,
+    recorder: Option<ContinuousRecorder>,
 }
 
 impl WarmWriter {
@@ -44,35 +96,47 @@ impl WarmWriter {
         receiver: mpsc::UnboundedReceiver<EvictedSegment>,
         motion_store: MotionStore,
         detection_store: DetectionStore,
-        data_dir: PathBuf,
+        alert_store: AlertStore,
         camera_id: String,
-        pre_padding_secs: u64,
-        post_padding_secs: u64,
+        config: &WarmConfig,
         warm_index: Option<WarmEventIndex>,
+        metadata_store: EventMetadataStore,
+        recorder: Option<ContinuousRecorder>,
     ) -> Self {
         Self {
             receiver,
             motion_store,
             detection_store,
-            data_dir,
+            alert_store,
+            data_dir: PathBuf::from(&config.data_dir),
             camera_id,
-            pre_padding_ns: pre_padding_secs * NANOS_PER_SEC,
-            post_padding_ns: post_padding_secs * NANOS_PER_SEC,
+            pre_padding_ns: config.pre_padding_secs * NANOS_PER_SEC,
+            post_padding_ns: config.post_padding_secs * NANOS_PER_SEC,
             pre_buffer: VecDeque::new(),
             pre_buffer_duration_ns: 0,
             current_event: None,
             warm_index,
+            metadata_store,
+            format: config.format,
+            recording_mode: config.recording_mode,
+            recorder,
         }
     }
 
     pub async fn run(mut self) {
         while let Some(evicted) = self.receiver.recv().await {
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.push(&evicted.segment).await;
+            }
             self.process_segment(evicted);
         }
-        // Channel closed — finalize any pending event
+        // Channel closed — finalize any pending event and recording chunk
         if self.current_event.is_some() {
             self.finalize_event().await;
         }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.finish().await;
+        }
         tracing::debug!(camera = %self.camera_id, "warm writer shutting down");
     }
 
@@ -81,64 +145,156 @@ impl WarmWriter {
             .motion_store
             .has_motion(&evicted.camera_id, evicted.sequence);
         let segment = evicted.segment;
+        // Loitering can be confirmed on a still segment
+        let alerts = self
+            .alert_store
+            .get_for_sequence(&evicted.camera_id, evicted.sequence);
 
-        let has_objects = has_motion
+        let has_objects = (has_motion
             && self
                 .detection_store
-                .has_detections(&evicted.camera_id, evicted.sequence);
-
-        if has_motion {
-            if let Some(ref mut event) = self.current_event {
-                event.last_motion_pts = segment.start_pts;
-                event.total_bytes += segment.data.len();
-                if has_objects {
-                    event.has_objects = true;
-                }
-                event.segments.push(segment);
-            } else {
+                .has_detections(&evicted.camera_id, evicted.sequence))
+            || !alerts.is_empty();
+
+        if has_motion || !alerts.is_empty() {
+            if self.current_event.is_none() {
                 // Start new event — prepend pre-buffer
-                let mut segments: Vec<GopSegment> = self.pre_buffer.drain(..).collect();
+                let pre_segments: Vec<GopSegment> = self.pre_buffer.drain(..).collect();
                 self.pre_buffer_duration_ns = 0;
-                let first_pts = segments
+                let first_pts = pre_segments
                     .first()
                     .map(|s| s.start_pts)
                     .unwrap_or(segment.start_pts);
-                let total_bytes: usize =
-                    segments.iter().map(|s| s.data.len()).sum::<usize>() + segment.data.len();
-                let motion_pts = segment.start_pts;
-                segments.push(segment);
-                self.current_event = Some(WarmEvent {
-                    segments,
+                let mut event = WarmEvent {
+                    segments: Vec::new(),
+                    segment_info: Vec::new(),
                     first_pts,
-                    last_motion_pts: motion_pts,
-                    total_bytes,
-                    has_objects,
-                });
+                    last_motion_pts: segment.start_pts,
+                    total_bytes: 0,
+                    has_objects: false,
+                    motion: Vec::new(),
+                    detections: Vec::new(),
+                    tracks: Vec::new(),
+                    alerts: Vec::new(),
+                    alert_ids: Vec::new(),
+                    snapshots: Vec::new(),
+                    thumbnail: None,
+                };
+                for pre in pre_segments {
+                    event.push(pre, None);
+                }
+                self.current_event = Some(event);
             }
+
+            let motion = self
+                .motion_store
+                .segment_motion(&evicted.camera_id, evicted.sequence);
+            let score = motion.as_ref().map(|m| m.score).unwrap_or(0.0);
+            let zones = motion.as_ref().map(|m| m.zones.clone()).unwrap_or_default();
+            let detections = if has_objects {
+                self.detection_store
+                    .get_for_sequence(&evicted.camera_id, evicted.sequence)
+            } else {
+                Vec::new()
+            };
+            let needs_thumbnail = self
+                .current_event
+                .as_ref()
+                .is_some_and(|e| e.thumbnail.is_none());
+            // A loitering frame shows what the event is about best
+            let thumbnail = match (
+                alerts.iter().find_map(|a| a.frame_jpeg.clone()),
+                detections.first(),
+            ) {
+                (Some(frame), _) => Some(frame),
+                (None, Some(det)) if needs_thumbnail => {
+                    self.detection_store.get_frame(&evicted.camera_id, det.id)
+                }
+                _ => None,
+            };
+
+            let event = self.current_event.as_mut().unwrap();
+            let offset_ms = event.offset_ms(segment.start_pts);
+            event.motion.push(MotionSample {
+                offset_ms,
+                duration_ms: segment.duration_ns / NANOS_PER_MS,
+                score,
+                zones,
+            });
+            event
+                .detections
+                .extend(detections.into_iter().map(|d| DetectionRecord {
+                    offset_ms,
+                    object_class: d.object_class,
+                    confidence: d.confidence,
+                    bbox: d.bbox,
+                }));
+            event.alert_ids.extend(alerts.iter().map(|a| a.id));
+            let first_index = event.alerts.len();
+            event
+                .snapshots
+                .extend(alerts.iter().enumerate().filter_map(|(i, a)| {
+                    Some((
+                        first_index + i,
+                        AlertSnapshots {
+                            before: a.before_jpeg.clone()?,
+                            after: a.frame_jpeg.clone()?,
+                        },
+                    ))
+                }));
+            let alerts: Vec<AlertRecord> = alerts
+                .into_iter()
+                .map(|a| AlertRecord {
+                    offset_ms: event.offset_ms(a.time_ns),
+                    end_offset_ms: a.end_ns.map(|end| event.offset_ms(end)),
+                    kind: a.kind,
+                    rule: a.rule,
+                    track_id: a.track_id,
+                    object_class: a.object_class,
+                    has_snapshots: false,
+                })
+                .collect();
+            event.alerts.extend(alerts);
+            if thumbnail.is_some() {
+                event.thumbnail = thumbnail;
+            }
+            event.last_motion_pts = segment.start_pts;
+            if has_objects {
+                event.has_objects = true;
+            }
+            event.push(segment, motion);
         } else if let Some(ref mut event) = self.current_event {
             let elapsed_since_motion = segment.start_pts.saturating_sub(event.last_motion_pts);
             if elapsed_since_motion <= self.post_padding_ns {
-                event.total_bytes += segment.data.len();
-                event.segments.push(segment);
+                event.push(segment, None);
             } else {
                 // Post-padding expired — finalize via spawn
                 let mut event = self.current_event.take().unwrap();
+                // This non-motion segment goes into pre-buffer for next event
+                self.push_pre_buffer(segment);
+                if !self.keeps_event(&event) {
+                    return;
+                }
+                self.attach_tracks(&mut event);
+                self.refresh_alert_ends(&mut event);
                 let data_dir = self.data_dir.clone();
                 let camera_id = self.camera_id.clone();
-                let has_objects = event.has_objects;
+                let event_type = event.event_type();
                 let warm_index = self.warm_index.clone();
+                let metadata_store = self.metadata_store.clone();
+                let format = self.format;
                 tokio::spawn(async move {
                     write_event(
                         &data_dir,
                         &camera_id,
                         &mut event,
-                        has_objects,
+                        event_type,
                         warm_index.as_ref(),
+                        &metadata_store,
+                        format,
                     )
                     .await;
                 });
-                // This non-motion segment goes into pre-buffer for next event
-                self.push_pre_buffer(segment);
             }
         } else {
             self.push_pre_buffer(segment);
@@ -158,15 +314,69 @@ impl WarmWriter {
         }
     }
 
+    /// Snapshot the tracks seen during the event, relative to its start
+    fn attach_tracks(&self, event: &mut WarmEvent) {
+        let end = event.first_pts + event.duration_ns();
+        event.tracks = self
+            .detection_store
+            .tracks_between(&self.camera_id, event.first_pts, end)
+            .into_iter()
+            .map(|t| TrackRecord {
+                id: t.id,
+                object_class: t.object_class,
+                confidence: t.confidence,
+                start_offset_ms: event.offset_ms(t.start_ns),
+                end_offset_ms: event.offset_ms(t.end_ns),
+                trajectory: t
+                    .trajectory
+                    .into_iter()
+                    .map(|(pts, bbox)| TrackPoint {
+                        offset_ms: event.offset_ms(pts),
+                        bbox,
+                    })
+                    .collect(),
+            })
+            .collect();
+    }
+
+    /// Pick up end times of lasting alerts that ended after they were added.
+    /// Ones still going on stay open-ended.
+    fn refresh_alert_ends(&self, event: &mut WarmEvent) {
+        for (record, &id) in event.alerts.iter_mut().zip(&event.alert_ids) {
+            if record.end_offset_ms.is_some() {
+                continue;
+            }
+            if let Some(end_ns) = self
+                .alert_store
+                .get(&self.camera_id, id)
+                .and_then(|a| a.end_ns)
+            {
+                record.end_offset_ms = Some(end_ns.saturating_sub(event.first_pts) / NANOS_PER_MS);
+            }
+        }
+    }
+
+    /// Objects-only mode drops events in which no object was detected
+    fn keeps_event(&self, event: &WarmEvent) -> bool {
+        self.recording_mode != RecordingMode::ObjectsOnly || event.has_objects
+    }
+
     async fn finalize_event(&mut self) {
         if let Some(ref mut event) = self.current_event.take() {
-            let has_objects = event.has_objects;
+            if !self.keeps_event(event) {
+                return;
+            }
+            self.attach_tracks(event);
+            self.refresh_alert_ends(event);
+            let event_type = event.event_type();
             write_event(
                 &self.data_dir,
                 &self.camera_id,
                 event,
-                has_objects,
+                event_type,
                 self.warm_index.as_ref(),
+                &self.metadata_store,
+                self.format,
             )
             .await;
         }
@@ -177,16 +387,17 @@ async fn write_event(
     data_dir: &std::path::Path,
     camera_id: &str,
     event: &mut WarmEvent,
-    has_objects: bool,
+    event_type: EventType,
     warm_index: Option<&WarmEventIndex>,
+    metadata_store: &EventMetadataStore,
+    format: StorageFormat,
 ) {
     let duration_ns = event.duration_ns();
     let duration_ms = duration_ns / NANOS_PER_MS;
     let segment_count = event.segments.len();
     let total_bytes = event.total_bytes;
 
-    let subdir = if has_objects { "objects" } else { "movements" };
-    let camera_dir = data_dir.join(camera_id).join(subdir);
+    let camera_dir = data_dir.join(camera_id).join(event_type.dir_name());
     if let Err(e) = tokio::fs::create_dir_all(&camera_dir).await {
         tracing::error!(
             camera = %camera_id,
@@ -196,17 +407,58 @@ async fn write_event(
         return;
     }
 
-    let filename = format!("{}_{}.ts", event.first_pts, duration_ms);
-    let file_path = camera_dir.join(&filename);
+    let remuxed = match format {
+        StorageFormat::Fmp4 => {
+            let remuxed = fmp4::remux_segments(&event.segments);
+            if remuxed.is_none() {
+                tracing::warn!(
+                    camera = %camera_id,
+                    "missing or changing parameter sets in event, storing as MPEG-TS"
+                );
+            }
+            remuxed
+        }
+        StorageFormat::Ts => None,
+    };
+    let (format, data) = match remuxed {
+        Some(data) => (StorageFormat::Fmp4, data),
+        None => {
+            let mut data = Vec::with_capacity(total_bytes);
+            for seg in &event.segments {
+                data.extend_from_slice(&seg.data);
+            }
+            (StorageFormat::Ts, data)
+        }
+    };
 
-    let mut data = Vec::with_capacity(total_bytes);
-    for seg in &event.segments {
-        data.extend_from_slice(&seg.data);
-    }
+    let filename = format!("{}_{}.{}", event.first_pts, duration_ms, format.extension());
+    let file_path = camera_dir.join(&filename);
 
     let file_size = data.len() as u64;
-    match tokio::fs::write(&file_path, &data).await {
+    match write_atomic(&file_path, &data).await {
         Ok(()) => {
+            let sidecar = EventSidecar {
+                camera_id: camera_id.to_string(),
+                start_pts_ns: event.first_pts,
+                duration_ms: duration_ms as u32,
+                segments: std::mem::take(&mut event.segment_info),
+                detections: event.detections.clone(),
+                tracks: event.tracks.clone(),
+                alerts: event.alerts.clone(),
+            };
+            let sidecar_path = file_path.with_extension("json");
+            let sidecar = match write_sidecar(&sidecar_path, &sidecar).await {
+                Ok(()) => Some(std::sync::Arc::new(sidecar)),
+                Err(e) => {
+                    tracing::warn!(
+                        camera = %camera_id,
+                        path = %sidecar_path.display(),
+                        error = %e,
+                        "failed to write event sidecar"
+                    );
+                    None
+                }
+            };
             tracing::info!(
                 camera = %camera_id,
                 path = %file_path.display(),
@@ -221,15 +473,29 @@ async fn write_event(
                     WarmEventEntry {
                         start_pts_ns: event.first_pts,
                         duration_ms: duration_ms as u32,
-                        event_type: if has_objects {
-                            EventType::Object
-                        } else {
-                            EventType::Movement
-                        },
+                        event_type,
                         file_size,
+                        format,
+                        sidecar,
                     },
                 );
             }
+            let metadata = EventMetadata {
+                start_pts_ns: event.first_pts,
+                motion: std::mem::take(&mut event.motion),
+                detections: std::mem::take(&mut event.detections),
+                tracks: std::mem::take(&mut event.tracks),
+                alerts: std::mem::take(&mut event.alerts),
+                has_thumbnail: false,
+            };
+            metadata_store
+                .record(
+                    camera_id,
+                    metadata,
+                    event.thumbnail.take(),
+                    std::mem::take(&mut event.snapshots),
+                )
+                .await;
         }
         Err(e) => {
             tracing::error!(
@@ -241,3 +507,11 @@ async fn write_event(
         }
     }
 }
+
+async fn write_sidecar(
+    path: &std::path::Path,
+    sidecar: &EventSidecar,
+) -> Result<(), std::io::Error> {
+    let json = serde_json::to_vec_pretty(sidecar)?;
+    write_atomic(path, &json).await
+}
@@ -0,0 +1,203 @@
+//! RTSP Basic and Digest (RFC 2617) authentication
+
+use super::base64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Challenge {
+    Basic,
+    Digest {
+        realm: String,
+        nonce: String,
+        qop: Option<String>,
+        opaque: Option<String>,
+    },
+}
+
+impl Challenge {
+    /// Parse the strongest scheme offered by a set of `WWW-Authenticate` headers
+    pub fn parse<'a>(headers: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut basic = false;
+        for header in headers {
+            let (scheme, params) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+            if scheme.eq_ignore_ascii_case("basic") {
+                basic = true;
+            } else if scheme.eq_ignore_ascii_case("digest") {
+                let param = |name: &str| auth_param(params, name);
+                return Some(Challenge::Digest {
+                    realm: param("realm")?,
+                    nonce: param("nonce")?,
+                    // Only "auth" is supported; "auth-int" servers also accept it
+                    qop: param("qop").map(|_| "auth".to_string()),
+                    opaque: param("opaque"),
+                });
+            }
+        }
+        basic.then_some(Challenge::Basic)
+    }
+
+    /// `Authorization` header value for one request
+    pub fn authorization(
+        &self,
+        method: &str,
+        uri: &str,
+        username: &str,
+        password: &str,
+        nonce_count: u32,
+    ) -> String {
+        match self {
+            Challenge::Basic => {
+                format!(
+                    "Basic {}",
+                    base64::encode(format!("{username}:{password}").as_bytes())
+                )
+            }
+            Challenge::Digest {
+                realm,
+                nonce,
+                qop,
+                opaque,
+            } => {
+                let ha1 = md5_hex(format!("{username}:{realm}:{password}").as_bytes());
+                let ha2 = md5_hex(format!("{method}:{uri}").as_bytes());
+                let mut header = format!(
+                    "Digest username=\"{username}\", realm=\"{realm}\", nonce=\"{nonce}\", uri=\"{uri}\""
+                );
+                let response = match qop {
+                    Some(qop) => {
+                        let nc = format!("{nonce_count:08x}");
+                        let cnonce = md5_hex(format!("{nonce}:{nonce_count}").as_bytes());
+                        header.push_str(&format!(
+                            ", qop={qop}, nc={nc}, cnonce=\"{}\"",
+                            &cnonce[..16]
+                        ));
+                        md5_hex(
+                            format!("{ha1}:{nonce}:{nc}:{}:{qop}:{ha2}", &cnonce[..16]).as_bytes(),
+                        )
+                    }
+                    None => md5_hex(format!("{ha1}:{nonce}:{ha2}").as_bytes()),
+                };
+                header.push_str(&format!(", response=\"{response}\""));
+                if let Some(opaque) = opaque {
+                    header.push_str(&format!(", opaque=\"{opaque}\""));
+                }
+                header
+            }
+        }
+    }
+}
+
+/// Value of `name=value` or `name="value"` in a comma-separated parameter list
+pub fn auth_param(params: &str, name: &str) -> Option<String> {
+    let mut rest = params;
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let key = key.trim().trim_start_matches(',').trim();
+        let after = after.trim_start();
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => match after.find(',') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            },
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value.trim().to_string());
+        }
+        rest = next.trim_start_matches([',', ' ']);
+    }
+    None
+}
+
+pub fn md5_hex(data: &[u8]) -> String {
+    md5(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// MD5 (RFC 1321). Only used for Digest auth, where it is mandated.
+fn md5(data: &[u8]) -> [u8; 16] {
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks_exact(64) {
+        let m: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut out = [0u8; 16];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            md5_hex(b"The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn test_digest_authorization() {
+        let challenge = Challenge::parse([
+            "Basic realm=\"cam\"",
+            "Digest realm=\"IP Camera\", nonce=\"abc123\", stale=\"FALSE\"",
+        ])
+        .unwrap();
+        let header = challenge.authorization("DESCRIBE", "rtsp://cam/stream", "admin", "secret", 1);
+        let ha1 = md5_hex(b"admin:IP Camera:secret");
+        let ha2 = md5_hex(b"DESCRIBE:rtsp://cam/stream");
+        let expected = md5_hex(format!("{ha1}:abc123:{ha2}").as_bytes());
+        assert_eq!(
+            auth_param(header.trim_start_matches("Digest "), "response"),
+            Some(expected)
+        );
+        assert_eq!(
+            auth_param(header.trim_start_matches("Digest "), "realm").as_deref(),
+            Some("IP Camera")
+        );
+    }
+}
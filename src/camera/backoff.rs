@@ -0,0 +1,75 @@
+//! Reconnect delays for a camera that keeps failing: doubling from an
+//! initial delay up to a cap, with jitter so cameras behind the same
+//! failed switch or NVR don't all retry in step.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    failures: u32,
+    /// Mixed into the jitter so cameras failing together spread out
+    seed: u64,
+}
+
+impl Backoff {
+    pub fn new(camera_id: &str, initial: Duration, max: Duration) -> Self {
+        let mut hasher = DefaultHasher::new();
+        camera_id.hash(&mut hasher);
+        Self {
+            initial,
+            max: max.max(initial),
+            failures: 0,
+            seed: hasher.finish(),
+        }
+    }
+
+    /// Delay before the next attempt: half the current step plus a random
+    /// part of the other half
+    pub fn next_delay(&mut self) -> Duration {
+        let step = self
+            .initial
+            .saturating_mul(1u32 << self.failures.min(16))
+            .min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        step / 2 + step.mul_f64(jitter(self.seed) / 2.0)
+    }
+
+    /// The camera delivered video again
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// Pseudo-random fraction in `[0, 1)` from the clock's nanoseconds, which
+/// is all the randomness a retry delay needs
+fn jitter(seed: u64) -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let mut hasher = DefaultHasher::new();
+    (seed, nanos).hash(&mut hasher);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let mut backoff = Backoff::new("cam", Duration::from_secs(2), Duration::from_secs(30));
+        let steps = [2, 4, 8, 16, 30, 30];
+        for step in steps {
+            let delay = backoff.next_delay();
+            let step = Duration::from_secs(step);
+            assert!(delay >= step / 2 && delay <= step, "{delay:?} for {step:?}");
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(2));
+    }
+}
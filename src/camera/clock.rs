@@ -0,0 +1,80 @@
+use std::time::SystemTime;
+
+use crate::buffer::ts::{self, PTS_WRAP};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// A jump larger than this (either way) is a discontinuity, not stream time
+const MAX_JUMP_NS: u64 = 60 * NANOS_PER_SEC;
+
+/// Maps 90 kHz stream timestamps onto wall-clock nanoseconds.
+///
+/// The first timestamp of a connection is anchored to `SystemTime::now()`;
+/// after that time only advances with the stream, so GOP durations are
+/// frame-accurate regardless of pipe buffering. 33-bit wraparound is
+/// unwrapped, and a discontinuity re-anchors to the wall clock.
+#[derive(Default)]
+pub struct StreamClock {
+    anchor_ns: u64,
+    last_ticks: Option<u64>,
+    elapsed_ns: u64,
+}
+
+impl StreamClock {
+    pub fn timestamp_ns(&mut self, ticks: u64) -> u64 {
+        let ticks = ticks % PTS_WRAP;
+        let Some(last) = self.last_ticks else {
+            self.anchor_ns = wall_clock_ns();
+            self.last_ticks = Some(ticks);
+            return self.anchor_ns;
+        };
+
+        let forward = ts::pts_diff(last, ticks);
+        let backward = ts::pts_diff(ticks, last);
+        if forward <= backward && ts::pts_to_ns(forward) <= MAX_JUMP_NS {
+            self.elapsed_ns += ts::pts_to_ns(forward);
+        } else if backward < forward && ts::pts_to_ns(backward) <= MAX_JUMP_NS {
+            // Reordered frame slightly in the past, e.g. B-frame PTS
+            return (self.anchor_ns + self.elapsed_ns).saturating_sub(ts::pts_to_ns(backward));
+        } else {
+            let current = self.anchor_ns + self.elapsed_ns;
+            tracing::warn!(
+                jump_ticks = forward.min(backward),
+                "stream timestamp discontinuity, re-anchoring to wall clock"
+            );
+            self.anchor_ns = wall_clock_ns().max(current);
+            self.elapsed_ns = 0;
+        }
+        self.last_ticks = Some(ticks);
+        self.anchor_ns + self.elapsed_ns
+    }
+}
+
+fn wall_clock_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_clock_follows_stream_time() {
+        let mut clock = StreamClock::default();
+        let start = clock.timestamp_ns(PTS_WRAP - 90_000);
+        // One second later, across the 33-bit wrap
+        assert_eq!(clock.timestamp_ns(0), start + NANOS_PER_SEC);
+        // Slightly earlier timestamps don't move the clock backwards for good
+        assert_eq!(
+            clock.timestamp_ns(PTS_WRAP - 45_000),
+            start + NANOS_PER_SEC / 2
+        );
+        assert_eq!(clock.timestamp_ns(180_000), start + 3 * NANOS_PER_SEC);
+        // A huge jump re-anchors instead of leaping hours ahead
+        let after_jump = clock.timestamp_ns(180_000 + 90_000 * 3600);
+        assert!(after_jump >= start + 3 * NANOS_PER_SEC);
+        assert!(after_jump < start + 3600 * NANOS_PER_SEC);
+    }
+}
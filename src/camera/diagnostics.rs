@@ -0,0 +1,76 @@
+//! Classification of FFmpeg's stderr. FFmpeg reports why a stream failed only
+//! as free text, so the common causes are picked out of it to tell users
+//! why a camera won't connect.
+
+use super::rtsp::RtspError;
+
+/// The error a line of FFmpeg output describes, if it is one we know
+pub fn classify_ffmpeg(line: &str) -> Option<RtspError> {
+    if unsupported_input_stream(line) {
+        return None;
+    }
+    let lower = line.to_ascii_lowercase();
+    if lower.contains("401 unauthorized") || lower.contains("authorization failed") {
+        return Some(RtspError::Unauthorized);
+    }
+    if lower.contains("connection refused") {
+        return Some(RtspError::ConnectionRefused);
+    }
+    if lower.contains("unsupported codec")
+        || lower.contains("codec not currently supported")
+        || lower.contains("decoder not found")
+        || (lower.contains("codec") && lower.contains("is not supported"))
+    {
+        return Some(RtspError::UnsupportedCodec(line.trim().to_string()));
+    }
+    if (lower.contains("missed") && lower.contains("packet"))
+        || lower.contains("max delay reached")
+        || lower.contains("rtp: dropping old packet")
+    {
+        return Some(RtspError::PacketLoss(line.trim().to_string()));
+    }
+    None
+}
+
+/// Whether the line reports an input stream ffmpeg can't handle and leaves
+/// out. Cameras often carry an ONVIF metadata track that gets this, which is
+/// harmless as long as the video stream comes through.
+pub fn unsupported_input_stream(line: &str) -> bool {
+    let lower = line.to_ascii_lowercase();
+    lower.contains("unsupported codec with id") && lower.contains("for input stream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_ffmpeg() {
+        let kind = |line: &str| classify_ffmpeg(line).map(|e| e.kind());
+        assert_eq!(
+            kind("[rtsp @ 0x55d0] method DESCRIBE failed: 401 Unauthorized"),
+            Some("unauthorized")
+        );
+        assert_eq!(
+            kind("[tcp @ 0x55d0] Connection to tcp://10.0.0.5:554?timeout=0 failed: Connection refused"),
+            Some("connection_refused")
+        );
+        // Usually a metadata track, see unsupported_input_stream
+        let metadata = "[rtsp @ 0x55d0] Unsupported codec with id 0 for input stream 1";
+        assert_eq!(kind(metadata), None);
+        assert!(unsupported_input_stream(metadata));
+        assert_eq!(
+            kind("[h264 @ 0x55d0] Decoder not found"),
+            Some("unsupported_codec")
+        );
+        assert_eq!(
+            kind("[rtsp @ 0x55d0] RTP: missed 12 packets"),
+            Some("packet_loss")
+        );
+        assert_eq!(
+            kind("[rtsp @ 0x55d0] max delay reached. need to consume packet"),
+            Some("packet_loss")
+        );
+        assert_eq!(kind("[h264 @ 0x55d0] SEI type 5 size 2040 truncated"), None);
+    }
+}
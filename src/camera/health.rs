@@ -0,0 +1,318 @@
+//! Per-camera stream health: connection state, keyframe timing and rates
+//! measured by the segmenter, ffmpeg warnings and reconnects. Dropping
+//! offline and coming back online are kept as events.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::rtsp::RtspError;
+
+/// Recent ffmpeg stderr lines kept per camera
+const MAX_WARNINGS: usize = 20;
+/// Recent online/offline events kept per camera
+const MAX_EVENTS: usize = 50;
+/// Recent connection and stream errors kept per camera
+const MAX_ERRORS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// Not streamed yet since startup
+    Connecting,
+    Online,
+    /// Disconnected, or connected but no keyframe for too long
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthEvent {
+    /// Unix time in milliseconds
+    pub time_ms: u64,
+    pub state: ConnectionState,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamWarning {
+    pub time_ms: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamError {
+    pub time_ms: u64,
+    /// What went wrong, such as `unauthorized` or `packet_loss`
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// Size and length of one finished GOP
+#[derive(Debug, Clone, Copy)]
+pub struct GopStats {
+    pub duration_ns: u64,
+    pub bytes: usize,
+    pub frames: u32,
+}
+
+/// Health of one camera as served by the status API
+#[derive(Debug, Clone, Serialize)]
+pub struct CameraStatus {
+    pub state: ConnectionState,
+    /// Unix time of the latest keyframe in milliseconds
+    pub last_keyframe_ms: Option<u64>,
+    pub last_keyframe_age_secs: Option<f64>,
+    /// Length, bitrate and frame rate of the latest complete GOP
+    pub gop_secs: Option<f64>,
+    pub bitrate_kbps: Option<f64>,
+    pub fps: Option<f64>,
+    pub reconnects: u64,
+    pub last_error: Option<String>,
+    pub recent_errors: Vec<StreamError>,
+    pub warnings: Vec<StreamWarning>,
+    pub events: Vec<HealthEvent>,
+    /// The camera's detection substream, when it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detect: Option<Box<CameraStatus>>,
+}
+
+struct CameraHealth {
+    state: ConnectionState,
+    last_keyframe: Option<(Instant, u64)>,
+    gop: Option<GopStats>,
+    reconnects: u64,
+    last_error: Option<String>,
+    errors: VecDeque<StreamError>,
+    warnings: VecDeque<StreamWarning>,
+    events: VecDeque<HealthEvent>,
+}
+
+impl CameraHealth {
+    fn new() -> Self {
+        Self {
+            state: ConnectionState::Connecting,
+            last_keyframe: None,
+            gop: None,
+            reconnects: 0,
+            last_error: None,
+            errors: VecDeque::new(),
+            warnings: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    fn transition(&mut self, camera_id: &str, state: ConnectionState, reason: &str) {
+        if self.state == state {
+            return;
+        }
+        self.state = state;
+        match state {
+            ConnectionState::Online => {
+                tracing::info!(camera = %camera_id, reason, "camera online");
+            }
+            _ => tracing::warn!(camera = %camera_id, reason, "camera offline"),
+        }
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(HealthEvent {
+            time_ms: now_ms(),
+            state,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Health of every configured camera, updated by its ingest pipeline
+#[derive(Clone)]
+pub struct HealthStore {
+    cameras: Arc<HashMap<String, RwLock<CameraHealth>>>,
+}
+
+impl HealthStore {
+    pub fn new(camera_ids: &[String]) -> Self {
+        let mut cameras = HashMap::new();
+        for id in camera_ids {
+            cameras.insert(id.clone(), RwLock::new(CameraHealth::new()));
+        }
+        Self {
+            cameras: Arc::new(cameras),
+        }
+    }
+
+    fn update(&self, camera_id: &str, f: impl FnOnce(&mut CameraHealth)) {
+        if let Some(lock) = self.cameras.get(camera_id) {
+            f(&mut lock.write().unwrap());
+        }
+    }
+
+    /// A keyframe arrived, closing the GOP before it if there was one
+    pub fn keyframe(&self, camera_id: &str, gop: Option<GopStats>) {
+        self.update(camera_id, |health| {
+            health.last_keyframe = Some((Instant::now(), now_ms()));
+            if gop.is_some_and(|g| g.duration_ns > 0) {
+                health.gop = gop;
+            }
+            health.transition(camera_id, ConnectionState::Online, "receiving keyframes");
+        });
+    }
+
+    /// The ingest pipeline stopped, with why
+    pub fn disconnected(&self, camera_id: &str, reason: &str) {
+        self.update(camera_id, |health| {
+            health.last_error = Some(reason.to_string());
+            health.transition(camera_id, ConnectionState::Offline, reason);
+        });
+    }
+
+    /// An error from the ingest pipeline or classified from its output
+    pub fn error(&self, camera_id: &str, error: &RtspError) {
+        self.update(camera_id, |health| {
+            if health.errors.len() >= MAX_ERRORS {
+                health.errors.pop_front();
+            }
+            health.errors.push_back(StreamError {
+                time_ms: now_ms(),
+                kind: error.kind(),
+                message: error.to_string(),
+            });
+        });
+    }
+
+    pub fn reconnecting(&self, camera_id: &str) {
+        self.update(camera_id, |health| health.reconnects += 1);
+    }
+
+    /// A line ffmpeg wrote to stderr
+    pub fn warning(&self, camera_id: &str, message: &str) {
+        self.update(camera_id, |health| {
+            if health.warnings.len() >= MAX_WARNINGS {
+                health.warnings.pop_front();
+            }
+            health.warnings.push_back(StreamWarning {
+                time_ms: now_ms(),
+                message: message.to_string(),
+            });
+        });
+    }
+
+    /// Mark an online camera offline when its last keyframe is older than
+    /// `timeout`, while the connection itself may still be open
+    pub fn check_stall(&self, camera_id: &str, timeout: Duration) {
+        self.update(camera_id, |health| {
+            let stalled = health
+                .last_keyframe
+                .is_some_and(|(at, _)| at.elapsed() > timeout);
+            if health.state == ConnectionState::Online && stalled {
+                let reason = format!("no keyframe for {}s", timeout.as_secs());
+                health.transition(camera_id, ConnectionState::Offline, &reason);
+            }
+        });
+    }
+
+    /// Status of a camera, with its detection substream folded in
+    pub fn status(&self, camera_id: &str) -> Option<CameraStatus> {
+        let mut status = self.stream_status(camera_id)?;
+        status.detect = self.stream_status(&detect_id(camera_id)).map(Box::new);
+        Some(status)
+    }
+
+    fn stream_status(&self, camera_id: &str) -> Option<CameraStatus> {
+        let health = self.cameras.get(camera_id)?.read().unwrap();
+        let secs = |ns: u64| ns as f64 / 1_000_000_000.0;
+        Some(CameraStatus {
+            state: health.state,
+            last_keyframe_ms: health.last_keyframe.map(|(_, ms)| ms),
+            last_keyframe_age_secs: health
+                .last_keyframe
+                .map(|(at, _)| at.elapsed().as_secs_f64()),
+            gop_secs: health.gop.map(|g| secs(g.duration_ns)),
+            bitrate_kbps: health
+                .gop
+                .map(|g| g.bytes as f64 * 8.0 / 1000.0 / secs(g.duration_ns)),
+            fps: health.gop.map(|g| g.frames as f64 / secs(g.duration_ns)),
+            reconnects: health.reconnects,
+            last_error: health.last_error.clone(),
+            recent_errors: health.errors.iter().cloned().collect(),
+            warnings: health.warnings.iter().cloned().collect(),
+            events: health.events.iter().cloned().collect(),
+            detect: None,
+        })
+    }
+}
+
+/// Id a camera's detection substream is ingested and tracked under
+pub fn detect_id(camera_id: &str) -> String {
+    format!("{camera_id}/detect")
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_transitions() {
+        let store = HealthStore::new(&["cam".to_string(), detect_id("cam")]);
+        assert_eq!(
+            store.status("cam").unwrap().state,
+            ConnectionState::Connecting
+        );
+
+        store.keyframe("cam", None);
+        store.keyframe(
+            "cam",
+            Some(GopStats {
+                duration_ns: 2_000_000_000,
+                bytes: 1_500_000,
+                frames: 50,
+            }),
+        );
+        let status = store.status("cam").unwrap();
+        assert_eq!(status.state, ConnectionState::Online);
+        assert_eq!(status.gop_secs, Some(2.0));
+        assert_eq!(status.bitrate_kbps, Some(6000.0));
+        assert_eq!(status.fps, Some(25.0));
+
+        // Still fresh, then stalled
+        store.check_stall("cam", Duration::from_secs(60));
+        assert_eq!(store.status("cam").unwrap().state, ConnectionState::Online);
+        std::thread::sleep(Duration::from_millis(2));
+        store.check_stall("cam", Duration::ZERO);
+        assert_eq!(store.status("cam").unwrap().state, ConnectionState::Offline);
+
+        store.error("cam", &RtspError::ConnectionRefused);
+        store.disconnected("cam", "connection refused");
+        store.reconnecting("cam");
+        store.keyframe("cam", None);
+        let status = store.status("cam").unwrap();
+        assert_eq!(status.reconnects, 1);
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+        assert_eq!(status.recent_errors[0].kind, "connection_refused");
+        assert_eq!(
+            status.detect.as_ref().unwrap().state,
+            ConnectionState::Connecting
+        );
+        store.keyframe(&detect_id("cam"), None);
+        let detect = store.status("cam").unwrap().detect.unwrap();
+        assert_eq!(detect.state, ConnectionState::Online);
+        assert!(detect.detect.is_none());
+        let states: Vec<ConnectionState> = status.events.iter().map(|e| e.state).collect();
+        assert_eq!(
+            states,
+            vec![
+                ConnectionState::Online,
+                ConnectionState::Offline,
+                ConnectionState::Online
+            ]
+        );
+    }
+}
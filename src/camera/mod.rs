@@ -1,3 +1,18 @@
+mod auth;
+mod backoff;
+mod base64;
+mod clock;
+mod diagnostics;
+mod health;
+mod native;
+mod rtp;
 mod rtsp;
+mod sdp;
+#[cfg(test)]
+mod test_server;
+mod watchdog;
 
-pub use rtsp::FfmpegPipeline;
+pub use backoff::Backoff;
+pub use diagnostics::classify_ffmpeg;
+pub use health::{detect_id, CameraStatus, HealthStore};
+pub use rtsp::{Pipeline, RtspError};
@@ -0,0 +1,188 @@
+//! RTP (RFC 3550) parsing and H.264 depacketization (RFC 6184)
+
+use crate::buffer::nal::{NalKind, VideoCodec};
+
+const NAL_STAP_A: u8 = 24;
+const NAL_FU_A: u8 = 28;
+
+#[derive(Debug)]
+pub struct RtpPacket<'a> {
+    pub payload_type: u8,
+    pub marker: bool,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub payload: &'a [u8],
+}
+
+pub fn parse(data: &[u8]) -> Option<RtpPacket<'_>> {
+    if data.len() < 12 || data[0] >> 6 != 2 {
+        return None;
+    }
+    let csrc_count = (data[0] & 0x0F) as usize;
+    let mut start = 12 + 4 * csrc_count;
+    if data[0] & 0x10 != 0 {
+        let words = u16::from_be_bytes([*data.get(start + 2)?, *data.get(start + 3)?]) as usize;
+        start += 4 + 4 * words;
+    }
+    let mut end = data.len();
+    if data[0] & 0x20 != 0 {
+        end = end.checked_sub(*data.last()? as usize)?;
+    }
+    Some(RtpPacket {
+        payload_type: data[1] & 0x7F,
+        marker: data[1] & 0x80 != 0,
+        sequence: u16::from_be_bytes([data[2], data[3]]),
+        timestamp: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+        payload: data.get(start..end)?,
+    })
+}
+
+/// NAL units sharing one RTP timestamp
+#[derive(Debug)]
+pub struct AccessUnit {
+    pub timestamp: u32,
+    pub keyframe: bool,
+    pub nals: Vec<Vec<u8>>,
+}
+
+/// Reassembles single NAL, STAP-A and FU-A payloads into access units. An
+/// access unit ends at the marker bit or when the timestamp changes.
+#[derive(Default)]
+pub struct H264Depacketizer {
+    timestamp: Option<u32>,
+    nals: Vec<Vec<u8>>,
+    fragment: Option<Vec<u8>>,
+    last_sequence: Option<u16>,
+    lost: u64,
+}
+
+impl H264Depacketizer {
+    pub fn push(&mut self, packet: &RtpPacket) -> Vec<AccessUnit> {
+        let mut done = Vec::new();
+
+        if let Some(last) = self.last_sequence {
+            let gap = packet.sequence.wrapping_sub(last.wrapping_add(1));
+            if gap != 0 && gap < 0x8000 {
+                self.lost += gap as u64;
+                // The fragment in flight is missing pieces
+                self.fragment = None;
+            }
+        }
+        self.last_sequence = Some(packet.sequence);
+
+        if self.timestamp.is_some_and(|ts| ts != packet.timestamp) {
+            done.extend(self.take());
+        }
+        self.timestamp = Some(packet.timestamp);
+
+        let payload = packet.payload;
+        let Some(&header) = payload.first() else {
+            return done;
+        };
+        match header & 0x1F {
+            1..=23 => self.nals.push(payload.to_vec()),
+            NAL_STAP_A => {
+                let mut rest = &payload[1..];
+                while rest.len() >= 2 {
+                    let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                    let Some(nal) = rest.get(2..2 + len) else {
+                        break;
+                    };
+                    if !nal.is_empty() {
+                        self.nals.push(nal.to_vec());
+                    }
+                    rest = &rest[2 + len..];
+                }
+            }
+            NAL_FU_A if payload.len() > 2 => {
+                let fu_header = payload[1];
+                if fu_header & 0x80 != 0 {
+                    let mut nal = Vec::with_capacity(payload.len() * 8);
+                    nal.push((header & 0xE0) | (fu_header & 0x1F));
+                    self.fragment = Some(nal);
+                }
+                if let Some(nal) = self.fragment.as_mut() {
+                    nal.extend_from_slice(&payload[2..]);
+                    if fu_header & 0x40 != 0 {
+                        let nal = self.fragment.take().unwrap_or_default();
+                        self.nals.push(nal);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if packet.marker {
+            done.extend(self.take());
+        }
+        done
+    }
+
+    /// Packets missing from the sequence so far
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    fn take(&mut self) -> Option<AccessUnit> {
+        self.fragment = None;
+        let timestamp = self.timestamp.take()?;
+        if self.nals.is_empty() {
+            return None;
+        }
+        let nals = std::mem::take(&mut self.nals);
+        let keyframe = nals
+            .iter()
+            .any(|nal| VideoCodec::H264.kind(nal) == NalKind::Keyframe);
+        Some(AccessUnit {
+            timestamp,
+            keyframe,
+            nals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sequence: u16, timestamp: u32, marker: bool, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x80, if marker { 0xE0 } else { 0x60 }];
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_depacketize_stap_a_and_fu_a() {
+        let mut depacketizer = H264Depacketizer::default();
+        let packets = [
+            // STAP-A with SPS and PPS
+            packet(1, 0, false, &[0x18, 0, 2, 0x67, 0x64, 0, 2, 0x68, 0xEE]),
+            // IDR split into three FU-A fragments
+            packet(2, 0, false, &[0x7C, 0x85, 1, 2]),
+            packet(3, 0, false, &[0x7C, 0x05, 3, 4]),
+            packet(4, 0, true, &[0x7C, 0x45, 5]),
+            packet(5, 3000, true, &[0x41, 9, 9]),
+        ];
+        let mut units = Vec::new();
+        for data in &packets {
+            units.extend(depacketizer.push(&parse(data).unwrap()));
+        }
+
+        assert_eq!(units.len(), 2);
+        assert!(units[0].keyframe);
+        assert_eq!(
+            units[0].nals,
+            vec![
+                vec![0x67, 0x64],
+                vec![0x68, 0xEE],
+                vec![0x65, 1, 2, 3, 4, 5]
+            ]
+        );
+        assert!(!units[1].keyframe);
+        assert_eq!(units[1].timestamp, 3000);
+        assert_eq!(depacketizer.lost(), 0);
+    }
+}
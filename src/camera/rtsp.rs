@@ -1,12 +1,19 @@
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
 use std::process::{Child, Command, Stdio};
-use std::sync::{Arc, RwLock};
-use std::time::SystemTime;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
 
 use thiserror::Error;
 
-use crate::buffer::{GopSegment, HotBuffer};
-use crate::config::CameraConfig;
+use super::clock::StreamClock;
+use super::diagnostics::{classify_ffmpeg, unsupported_input_stream};
+use super::health::{GopStats, HealthStore};
+use super::native::NativePipeline;
+use super::watchdog::StallWatchdog;
+use crate::buffer::nal::VideoCodec;
+use crate::buffer::{ts, GopSegment, HotBuffer};
+use crate::config::{CameraConfig, IngestBackend};
 
 #[derive(Debug, Error)]
 pub enum RtspError {
@@ -16,20 +23,96 @@ pub enum RtspError {
     FfmpegNotFound,
     #[error("ffmpeg failed: {0}")]
     FfmpegFailed(String),
+    #[error("rtsp error: {0}")]
+    Protocol(String),
+    #[error("authentication failed")]
+    Unauthorized,
+    #[error("connection refused")]
+    ConnectionRefused,
+    #[error("unsupported codec: {0}")]
+    UnsupportedCodec(String),
+    #[error("packet loss: {0}")]
+    PacketLoss(String),
+    #[error("stream stalled, no keyframe for {0}s")]
+    Stalled(u64),
+}
+
+impl RtspError {
+    /// Short name for the camera status API
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RtspError::Io(_) => "io",
+            RtspError::FfmpegNotFound => "ffmpeg_not_found",
+            RtspError::FfmpegFailed(_) => "ffmpeg_failed",
+            RtspError::Protocol(_) => "protocol",
+            RtspError::Unauthorized => "unauthorized",
+            RtspError::ConnectionRefused => "connection_refused",
+            RtspError::UnsupportedCodec(_) => "unsupported_codec",
+            RtspError::PacketLoss(_) => "packet_loss",
+            RtspError::Stalled(_) => "stalled",
+        }
+    }
+}
+
+/// How often the FFmpeg watchdog looks for a stalled stream
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One connection to a camera through the selected ingest backend
+pub enum Pipeline {
+    Native(NativePipeline),
+    Ffmpeg(FfmpegPipeline),
+}
+
+impl Pipeline {
+    pub fn new(
+        config: &CameraConfig,
+        backend: IngestBackend,
+        buffer: Arc<RwLock<HotBuffer>>,
+        health: HealthStore,
+    ) -> Result<Self, RtspError> {
+        Ok(match backend {
+            IngestBackend::Native => Pipeline::Native(NativePipeline::new(config, buffer, health)?),
+            IngestBackend::Ffmpeg => Pipeline::Ffmpeg(FfmpegPipeline::new(config, buffer, health)?),
+        })
+    }
+
+    /// The backend to connect to `url` with: FFmpeg when the native client
+    /// can't handle its scheme
+    pub fn backend_for(url: &str, preferred: IngestBackend) -> IngestBackend {
+        match preferred {
+            IngestBackend::Native if !NativePipeline::supports_url(url) => IngestBackend::Ffmpeg,
+            backend => backend,
+        }
+    }
+
+    pub fn run(&self, shutdown: &std::sync::atomic::AtomicBool) -> Result<(), RtspError> {
+        match self {
+            Pipeline::Native(pipeline) => pipeline.run(shutdown),
+            Pipeline::Ffmpeg(pipeline) => pipeline.run(shutdown),
+        }
+    }
 }
 
 pub struct FfmpegPipeline {
     camera_id: String,
     url: String,
     buffer: Arc<RwLock<HotBuffer>>,
+    health: HealthStore,
+    stall_gops: u32,
 }
 
 impl FfmpegPipeline {
-    pub fn new(config: &CameraConfig, buffer: Arc<RwLock<HotBuffer>>) -> Result<Self, RtspError> {
+    pub fn new(
+        config: &CameraConfig,
+        buffer: Arc<RwLock<HotBuffer>>,
+        health: HealthStore,
+    ) -> Result<Self, RtspError> {
         Ok(Self {
             camera_id: config.id.clone(),
             url: config.url.clone(),
             buffer,
+            health,
+            stall_gops: config.stall_gops,
         })
     }
 
@@ -39,14 +122,95 @@ impl FfmpegPipeline {
             "failed to capture stdout".to_string(),
         ))?;
 
+        // Warnings go to the camera's status, and reading them keeps ffmpeg
+        // from blocking on a full pipe. The first fatal error recognised in
+        // them is why the stream ended.
+        let fatal = Arc::new(Mutex::new(None));
+        let skipped_stream = Arc::new(Mutex::new(None));
+        let stderr_handle = child.stderr.take().map(|stderr| {
+            let camera_id = self.camera_id.clone();
+            let health = self.health.clone();
+            let fatal = Arc::clone(&fatal);
+            let skipped_stream = Arc::clone(&skipped_stream);
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    tracing::debug!(camera = %camera_id, "ffmpeg: {}", line);
+                    health.warning(&camera_id, line);
+                    if unsupported_input_stream(line) {
+                        skipped_stream
+                            .lock()
+                            .unwrap()
+                            .get_or_insert_with(|| line.to_string());
+                    }
+                    match classify_ffmpeg(line) {
+                        Some(e @ RtspError::PacketLoss(_)) => health.error(&camera_id, &e),
+                        Some(e) => {
+                            fatal.lock().unwrap().get_or_insert(e);
+                        }
+                        None => {}
+                    }
+                }
+            })
+        });
+
+        // A blocked read only returns once ffmpeg is killed
+        let watchdog = Arc::new(StallWatchdog::new(self.stall_gops));
+        let child = Arc::new(Mutex::new(child));
+        let (stop_watchdog, stopped) = mpsc::channel::<()>();
+        let watchdog_handle = {
+            let camera_id = self.camera_id.clone();
+            let watchdog = Arc::clone(&watchdog);
+            let child = Arc::clone(&child);
+            thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(WATCHDOG_INTERVAL)
+                {
+                    if watchdog.check() {
+                        tracing::warn!(
+                            camera = %camera_id,
+                            timeout_secs = watchdog.timeout().as_secs(),
+                            "no keyframe from ffmpeg, killing it"
+                        );
+                        let _ = child.lock().unwrap().kill();
+                        break;
+                    }
+                }
+            })
+        };
+
         tracing::info!(camera = %self.camera_id, "ffmpeg pipeline started");
 
-        let result = self.process_stream(stdout, shutdown);
+        let result = self.process_stream(stdout, &watchdog, shutdown);
 
-        let _ = child.kill();
-        let _ = child.wait();
+        drop(stop_watchdog);
+        let _ = watchdog_handle.join();
+        {
+            let mut child = child.lock().unwrap();
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Some(handle) = stderr_handle {
+            let _ = handle.join();
+        }
 
-        result
+        if watchdog.stalled() {
+            return Err(RtspError::Stalled(watchdog.timeout().as_secs()));
+        }
+        let fatal = fatal.lock().unwrap().take();
+        let skipped_stream = skipped_stream.lock().unwrap().take();
+        match (fatal, skipped_stream) {
+            (Some(e), _) => Err(e),
+            // A stream ffmpeg left out only matters if no video came through,
+            // when it may have been the video itself
+            (None, Some(line)) if !watchdog.seen_keyframe() => {
+                Err(RtspError::UnsupportedCodec(line))
+            }
+            _ => result,
+        }
     }
 
     fn spawn_ffmpeg(&self) -> Result<Child, RtspError> {
@@ -84,9 +248,15 @@ impl FfmpegPipeline {
     fn process_stream<R: Read>(
         &self,
         mut reader: R,
+        watchdog: &Arc<StallWatchdog>,
         shutdown: &std::sync::atomic::AtomicBool,
     ) -> Result<(), RtspError> {
-        let mut segmenter = MpegTsSegmenter::new(self.camera_id.clone(), Arc::clone(&self.buffer));
+        let mut segmenter = MpegTsSegmenter::new(
+            self.camera_id.clone(),
+            Arc::clone(&self.buffer),
+            self.health.clone(),
+            Arc::clone(watchdog),
+        );
         let mut buf = [0u8; 188 * 64];
 
         while !shutdown.load(std::sync::atomic::Ordering::Relaxed) {
@@ -102,13 +272,34 @@ impl FfmpegPipeline {
     }
 }
 
+/// Give up looking for the first slice of an access unit after this much
+/// elementary stream data
+const KEYFRAME_SCAN_BYTES: usize = 16 * 1024;
+
+/// Packets of a video access unit whose keyframe status is not known yet,
+/// because the muxer did not set random_access_indicator
+struct PendingAccessUnit {
+    timestamps: Option<(u64, u64)>,
+    packets: Vec<u8>,
+    pes: Vec<u8>,
+}
+
 /// Segments raw MPEG-TS stream based on keyframe detection
 /// Stores raw MPEG-TS packets directly - no re-muxing needed
-struct MpegTsSegmenter {
+/// Segment timestamps come from the keyframe PTS, anchored to wall clock
+/// once per connection
+pub(super) struct MpegTsSegmenter {
     camera_id: String,
     buffer: Arc<RwLock<HotBuffer>>,
+    health: HealthStore,
+    watchdog: Arc<StallWatchdog>,
+    clock: StreamClock,
+    /// Video pictures since the last keyframe
+    pictures: u32,
     current_segment: Option<GopSegment>,
+    pending: Option<PendingAccessUnit>,
     video_pid: Option<u16>,
+    video_codec: Option<VideoCodec>,
     audio_pid: Option<u16>,
     pat_packet: Option<[u8; 188]>,
     pmt_packet: Option<[u8; 188]>,
@@ -117,12 +308,23 @@ struct MpegTsSegmenter {
 }
 
 impl MpegTsSegmenter {
-    fn new(camera_id: String, buffer: Arc<RwLock<HotBuffer>>) -> Self {
+    pub(super) fn new(
+        camera_id: String,
+        buffer: Arc<RwLock<HotBuffer>>,
+        health: HealthStore,
+        watchdog: Arc<StallWatchdog>,
+    ) -> Self {
         Self {
             camera_id,
             buffer,
+            health,
+            watchdog,
+            clock: StreamClock::default(),
+            pictures: 0,
             current_segment: None,
+            pending: None,
             video_pid: None,
+            video_codec: None,
             audio_pid: None,
             pat_packet: None,
             pmt_packet: None,
@@ -131,7 +333,7 @@ impl MpegTsSegmenter {
         }
     }
 
-    fn process(&mut self, data: &[u8]) {
+    pub(super) fn process(&mut self, data: &[u8]) {
         // Handle partial packet from previous read
         let mut offset = 0;
         if !self.partial_packet.is_empty() {
@@ -172,8 +374,7 @@ impl MpegTsSegmenter {
     }
 
     fn process_packet(&mut self, packet: &[u8]) {
-        let pid = ((packet[1] as u16 & 0x1F) << 8) | packet[2] as u16;
-        let has_adaptation = (packet[3] & 0x20) != 0;
+        let pid = ts::pid(packet);
 
         // Capture PAT
         if pid == 0 {
@@ -191,32 +392,81 @@ impl MpegTsSegmenter {
             self.parse_pmt(packet);
         }
 
-        // Detect keyframe from random_access_indicator
-        let is_keyframe = if has_adaptation && Some(pid) == self.video_pid {
-            let adaptation_len = packet[4] as usize;
-            if adaptation_len > 0 && adaptation_len < 184 {
-                (packet[5] & 0x40) != 0
+        let is_video = Some(pid) == self.video_pid;
+        if is_video && ts::payload_unit_start(packet) {
+            self.resolve_pending(false);
+            self.pictures += 1;
+
+            // Detect keyframe from random_access_indicator
+            if ts::random_access(packet) {
+                self.split(ts::pes_timestamps(packet));
             } else {
-                false
+                // Without the flag, hold the packets back until the first
+                // slice NAL unit shows whether this is an IDR/IRAP picture
+                self.pending = Some(PendingAccessUnit {
+                    timestamps: ts::pes_timestamps(packet),
+                    packets: Vec::new(),
+                    pes: Vec::new(),
+                });
             }
-        } else {
-            false
+        }
+
+        let keyframe = {
+            let Some(pending) = self.pending.as_mut() else {
+                self.append(packet);
+                return;
+            };
+            pending.packets.extend_from_slice(packet);
+            if is_video {
+                if let Some(offset) = ts::payload_offset(packet) {
+                    pending.pes.extend_from_slice(&packet[offset..]);
+                }
+            }
+            let keyframe = match (self.video_codec, ts::pes_payload(&pending.pes)) {
+                (Some(codec), Some(es)) => codec.access_unit_is_keyframe(es),
+                _ => None,
+            };
+            keyframe.or((pending.pes.len() > KEYFRAME_SCAN_BYTES).then_some(false))
+        };
+        if let Some(keyframe) = keyframe {
+            self.resolve_pending(keyframe);
+        }
+    }
+
+    fn resolve_pending(&mut self, keyframe: bool) {
+        let Some(pending) = self.pending.take() else {
+            return;
         };
+        if keyframe {
+            self.split(pending.timestamps);
+        }
+        for packet in pending.packets.chunks_exact(188) {
+            self.append(packet);
+        }
+    }
 
-        // Start new segment on keyframe
-        if is_keyframe {
-            let pts_ns = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos() as u64;
-            self.finalize_segment(pts_ns);
-            self.start_segment(pts_ns);
+    /// Start new segment on keyframe, stamped with its presentation time
+    fn split(&mut self, timestamps: Option<(u64, u64)>) {
+        match timestamps {
+            Some((pts, _)) => {
+                let pts_ns = self.clock.timestamp_ns(pts);
+                let gop = self.finalize_segment(pts_ns);
+                self.health.keyframe(&self.camera_id, gop);
+                self.watchdog.keyframe(gop);
+                self.start_segment(pts_ns);
+            }
+            None => {
+                tracing::debug!(camera = %self.camera_id, "keyframe without PTS, not splitting");
+            }
         }
+    }
 
-        // Append packet to current segment
+    /// Append packet to current segment
+    fn append(&mut self, packet: &[u8]) {
+        let is_video = Some(ts::pid(packet)) == self.video_pid;
         if let Some(ref mut segment) = self.current_segment {
             segment.data.extend_from_slice(packet);
-            if Some(pid) == self.video_pid {
+            if is_video {
                 segment.frame_count += 1;
             }
         }
@@ -239,15 +489,25 @@ impl MpegTsSegmenter {
         self.current_segment = Some(segment);
     }
 
-    fn finalize_segment(&mut self, end_pts_ns: u64) {
-        if let Some(mut segment) = self.current_segment.take() {
-            segment.finalize(end_pts_ns);
-            if segment.frame_count > 0 {
-                if let Ok(mut hot) = self.buffer.write() {
-                    hot.push(segment);
-                }
+    /// Push the current segment to the hot buffer, returning its stats
+    fn finalize_segment(&mut self, end_pts_ns: u64) -> Option<GopStats> {
+        // The keyframe that ends this GOP was already counted
+        let frames = self.pictures.saturating_sub(1);
+        self.pictures = 1;
+
+        let mut segment = self.current_segment.take()?;
+        segment.finalize(end_pts_ns);
+        let stats = GopStats {
+            duration_ns: segment.duration_ns,
+            bytes: segment.data.len(),
+            frames,
+        };
+        if segment.frame_count > 0 {
+            if let Ok(mut hot) = self.buffer.write() {
+                hot.push(segment);
             }
         }
+        Some(stats)
     }
 
     fn parse_pat(&mut self, packet: &[u8]) {
@@ -311,10 +571,13 @@ impl MpegTsSegmenter {
             let elem_pid = ((packet[pos + 1] as u16 & 0x1F) << 8) | packet[pos + 2] as u16;
             let es_info_len = ((packet[pos + 3] as usize & 0x0F) << 8) | packet[pos + 4] as usize;
 
-            // H.264 stream type = 0x1B
-            if stream_type == 0x1B && self.video_pid.is_none() {
-                self.video_pid = Some(elem_pid);
-                tracing::debug!(camera = %self.camera_id, video_pid = elem_pid, "detected H.264 video PID");
+            // H.264 stream type = 0x1B, H.265 = 0x24
+            if let Some(codec) = VideoCodec::from_stream_type(stream_type) {
+                if self.video_pid.is_none() {
+                    self.video_pid = Some(elem_pid);
+                    self.video_codec = Some(codec);
+                    tracing::debug!(camera = %self.camera_id, video_pid = elem_pid, ?codec, "detected video PID");
+                }
             }
 
             // AAC audio stream types: 0x0F (MPEG-2 AAC), 0x11 (MPEG-4 AAC), 0x81 (AC-3)
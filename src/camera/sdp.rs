@@ -0,0 +1,116 @@
+//! Just enough SDP (RFC 8866) to set up the video track of an RTSP session
+
+use super::base64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoTrack {
+    pub payload_type: u8,
+    /// Encoding name from `a=rtpmap`, e.g. `H264`
+    pub encoding: String,
+    pub clock_rate: u32,
+    /// `a=control` attribute, relative to the session's base URL unless absolute
+    pub control: Option<String>,
+    /// Parameter sets from `sprop-parameter-sets`, without start codes
+    pub parameter_sets: Vec<Vec<u8>>,
+}
+
+/// First `m=video` section of a session description
+pub fn parse_video_track(sdp: &str) -> Option<VideoTrack> {
+    let mut in_video = false;
+    let mut track: Option<VideoTrack> = None;
+    for line in sdp.lines().map(str::trim) {
+        if let Some(media) = line.strip_prefix("m=") {
+            if track.is_some() {
+                break;
+            }
+            in_video = media.starts_with("video ");
+            if in_video {
+                let payload_type = media.split_whitespace().nth(3)?.parse().ok()?;
+                track = Some(VideoTrack {
+                    payload_type,
+                    encoding: String::new(),
+                    clock_rate: 90_000,
+                    control: None,
+                    parameter_sets: Vec::new(),
+                });
+            }
+            continue;
+        }
+        let Some(track) = track.as_mut().filter(|_| in_video) else {
+            continue;
+        };
+
+        if let Some(rtpmap) = line.strip_prefix("a=rtpmap:") {
+            let (pt, format) = rtpmap.split_once(' ')?;
+            if pt.parse() == Ok(track.payload_type) {
+                let mut parts = format.split('/');
+                track.encoding = parts.next().unwrap_or_default().to_string();
+                if let Some(rate) = parts.next().and_then(|r| r.parse().ok()) {
+                    track.clock_rate = rate;
+                }
+            }
+        } else if let Some(fmtp) = line.strip_prefix("a=fmtp:") {
+            let Some((pt, params)) = fmtp.split_once(' ') else {
+                continue;
+            };
+            if pt.parse() != Ok(track.payload_type) {
+                continue;
+            }
+            for param in params.split(';') {
+                if let Some(sets) = param.trim().strip_prefix("sprop-parameter-sets=") {
+                    track.parameter_sets = sets
+                        .split(',')
+                        .filter_map(base64::decode)
+                        .filter(|nal| !nal.is_empty())
+                        .collect();
+                }
+            }
+        } else if let Some(control) = line.strip_prefix("a=control:") {
+            track.control = Some(control.to_string());
+        }
+    }
+    track
+}
+
+/// Resolve a track's control attribute against the session base URL
+pub fn control_url(base: &str, control: Option<&str>) -> String {
+    match control {
+        None | Some("*") => base.to_string(),
+        Some(control) if control.starts_with("rtsp://") || control.starts_with("rtsps://") => {
+            control.to_string()
+        }
+        Some(control) => format!("{}/{}", base.trim_end_matches('/'), control),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_video_track() {
+        let sdp = "v=0\r\n\
+            o=- 0 0 IN IP4 127.0.0.1\r\n\
+            s=Camera\r\n\
+            a=control:*\r\n\
+            m=audio 0 RTP/AVP 97\r\n\
+            a=rtpmap:97 MPEG4-GENERIC/16000\r\n\
+            a=control:trackID=2\r\n\
+            m=video 0 RTP/AVP 96\r\n\
+            a=rtpmap:96 H264/90000\r\n\
+            a=fmtp:96 packetization-mode=1;profile-level-id=640028;sprop-parameter-sets=Z2QAKA==,aO48gA==\r\n\
+            a=control:trackID=1\r\n";
+        let track = parse_video_track(sdp).unwrap();
+        assert_eq!(track.payload_type, 96);
+        assert_eq!(track.encoding, "H264");
+        assert_eq!(track.control.as_deref(), Some("trackID=1"));
+        assert_eq!(
+            track.parameter_sets,
+            vec![vec![0x67, 0x64, 0x00, 0x28], vec![0x68, 0xEE, 0x3C, 0x80]]
+        );
+        assert_eq!(
+            control_url("rtsp://cam/stream/", track.control.as_deref()),
+            "rtsp://cam/stream/trackID=1"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Keyframe watchdog for one ingest session. A camera can stop sending video
+//! while keeping its connection open, which leaves a blocking read waiting
+//! forever; the session is given up once no keyframe arrived for a few GOPs.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::health::GopStats;
+
+/// Allowance for connecting and the first keyframe, before the GOP length
+/// is known
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Lower bound on the timeout for very short GOPs
+const MIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) struct StallWatchdog {
+    started: Instant,
+    gops: u32,
+    /// Milliseconds since `started` of the latest keyframe
+    last_keyframe_ms: AtomicU64,
+    /// Length of the latest complete GOP, 0 until one was seen
+    gop_ms: AtomicU64,
+    seen_keyframe: AtomicBool,
+    stalled: AtomicBool,
+}
+
+impl StallWatchdog {
+    pub(super) fn new(gops: u32) -> Self {
+        Self {
+            started: Instant::now(),
+            gops: gops.max(1),
+            last_keyframe_ms: AtomicU64::new(0),
+            gop_ms: AtomicU64::new(0),
+            seen_keyframe: AtomicBool::new(false),
+            stalled: AtomicBool::new(false),
+        }
+    }
+
+    pub(super) fn keyframe(&self, gop: Option<GopStats>) {
+        self.last_keyframe_ms
+            .store(self.elapsed_ms(), Ordering::Relaxed);
+        self.seen_keyframe.store(true, Ordering::Relaxed);
+        if let Some(gop) = gop.filter(|g| g.duration_ns > 0) {
+            self.gop_ms
+                .store(gop.duration_ns / 1_000_000, Ordering::Relaxed);
+        }
+    }
+
+    /// How long to wait for the next keyframe
+    pub(super) fn timeout(&self) -> Duration {
+        match self.gop_ms.load(Ordering::Relaxed) {
+            0 => STARTUP_TIMEOUT,
+            gop_ms => Duration::from_millis(gop_ms * self.gops as u64).max(MIN_TIMEOUT),
+        }
+    }
+
+    /// Whether the keyframe is overdue. Once it is, the session stays
+    /// marked as stalled.
+    pub(super) fn check(&self) -> bool {
+        let since_keyframe = self
+            .elapsed_ms()
+            .saturating_sub(self.last_keyframe_ms.load(Ordering::Relaxed));
+        if Duration::from_millis(since_keyframe) > self.timeout() {
+            self.stalled.store(true, Ordering::Relaxed);
+        }
+        self.stalled()
+    }
+
+    /// Whether any video came through in this session
+    pub(super) fn seen_keyframe(&self) -> bool {
+        self.seen_keyframe.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn stalled(&self) -> bool {
+        self.stalled.load(Ordering::Relaxed)
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
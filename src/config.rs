@@ -18,6 +18,310 @@ pub enum ConfigError {
 pub struct CameraConfig {
     pub id: String,
     pub url: String,
+    /// Low-resolution substream used for motion analysis instead of `url`
+    #[serde(default)]
+    pub detect_url: Option<String>,
+    /// Shorthand for `storage.recording_mode`, kept for existing configs
+    #[serde(default)]
+    pub recording_mode: Option<RecordingMode>,
+    #[serde(default)]
+    pub ingest: IngestBackend,
+    /// Seconds without a keyframe before the camera is reported offline
+    #[serde(default = "default_offline_after_secs")]
+    pub offline_after_secs: u64,
+    /// GOPs without a keyframe before the connection is dropped and retried
+    #[serde(default = "default_stall_gops")]
+    pub stall_gops: u32,
+    /// First delay between reconnect attempts, doubled on each failure
+    #[serde(default = "default_reconnect_delay_secs")]
+    pub reconnect_delay_secs: u64,
+    #[serde(default = "default_reconnect_max_secs")]
+    pub reconnect_max_secs: u64,
+    #[serde(default)]
+    pub analytics: AnalyticsOverride,
+    #[serde(default)]
+    pub object_detection: ObjectDetectionOverride,
+    #[serde(default)]
+    pub storage: StorageOverride,
+    #[serde(default)]
+    pub zones: Vec<ZoneConfig>,
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    #[serde(default)]
+    pub loitering: Vec<LoiteringConfig>,
+}
+
+fn default_offline_after_secs() -> u64 {
+    10
+}
+
+fn default_stall_gops() -> u32 {
+    3
+}
+
+fn default_reconnect_delay_secs() -> u64 {
+    1
+}
+
+fn default_reconnect_max_secs() -> u64 {
+    60
+}
+
+impl CameraConfig {
+    /// `[analytics]` with this camera's `analytics` and `object_detection`
+    /// tables layered over it
+    pub fn analytics(&self, global: &AnalyticsConfig) -> AnalyticsConfig {
+        let mut config = global.clone();
+        let analytics = &self.analytics;
+        if let Some(enabled) = analytics.enabled {
+            config.enabled = enabled;
+        }
+        if let Some(sample_fps) = analytics.sample_fps {
+            config.sample_fps = sample_fps;
+        }
+        if let Some(tamper) = analytics.tamper_detection {
+            config.tamper_detection = tamper;
+        }
+
+        let detection = &self.object_detection;
+        let od = &mut config.object_detection;
+        if let Some(enabled) = detection.enabled {
+            od.enabled = enabled;
+        }
+        if let Some(model_path) = &detection.model_path {
+            od.model_path = model_path.clone();
+        }
+        if let Some(threshold) = detection.confidence_threshold {
+            od.confidence_threshold = threshold;
+        }
+        if let Some(classes) = &detection.classes {
+            od.classes = classes.clone();
+        }
+        if let Some(backend) = detection.backend {
+            od.backend = backend;
+        }
+        if let Some(labels_path) = &detection.labels_path {
+            od.labels_path = Some(labels_path.clone());
+        }
+        if let Some(priority) = detection.priority {
+            od.priority = priority;
+        }
+        config.zones = self.zones.clone();
+        config.rules = self.rules.clone();
+        config.loitering = self.loitering.clone();
+        config
+    }
+
+    /// `[storage]` with this camera's `storage` table layered over it
+    pub fn storage(&self, global: &WarmConfig) -> WarmConfig {
+        let mut config = global.clone();
+        let storage = &self.storage;
+        if let Some(secs) = storage.pre_padding_secs {
+            config.pre_padding_secs = secs;
+        }
+        if let Some(secs) = storage.post_padding_secs {
+            config.post_padding_secs = secs;
+        }
+        if let Some(format) = storage.format {
+            config.format = format;
+        }
+        if let Some(mode) = storage.recording_mode.or(self.recording_mode) {
+            config.recording_mode = mode;
+        }
+        if let Some(secs) = storage.continuous_chunk_secs {
+            config.continuous_chunk_secs = secs;
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneKind {
+    /// Motion only counts inside include zones, when there are any
+    #[default]
+    Include,
+    /// Motion is ignored here, e.g. a street or a neighbour's window
+    Exclude,
+    /// Only referenced by rules and loitering, motion is left unmasked
+    Area,
+}
+
+/// `[[cameras.zones]]` polygon in normalized frame coordinates (0.0–1.0)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZoneConfig {
+    /// Recorded on motion entries when motion falls inside an include zone,
+    /// and referenced by rules and loitering
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub kind: ZoneKind,
+    pub points: Vec<[f32; 2]>,
+}
+
+/// `[[cameras.rules]]` evaluated on tracked objects
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    /// Object classes the rule applies to, all when empty
+    #[serde(default)]
+    pub classes: Vec<String>,
+    #[serde(flatten)]
+    pub kind: RuleKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleKind {
+    /// Crossing the segment between two normalized points
+    Line {
+        points: [[f32; 2]; 2],
+        #[serde(default)]
+        direction: LineDirection,
+    },
+    /// Entering, leaving or staying in a named `[[cameras.zones]]` polygon
+    Zone {
+        zone: String,
+        trigger: ZoneTrigger,
+        #[serde(default = "default_dwell_secs")]
+        dwell_secs: u64,
+    },
+}
+
+/// Side of a line an object crosses over to, looking from its first point
+/// towards its second
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineDirection {
+    #[default]
+    Both,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneTrigger {
+    Enter,
+    Exit,
+    /// Inside for at least `dwell_secs`
+    Dwell,
+}
+
+fn default_dwell_secs() -> u64 {
+    10
+}
+
+/// `[[cameras.loitering]]`: objects staying in a zone, or anywhere in view
+/// without one, for at least `min_secs`
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoiteringConfig {
+    pub name: String,
+    /// Object classes watched, all when empty
+    #[serde(default)]
+    pub classes: Vec<String>,
+    /// Named `[[cameras.zones]]` polygon, the whole frame when unset
+    #[serde(default)]
+    pub zone: Option<String>,
+    #[serde(default = "default_loitering_secs")]
+    pub min_secs: u64,
+}
+
+fn default_loitering_secs() -> u64 {
+    60
+}
+
+/// Per-camera `[cameras.analytics]` overrides
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AnalyticsOverride {
+    pub enabled: Option<bool>,
+    pub sample_fps: Option<u32>,
+    pub tamper_detection: Option<bool>,
+}
+
+/// Per-camera `[cameras.object_detection]` overrides
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ObjectDetectionOverride {
+    pub enabled: Option<bool>,
+    pub model_path: Option<String>,
+    pub confidence_threshold: Option<f32>,
+    pub classes: Option<Vec<String>>,
+    pub backend: Option<DetectorBackend>,
+    pub labels_path: Option<String>,
+    pub priority: Option<u8>,
+}
+
+/// Per-camera `[cameras.storage]` overrides. Paths, retention and the cold
+/// tier stay global.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StorageOverride {
+    pub pre_padding_secs: Option<u64>,
+    pub post_padding_secs: Option<u64>,
+    pub format: Option<StorageFormat>,
+    pub recording_mode: Option<RecordingMode>,
+    pub continuous_chunk_secs: Option<u64>,
+}
+
+/// Output format of the object detection model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectorBackend {
+    /// NMS-free `logits` and `pred_boxes` outputs
+    #[default]
+    Yolo26,
+    /// Single `[1, 4 + classes, anchors]` output that needs NMS, as exported
+    /// by Ultralytics for YOLOv5u and YOLOv8
+    #[serde(alias = "yolov5")]
+    Yolov8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestBackend {
+    /// In-process RTSP client over TCP interleaved RTP (H.264 only)
+    #[default]
+    Native,
+    /// `ffmpeg -c copy -f mpegts` subprocess
+    Ffmpeg,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingMode {
+    /// Write motion-triggered event files only
+    #[default]
+    Motion,
+    /// Record every segment into chunk files, plus motion event files
+    Continuous,
+    /// Write only events that contain detected objects
+    ObjectsOnly,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageFormat {
+    /// Concatenated MPEG-TS GOPs as received from the camera
+    #[default]
+    Ts,
+    /// Fragmented MP4 (CMAF), one fragment per GOP
+    Fmp4,
+}
+
+impl StorageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            StorageFormat::Ts => "ts",
+            StorageFormat::Fmp4 => "mp4",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext {
+            "ts" => Some(StorageFormat::Ts),
+            "mp4" => Some(StorageFormat::Fmp4),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -88,6 +392,15 @@ pub struct ObjectDetectionConfig {
     pub confidence_threshold: f32,
     #[serde(default = "default_classes")]
     pub classes: Vec<String>,
+    #[serde(default)]
+    pub backend: DetectorBackend,
+    /// Class names one per line, in model output order. COCO when unset.
+    #[serde(default)]
+    pub labels_path: Option<String>,
+    /// Cameras with a higher priority are served first when the shared
+    /// inference queue backs up
+    #[serde(default)]
+    pub priority: u8,
 }
 
 impl Default for ObjectDetectionConfig {
@@ -97,18 +410,89 @@ impl Default for ObjectDetectionConfig {
             model_path: default_model_path(),
             confidence_threshold: default_confidence_threshold(),
             classes: default_classes(),
+            backend: DetectorBackend::default(),
+            labels_path: None,
+            priority: 0,
+        }
+    }
+}
+
+/// `[analytics.inference]`: the object detection sessions shared by all
+/// cameras using the same model
+#[derive(Debug, Clone, Deserialize)]
+pub struct InferenceConfig {
+    /// Threads ONNX Runtime uses within an operator, per model
+    #[serde(default = "default_intra_threads")]
+    pub intra_threads: usize,
+    /// Threads ONNX Runtime runs independent operators on, per model
+    #[serde(default = "default_inter_threads")]
+    pub inter_threads: usize,
+    /// Most frames run through the model at once
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize,
+    /// How long a batch waits to fill up once it has a frame
+    #[serde(default = "default_batch_wait_ms")]
+    pub batch_wait_ms: u64,
+    /// Frames waiting beyond this are dropped, lowest priority first
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+}
+
+impl Default for InferenceConfig {
+    fn default() -> Self {
+        Self {
+            intra_threads: default_intra_threads(),
+            inter_threads: default_inter_threads(),
+            max_batch: default_max_batch(),
+            batch_wait_ms: default_batch_wait_ms(),
+            queue_size: default_queue_size(),
         }
     }
 }
 
+fn default_intra_threads() -> usize {
+    4
+}
+
+fn default_inter_threads() -> usize {
+    1
+}
+
+fn default_max_batch() -> usize {
+    8
+}
+
+fn default_batch_wait_ms() -> u64 {
+    10
+}
+
+fn default_queue_size() -> usize {
+    32
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AnalyticsConfig {
     #[serde(default)]
     pub enabled: bool,
     #[serde(default = "default_sample_fps")]
     pub sample_fps: u32,
+    /// Alert when the lens is covered, the camera is redirected or
+    /// defocused, or the picture suddenly goes dark
+    #[serde(default)]
+    pub tamper_detection: bool,
     #[serde(default)]
     pub object_detection: ObjectDetectionConfig,
+    #[serde(default)]
+    pub inference: InferenceConfig,
+    /// Set per camera from `[[cameras.zones]]`
+    #[serde(skip)]
+    pub zones: Vec<ZoneConfig>,
+    /// Set per camera from `[[cameras.rules]]`
+    #[serde(skip)]
+    pub rules: Vec<RuleConfig>,
+    /// Set per camera from `[[cameras.loitering]]`
+    #[serde(skip)]
+    pub loitering: Vec<LoiteringConfig>,
 }
 
 impl Default for AnalyticsConfig {
@@ -116,7 +500,12 @@ impl Default for AnalyticsConfig {
         Self {
             enabled: false,
             sample_fps: default_sample_fps(),
+            tamper_detection: false,
             object_detection: ObjectDetectionConfig::default(),
+            inference: InferenceConfig::default(),
+            zones: Vec::new(),
+            rules: Vec::new(),
+            loitering: Vec::new(),
         }
     }
 }
@@ -137,6 +526,71 @@ fn default_warm_post_padding_secs() -> u64 {
     10
 }
 
+fn default_cold_archive_after_hours() -> u64 {
+    48
+}
+
+fn default_cold_retention_days() -> u64 {
+    60
+}
+
+fn default_cold_height() -> u32 {
+    480
+}
+
+fn default_cold_fps() -> u32 {
+    5
+}
+
+fn default_cold_scan_interval_secs() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColdConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_cold_archive_after_hours")]
+    pub archive_after_hours: u64,
+    #[serde(default = "default_cold_retention_days")]
+    pub retention_days: u64,
+    #[serde(default = "default_cold_height")]
+    pub height: u32,
+    #[serde(default = "default_cold_fps")]
+    pub fps: u32,
+    #[serde(default = "default_cold_scan_interval_secs")]
+    pub scan_interval_secs: u64,
+}
+
+impl Default for ColdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            archive_after_hours: default_cold_archive_after_hours(),
+            retention_days: default_cold_retention_days(),
+            height: default_cold_height(),
+            fps: default_cold_fps(),
+            scan_interval_secs: default_cold_scan_interval_secs(),
+        }
+    }
+}
+
+fn default_continuous_chunk_secs() -> u64 {
+    3600
+}
+
+fn default_warm_max_age_hours() -> u64 {
+    48
+}
+
+fn default_warm_min_free_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_warm_retention_interval_secs() -> u64 {
+    300
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct WarmConfig {
     #[serde(default = "default_warm_enabled")]
@@ -147,6 +601,22 @@ pub struct WarmConfig {
     pub pre_padding_secs: u64,
     #[serde(default = "default_warm_post_padding_secs")]
     pub post_padding_secs: u64,
+    #[serde(default)]
+    pub format: StorageFormat,
+    #[serde(default)]
+    pub recording_mode: RecordingMode,
+    #[serde(default = "default_continuous_chunk_secs")]
+    pub continuous_chunk_secs: u64,
+    #[serde(default = "default_warm_max_age_hours")]
+    pub max_age_hours: u64,
+    #[serde(default)]
+    pub max_bytes_per_camera: u64,
+    #[serde(default = "default_warm_min_free_bytes")]
+    pub min_free_bytes: u64,
+    #[serde(default = "default_warm_retention_interval_secs")]
+    pub retention_interval_secs: u64,
+    #[serde(default)]
+    pub cold: ColdConfig,
 }
 
 impl Default for WarmConfig {
@@ -156,6 +626,14 @@ impl Default for WarmConfig {
             data_dir: default_warm_data_dir(),
             pre_padding_secs: default_warm_pre_padding_secs(),
             post_padding_secs: default_warm_post_padding_secs(),
+            format: StorageFormat::default(),
+            recording_mode: RecordingMode::default(),
+            continuous_chunk_secs: default_continuous_chunk_secs(),
+            max_age_hours: default_warm_max_age_hours(),
+            max_bytes_per_camera: 0,
+            min_free_bytes: default_warm_min_free_bytes(),
+            retention_interval_secs: default_warm_retention_interval_secs(),
+            cold: ColdConfig::default(),
         }
     }
 }
@@ -210,3 +688,116 @@ impl Config {
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [analytics]
+            enabled = true
+            [analytics.object_detection]
+            enabled = true
+            classes = ["person"]
+            [analytics.inference]
+            intra_threads = 2
+            [storage]
+            pre_padding_secs = 5
+
+            [[cameras]]
+            id = "driveway"
+            url = "rtsp://cam/1"
+            [cameras.analytics]
+            sample_fps = 10
+            tamper_detection = true
+            [cameras.object_detection]
+            classes = ["car", "truck"]
+            confidence_threshold = 0.7
+            backend = "yolov5"
+            labels_path = "models/plates.txt"
+            priority = 2
+            [cameras.storage]
+            post_padding_secs = 30
+            recording_mode = "continuous"
+            [[cameras.zones]]
+            name = "gate"
+            points = [[0.0, 0.5], [0.5, 0.5], [0.5, 1.0]]
+            [[cameras.zones]]
+            kind = "exclude"
+            points = [[0.5, 0.0], [1.0, 0.0], [1.0, 0.5]]
+            [[cameras.rules]]
+            name = "inbound"
+            type = "line"
+            points = [[0.2, 0.6], [0.8, 0.6]]
+            direction = "left"
+            [[cameras.rules]]
+            name = "car at gate"
+            type = "zone"
+            zone = "gate"
+            trigger = "dwell"
+            classes = ["car"]
+            [[cameras.loitering]]
+            name = "lurking"
+            zone = "gate"
+            classes = ["person"]
+
+            [[cameras]]
+            id = "indoor"
+            url = "rtsp://cam/2"
+            recording_mode = "objects_only"
+            "#,
+        )
+        .unwrap();
+
+        let driveway = &config.cameras[0];
+        let analytics = driveway.analytics(&config.analytics);
+        assert!(analytics.enabled);
+        assert_eq!(analytics.sample_fps, 10);
+        assert!(analytics.tamper_detection);
+        assert_eq!(analytics.object_detection.classes, vec!["car", "truck"]);
+        assert_eq!(analytics.object_detection.confidence_threshold, 0.7);
+        assert_eq!(analytics.object_detection.backend, DetectorBackend::Yolov8);
+        assert_eq!(
+            analytics.object_detection.labels_path.as_deref(),
+            Some("models/plates.txt")
+        );
+        assert_eq!(analytics.object_detection.priority, 2);
+        assert_eq!(analytics.inference.intra_threads, 2);
+        assert_eq!(analytics.inference.max_batch, 8);
+        assert_eq!(analytics.zones.len(), 2);
+        assert_eq!(analytics.zones[0].kind, ZoneKind::Include);
+        assert_eq!(analytics.zones[1].kind, ZoneKind::Exclude);
+        assert!(matches!(
+            analytics.rules[0].kind,
+            RuleKind::Line {
+                direction: LineDirection::Left,
+                ..
+            }
+        ));
+        assert!(matches!(
+            analytics.rules[1].kind,
+            RuleKind::Zone {
+                trigger: ZoneTrigger::Dwell,
+                dwell_secs: 10,
+                ..
+            }
+        ));
+        assert_eq!(analytics.rules[1].classes, vec!["car"]);
+        assert_eq!(analytics.loitering[0].zone.as_deref(), Some("gate"));
+        assert_eq!(analytics.loitering[0].min_secs, 60);
+        let storage = driveway.storage(&config.storage);
+        assert_eq!(storage.pre_padding_secs, 5);
+        assert_eq!(storage.post_padding_secs, 30);
+        assert_eq!(storage.recording_mode, RecordingMode::Continuous);
+
+        let indoor = &config.cameras[1];
+        assert_eq!(indoor.analytics(&config.analytics).sample_fps, 5);
+        assert_eq!(
+            indoor.storage(&config.storage).recording_mode,
+            RecordingMode::ObjectsOnly
+        );
+    }
+}
@@ -13,13 +13,17 @@ mod install;
 mod storage;
 mod update;
 
-use analytics::ObjectDetector;
 use api::AppState;
+use buffer::cold::ColdArchiver;
+use buffer::continuous::ContinuousRecorder;
 use buffer::warm::WarmWriter;
 use buffer::HotBuffer;
-use camera::FfmpegPipeline;
-use config::Config;
-use storage::{DetectionStore, MotionStore, WarmEventIndex};
+use camera::{Backoff, HealthStore, Pipeline, RtspError};
+use config::{Config, IngestBackend, RecordingMode};
+use storage::retention::{RetentionPolicy, RetentionWorker};
+use storage::{
+    AlertStore, DetectionStore, EventMetadataStore, MotionStore, RecordingIndex, WarmEventIndex,
+};
 
 fn dispatch_subcommand() -> bool {
     let args: Vec<String> = std::env::args().skip(1).collect();
@@ -76,45 +80,103 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let camera_ids: Vec<String> = config.cameras.iter().map(|c| c.id.clone()).collect();
     let motion_store = MotionStore::new(&camera_ids);
     let detection_store = DetectionStore::new(&camera_ids);
+    let alert_store = AlertStore::new(&camera_ids);
+    // Detection substreams are tracked too, and reported with their camera
+    let health_ids: Vec<String> = config
+        .cameras
+        .iter()
+        .flat_map(|c| {
+            let detect = c.detect_url.is_some() && c.analytics(&config.analytics).enabled;
+            std::iter::once(c.id.clone()).chain(detect.then(|| camera::detect_id(&c.id)))
+        })
+        .collect();
+    let health_store = HealthStore::new(&health_ids);
 
-    let object_detector = if config.analytics.enabled && config.analytics.object_detection.enabled {
-        match ObjectDetector::new(
-            &config.analytics.object_detection.model_path,
-            config.analytics.object_detection.confidence_threshold,
-            config.analytics.object_detection.classes.clone(),
-        ) {
-            Ok(detector) => {
-                tracing::info!(
-                    model = %config.analytics.object_detection.model_path,
-                    "object detector loaded"
-                );
-                Some(Arc::new(detector))
-            }
-            Err(e) => {
-                tracing::error!(error = %e, "failed to load object detector, continuing without it");
-                None
-            }
-        }
+    let warm_index = if config.storage.enabled {
+        let index = WarmEventIndex::new(
+            &camera_ids,
+            std::path::PathBuf::from(&config.storage.data_dir),
+        );
+        index.recover();
+        index.scan();
+        Some(index)
     } else {
         None
     };
 
-    let warm_index = if config.storage.enabled {
-        let index = WarmEventIndex::new(
+    let recording_index = if config.storage.enabled {
+        let index = RecordingIndex::new(
             &camera_ids,
             std::path::PathBuf::from(&config.storage.data_dir),
         );
+        index.recover();
         index.scan();
         Some(index)
     } else {
         None
     };
 
+    let metadata_store = EventMetadataStore::new(
+        &camera_ids,
+        std::path::PathBuf::from(&config.storage.data_dir),
+    );
+    if config.storage.enabled {
+        metadata_store.load();
+    }
+
+    let cold_index = if config.storage.enabled && config.storage.cold.enabled {
+        let index = WarmEventIndex::new_cold(
+            &camera_ids,
+            std::path::PathBuf::from(&config.storage.data_dir),
+        );
+        index.recover();
+        index.scan();
+        Some(index)
+    } else {
+        None
+    };
+
+    if let Some(warm) = &warm_index {
+        let indexes: Vec<&WarmEventIndex> = std::iter::once(warm).chain(&cold_index).collect();
+        metadata_store.reconcile(&indexes);
+    }
+
+    let retention_handle = warm_index.as_ref().map(|index| {
+        let worker = RetentionWorker::new(
+            index.clone(),
+            cold_index.clone(),
+            recording_index.clone(),
+            metadata_store.clone(),
+            std::path::PathBuf::from(&config.storage.data_dir),
+            RetentionPolicy {
+                max_age_hours: config.storage.max_age_hours,
+                max_bytes_per_camera: config.storage.max_bytes_per_camera,
+                min_free_bytes: config.storage.min_free_bytes,
+                interval_secs: config.storage.retention_interval_secs,
+            },
+        );
+        tokio::spawn(worker.run())
+    });
+
+    let cold_handle = match (&warm_index, &cold_index) {
+        (Some(warm), Some(cold)) => {
+            let archiver = ColdArchiver::new(
+                warm.clone(),
+                cold.clone(),
+                metadata_store.clone(),
+                &config.storage.cold,
+            );
+            Some(tokio::spawn(archiver.run()))
+        }
+        _ => None,
+    };
+
     let shutdown = Arc::new(AtomicBool::new(false));
     let mut handles = Vec::new();
     let mut analyzer_handles = Vec::new();
     let mut warm_handles = Vec::new();
     let mut buffers_map: HashMap<String, Arc<RwLock<HotBuffer>>> = HashMap::new();
+    let mut inference_services = HashMap::new();
 
     for cam_config in config.cameras {
         let buffer = HotBuffer::new(cam_config.id.clone(), config.buffer.hot_duration_secs);
@@ -122,18 +184,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let camera_id = cam_config.id.clone();
         let shutdown_clone = Arc::clone(&shutdown);
 
+        let analytics_config = cam_config.analytics(&config.analytics);
+        let storage_config = cam_config.storage(&config.storage);
+
         if config.storage.enabled {
             let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
             buffer.write().unwrap().set_eviction_sender(tx);
+            let recorder = match (&recording_index, storage_config.recording_mode) {
+                (Some(index), RecordingMode::Continuous) => Some(ContinuousRecorder::new(
+                    camera_id.clone(),
+                    storage_config.continuous_chunk_secs,
+                    index.clone(),
+                )),
+                _ => None,
+            };
             let writer = WarmWriter::new(
                 rx,
                 motion_store.clone(),
                 detection_store.clone(),
-                std::path::PathBuf::from(&config.storage.data_dir),
+                alert_store.clone(),
                 camera_id.clone(),
-                config.storage.pre_padding_secs,
-                config.storage.post_padding_secs,
+                &storage_config,
                 warm_index.clone(),
+                metadata_store.clone(),
+                recorder,
             );
             let warm_handle = tokio::spawn(writer.run());
             warm_handles.push(warm_handle);
@@ -141,42 +215,108 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         buffers_map.insert(camera_id.clone(), Arc::clone(&buffer));
 
+        // The substream gets its own ingest and hot buffer, read only by the
+        // analyzer and never recorded
+        let detect_buffer = match &cam_config.detect_url {
+            Some(url) if analytics_config.enabled => {
+                let detect_id = camera::detect_id(&camera_id);
+                let detect_buffer =
+                    HotBuffer::new(detect_id.clone(), config.buffer.hot_duration_secs);
+                let detect_config = config::CameraConfig {
+                    id: detect_id.clone(),
+                    url: url.clone(),
+                    detect_url: None,
+                    ..cam_config.clone()
+                };
+                let detect_clone = Arc::clone(&detect_buffer);
+                let shutdown_clone = Arc::clone(&shutdown);
+                let health = health_store.clone();
+                let handle = tokio::spawn(async move {
+                    run_camera(detect_config, detect_clone, health, shutdown_clone).await;
+                });
+                handles.push((detect_id, handle, Arc::clone(&detect_buffer)));
+                Some(detect_buffer)
+            }
+            _ => None,
+        };
+
+        let health = health_store.clone();
         let handle = tokio::spawn(async move {
-            run_camera(cam_config, buffer_clone, shutdown_clone).await;
+            run_camera(cam_config, buffer_clone, health, shutdown_clone).await;
         });
 
         handles.push((camera_id.clone(), handle, Arc::clone(&buffer)));
 
-        if config.analytics.enabled {
-            let det_store = if object_detector.is_some() {
-                Some(detection_store.clone())
+        if analytics_config.enabled {
+            let detection_config = &analytics_config.object_detection;
+            let obj_det = if detection_config.enabled {
+                // Cameras on the same model share one session; a model that
+                // failed to load is not retried for the next camera
+                let key = (
+                    detection_config.model_path.clone(),
+                    detection_config.backend,
+                    detection_config.labels_path.clone(),
+                );
+                let service = inference_services.entry(key).or_insert_with(|| {
+                    match analytics::InferenceService::start(
+                        detection_config,
+                        &analytics_config.inference,
+                    ) {
+                        Ok(service) => {
+                            tracing::info!(
+                                model = %detection_config.model_path,
+                                backend = ?detection_config.backend,
+                                "object detector loaded"
+                            );
+                            Some(service)
+                        }
+                        Err(e) => {
+                            tracing::error!(
+                                model = %detection_config.model_path,
+                                error = %e,
+                                "failed to load object detector, continuing without it"
+                            );
+                            None
+                        }
+                    }
+                });
+                service
+                    .as_ref()
+                    .map(|s| s.client(&camera_id, detection_config))
             } else {
                 None
             };
-
-            let obj_det = object_detector.as_ref().and_then(|_| {
-                ObjectDetector::new(
-                    &config.analytics.object_detection.model_path,
-                    config.analytics.object_detection.confidence_threshold,
-                    config.analytics.object_detection.classes.clone(),
-                )
-                .ok()
-            });
+            let det_store = obj_det.as_ref().map(|_| detection_store.clone());
 
             let analyzer_handle = analytics::spawn_analyzer(
                 camera_id,
                 buffer,
+                detect_buffer,
                 motion_store.clone(),
                 det_store,
+                alert_store.clone(),
                 obj_det,
-                config.analytics.clone(),
+                analytics_config,
                 Arc::clone(&shutdown),
             );
             analyzer_handles.push(analyzer_handle);
         }
     }
 
-    let app_state = AppState::new(buffers_map, motion_store, detection_store, warm_index);
+    // Inference workers stop once the analyzers holding their clients do
+    drop(inference_services);
+
+    let app_state = AppState::new(
+        buffers_map,
+        motion_store,
+        detection_store,
+        alert_store,
+        warm_index,
+        cold_index,
+        recording_index,
+        metadata_store,
+        health_store,
+    );
     let server_handle = tokio::spawn(async move {
         if let Err(e) = api::start_server(app_state, http_port).await {
             tracing::error!("HTTP server error: {}", e);
@@ -197,6 +337,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     server_handle.abort();
 
+    if let Some(handle) = retention_handle {
+        handle.abort();
+    }
+
+    if let Some(handle) = cold_handle {
+        handle.abort();
+    }
+
     for handle in analyzer_handles {
         handle.abort();
     }
@@ -225,6 +373,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 async fn run_camera(
     config: config::CameraConfig,
     buffer: Arc<RwLock<HotBuffer>>,
+    health: HealthStore,
     shutdown: Arc<AtomicBool>,
 ) {
     let camera_id = config.id.clone();
@@ -248,32 +397,75 @@ async fn run_camera(
         }
     });
 
+    // A stream can stall without the connection dropping
+    let stall_health = health.clone();
+    let stall_camera_id = camera_id.clone();
+    let stall_shutdown = Arc::clone(&shutdown);
+    let offline_after = std::time::Duration::from_secs(config.offline_after_secs);
+    let stall_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
+        while !stall_shutdown.load(Ordering::Relaxed) {
+            interval.tick().await;
+            stall_health.check_stall(&stall_camera_id, offline_after);
+        }
+    });
+
+    let mut backend = Pipeline::backend_for(&config.url, config.ingest);
+    if backend != config.ingest {
+        tracing::info!(camera = %camera_id, "URL not supported by the native client, using ffmpeg");
+    }
+    let mut attempts = 0u64;
+    let mut backoff = Backoff::new(
+        &camera_id,
+        std::time::Duration::from_secs(config.reconnect_delay_secs),
+        std::time::Duration::from_secs(config.reconnect_max_secs),
+    );
+
     while !shutdown.load(Ordering::Relaxed) {
-        tracing::info!(camera = %camera_id, url = %config.url, "connecting to camera");
+        tracing::info!(camera = %camera_id, url = %config.url, ?backend, "connecting to camera");
+        if attempts > 0 {
+            health.reconnecting(&camera_id);
+        }
+        attempts += 1;
 
-        let pipeline = match FfmpegPipeline::new(&config, Arc::clone(&buffer)) {
+        let pipeline = match Pipeline::new(&config, backend, Arc::clone(&buffer), health.clone()) {
             Ok(p) => p,
             Err(e) => {
                 tracing::error!(camera = %camera_id, "failed to create pipeline: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                health.error(&camera_id, &e);
+                health.disconnected(&camera_id, &e.to_string());
+                tokio::time::sleep(backoff.next_delay()).await;
                 continue;
             }
         };
 
         let shutdown_ref = Arc::clone(&shutdown);
         let camera_id_ref = camera_id.clone();
+        let segments_before = buffer.read().map(|b| b.last_sequence()).unwrap_or(0);
 
         let result = tokio::task::spawn_blocking(move || pipeline.run(&shutdown_ref)).await;
 
         match result {
             Ok(Ok(())) => {
                 tracing::info!(camera = %camera_id, "pipeline stopped normally");
+                if !shutdown.load(Ordering::Relaxed) {
+                    health.disconnected(&camera_id, "stream ended");
+                }
+            }
+            Ok(Err(RtspError::UnsupportedCodec(codec))) if backend == IngestBackend::Native => {
+                tracing::warn!(camera = %camera_id, %codec, "codec not supported natively, falling back to ffmpeg");
+                backend = IngestBackend::Ffmpeg;
+                attempts = 0;
+                continue;
             }
             Ok(Err(e)) => {
-                tracing::error!(camera = %camera_id, "pipeline error: {}", e);
+                tracing::error!(camera = %camera_id, kind = e.kind(), "pipeline error: {}", e);
+                health.error(&camera_id, &e);
+                health.disconnected(&camera_id, &e.to_string());
             }
             Err(e) => {
                 tracing::error!(camera = %camera_id, "pipeline task panicked: {}", e);
+                health.disconnected(&camera_id, "pipeline task panicked");
             }
         }
 
@@ -281,9 +473,20 @@ async fn run_camera(
             break;
         }
 
-        tracing::info!(camera = %camera_id_ref, "reconnecting in 5 seconds");
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        // A session that delivered video starts the delays over
+        let segments_after = buffer.read().map(|b| b.last_sequence()).unwrap_or(0);
+        if segments_after > segments_before {
+            backoff.reset();
+        }
+        let delay = backoff.next_delay();
+        tracing::info!(
+            camera = %camera_id_ref,
+            delay_ms = delay.as_millis() as u64,
+            "reconnecting"
+        );
+        tokio::time::sleep(delay).await;
     }
 
     stats_handle.abort();
+    stall_handle.abort();
 }
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+/// What raised an alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    LineCrossed,
+    ZoneEnter,
+    ZoneExit,
+    ZoneDwell,
+    Loitering,
+    /// Lens covered or blacked out
+    TamperCovered,
+    /// Camera pointed somewhere else
+    TamperRedirected,
+    TamperDefocused,
+    /// Sudden large drop in brightness
+    TamperDark,
+}
+
+impl AlertKind {
+    pub fn is_tamper(self) -> bool {
+        matches!(
+            self,
+            AlertKind::TamperCovered
+                | AlertKind::TamperRedirected
+                | AlertKind::TamperDefocused
+                | AlertKind::TamperDark
+        )
+    }
+
+    /// Alerts that last until an end time is set, rather than being instant
+    pub fn is_lasting(self) -> bool {
+        self == AlertKind::Loitering || self.is_tamper()
+    }
+}
+
+/// A rule hit or other analytics alert against a hot buffer segment
+#[derive(Debug, Clone)]
+pub struct AlertEntry {
+    pub id: u64,
+    pub segment_sequence: u64,
+    pub time_ns: u64,
+    /// Set once a lasting condition such as loitering is over
+    pub end_ns: Option<u64>,
+    pub kind: AlertKind,
+    /// Name of the rule that fired
+    pub rule: String,
+    pub track_id: Option<u64>,
+    pub object_class: Option<String>,
+    /// Representative JPEG frame, for alerts that keep one
+    pub frame_jpeg: Option<Vec<u8>>,
+    /// The view before a tamper alert, with `frame_jpeg` the view after
+    pub before_jpeg: Option<Vec<u8>>,
+}
+
+pub struct AlertStore {
+    cameras: Arc<HashMap<String, RwLock<VecDeque<AlertEntry>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl AlertStore {
+    pub fn new(camera_ids: &[String]) -> Self {
+        let mut cameras = HashMap::new();
+        for id in camera_ids {
+            cameras.insert(id.clone(), RwLock::new(VecDeque::new()));
+        }
+        Self {
+            cameras: Arc::new(cameras),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Store `entry` under a fresh id, which is returned
+    pub fn insert(&self, camera_id: &str, mut entry: AlertEntry) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        entry.id = id;
+        if let Some(lock) = self.cameras.get(camera_id) {
+            lock.write().unwrap().push_back(entry);
+        }
+        id
+    }
+
+    pub fn set_end(&self, camera_id: &str, id: u64, end_ns: u64) {
+        if let Some(lock) = self.cameras.get(camera_id) {
+            if let Some(entry) = lock.write().unwrap().iter_mut().find(|e| e.id == id) {
+                entry.end_ns = Some(end_ns);
+            }
+        }
+    }
+
+    pub fn get(&self, camera_id: &str, id: u64) -> Option<AlertEntry> {
+        self.cameras
+            .get(camera_id)?
+            .read()
+            .unwrap()
+            .iter()
+            .find(|e| e.id == id)
+            .cloned()
+    }
+
+    pub fn get_alerts(&self, camera_id: &str) -> Vec<AlertEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => lock.read().unwrap().iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_for_sequence(&self, camera_id: &str, segment_sequence: u64) -> Vec<AlertEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => lock
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|e| e.segment_sequence == segment_sequence)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn cleanup(&self, camera_id: &str, min_sequence: u64) {
+        if let Some(lock) = self.cameras.get(camera_id) {
+            // Alerts can be raised out of sequence order. Lasting ones stay
+            // until they end.
+            lock.write().unwrap().retain(|e| {
+                e.segment_sequence >= min_sequence || (e.kind.is_lasting() && e.end_ns.is_none())
+            });
+        }
+    }
+}
+
+impl Clone for AlertStore {
+    fn clone(&self) -> Self {
+        Self {
+            cameras: Arc::clone(&self.cameras),
+            next_id: Arc::clone(&self.next_id),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+pub const PARTIAL_EXTENSION: &str = "partial";
+
+/// Temporary path a file is written to before being renamed into place
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(PARTIAL_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// Write `data` to a `.partial` file, fsync it, rename it over `path` and
+/// fsync the directory so a crash never leaves a truncated file at `path`.
+pub async fn write_atomic(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let tmp_path = partial_path(path);
+    let result = match write_synced(&tmp_path, data).await {
+        Ok(()) => commit(&tmp_path, path).await,
+        Err(e) => Err(e),
+    };
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+async fn write_synced(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let mut file = tokio::fs::File::create(path).await?;
+    file.write_all(data).await?;
+    file.sync_all().await
+}
+
+/// `write_atomic` for callers outside the runtime, such as startup recovery
+pub fn write_atomic_sync(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    let tmp_path = partial_path(path);
+    let result = std::fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp_path, path))
+        .and_then(|()| match path.parent() {
+            Some(parent) => std::fs::File::open(parent)?.sync_all(),
+            None => Ok(()),
+        });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Rename an already written `.partial` file into place and persist the rename
+pub async fn commit(tmp_path: &Path, path: &Path) -> Result<(), std::io::Error> {
+    tokio::fs::rename(tmp_path, path).await?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::File::open(parent).await?.sync_all().await?;
+    }
+    Ok(())
+}
@@ -2,11 +2,15 @@ use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
+use super::BoundingBox;
+
 pub struct DetectionEntry {
     pub id: u64,
     pub segment_sequence: u64,
     pub object_class: String,
     pub confidence: f32,
+    /// Full-frame normalized box, when the detector reported one
+    pub bbox: Option<BoundingBox>,
     pub frame_jpeg: Vec<u8>,
 }
 
@@ -15,21 +19,39 @@ pub struct DetectionSnapshot {
     pub segment_sequence: u64,
     pub object_class: String,
     pub confidence: f32,
+    pub bbox: Option<BoundingBox>,
+}
+
+/// An object followed across sampled frames, in stream time
+#[derive(Debug, Clone)]
+pub struct TrackEntry {
+    pub id: u64,
+    pub object_class: String,
+    pub confidence: f32,
+    pub start_ns: u64,
+    pub end_ns: u64,
+    /// Newest segment the track was seen in, for cleanup
+    pub last_sequence: u64,
+    pub trajectory: Vec<(u64, BoundingBox)>,
 }
 
 pub struct DetectionStore {
     cameras: Arc<HashMap<String, RwLock<VecDeque<DetectionEntry>>>>,
+    tracks: Arc<HashMap<String, RwLock<Vec<TrackEntry>>>>,
     next_id: Arc<AtomicU64>,
 }
 
 impl DetectionStore {
     pub fn new(camera_ids: &[String]) -> Self {
         let mut cameras = HashMap::new();
+        let mut tracks = HashMap::new();
         for id in camera_ids {
             cameras.insert(id.clone(), RwLock::new(VecDeque::new()));
+            tracks.insert(id.clone(), RwLock::new(Vec::new()));
         }
         Self {
             cameras: Arc::new(cameras),
+            tracks: Arc::new(tracks),
             next_id: Arc::new(AtomicU64::new(1)),
         }
     }
@@ -40,6 +62,7 @@ impl DetectionStore {
         segment_sequence: u64,
         object_class: String,
         confidence: f32,
+        bbox: Option<BoundingBox>,
         frame_jpeg: Vec<u8>,
     ) -> u64 {
         let id = self.next_id.fetch_add(1, Ordering::Relaxed);
@@ -49,6 +72,7 @@ impl DetectionStore {
                 segment_sequence,
                 object_class,
                 confidence,
+                bbox,
                 frame_jpeg,
             });
         }
@@ -66,6 +90,31 @@ impl DetectionStore {
                         segment_sequence: e.segment_sequence,
                         object_class: e.object_class.clone(),
                         confidence: e.confidence,
+                        bbox: e.bbox,
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_for_sequence(
+        &self,
+        camera_id: &str,
+        segment_sequence: u64,
+    ) -> Vec<DetectionSnapshot> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => {
+                let entries = lock.read().unwrap();
+                entries
+                    .iter()
+                    .filter(|e| e.segment_sequence == segment_sequence)
+                    .map(|e| DetectionSnapshot {
+                        id: e.id,
+                        segment_sequence: e.segment_sequence,
+                        object_class: e.object_class.clone(),
+                        confidence: e.confidence,
+                        bbox: e.bbox,
                     })
                     .collect()
             }
@@ -95,6 +144,31 @@ impl DetectionStore {
         }
     }
 
+    /// Insert a track or replace it with its latest state
+    pub fn update_track(&self, camera_id: &str, track: TrackEntry) {
+        if let Some(lock) = self.tracks.get(camera_id) {
+            let mut tracks = lock.write().unwrap();
+            match tracks.iter_mut().find(|t| t.id == track.id) {
+                Some(existing) => *existing = track,
+                None => tracks.push(track),
+            }
+        }
+    }
+
+    /// Tracks seen at some point between `start_ns` and `end_ns`
+    pub fn tracks_between(&self, camera_id: &str, start_ns: u64, end_ns: u64) -> Vec<TrackEntry> {
+        match self.tracks.get(camera_id) {
+            Some(lock) => lock
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|t| t.start_ns < end_ns && t.end_ns >= start_ns)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
     pub fn cleanup(&self, camera_id: &str, min_sequence: u64) {
         if let Some(lock) = self.cameras.get(camera_id) {
             let mut entries = lock.write().unwrap();
@@ -106,6 +180,11 @@ impl DetectionStore {
                 }
             }
         }
+        if let Some(lock) = self.tracks.get(camera_id) {
+            lock.write()
+                .unwrap()
+                .retain(|t| t.last_sequence >= min_sequence);
+        }
     }
 }
 
@@ -113,6 +192,7 @@ impl Clone for DetectionStore {
     fn clone(&self) -> Self {
         Self {
             cameras: Arc::clone(&self.cameras),
+            tracks: Arc::clone(&self.tracks),
             next_id: Arc::clone(&self.next_id),
         }
     }
@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::atomic::write_atomic;
+use super::{AlertKind, BoundingBox, WarmEventIndex};
+
+const LOG_FILE: &str = "metadata.jsonl";
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotionSample {
+    pub offset_ms: u64,
+    pub duration_ms: u64,
+    pub score: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectionRecord {
+    pub offset_ms: u64,
+    pub object_class: String,
+    pub confidence: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BoundingBox>,
+}
+
+/// A rule hit during the event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub offset_ms: u64,
+    /// End of a lasting alert such as loitering, when it ended in time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_offset_ms: Option<u64>,
+    pub kind: AlertKind,
+    pub rule: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_class: Option<String>,
+    /// Before and after snapshots are stored for this alert
+    #[serde(default)]
+    pub has_snapshots: bool,
+}
+
+/// Views of the camera before and after a tamper alert
+pub struct AlertSnapshots {
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackPoint {
+    pub offset_ms: u64,
+    pub bbox: BoundingBox,
+}
+
+/// One tracked object within an event, in place of its many detections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackRecord {
+    pub id: u64,
+    pub object_class: String,
+    pub confidence: f32,
+    pub start_offset_ms: u64,
+    pub end_offset_ms: u64,
+    pub trajectory: Vec<TrackPoint>,
+}
+
+/// Motion timeline and detections for a single stored event, keyed by the
+/// event's `start_pts_ns`. Offsets are relative to the event start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventMetadata {
+    pub start_pts_ns: u64,
+    #[serde(default)]
+    pub motion: Vec<MotionSample>,
+    #[serde(default)]
+    pub detections: Vec<DetectionRecord>,
+    #[serde(default)]
+    pub tracks: Vec<TrackRecord>,
+    #[serde(default)]
+    pub alerts: Vec<AlertRecord>,
+    #[serde(default)]
+    pub has_thumbnail: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogRecord {
+    Insert(EventMetadata),
+    Remove { start_pts_ns: u64 },
+}
+
+/// Durable event metadata backed by an append-only JSON lines log per camera
+/// (`<data_dir>/<camera>/metadata.jsonl`). The log is replayed and compacted
+/// on startup; thumbnails are stored as JPEG files next to it.
+///
+/// The sidecar written with each event file is authoritative. The log is a
+/// quick index over them, and `reconcile` brings it back in line after a
+/// crash: entries for missing events are dropped and events the log lost are
+/// rebuilt from their sidecars.
+#[derive(Clone)]
+pub struct EventMetadataStore {
+    cameras: Arc<HashMap<String, RwLock<BTreeMap<u64, EventMetadata>>>>,
+    data_dir: PathBuf,
+}
+
+impl EventMetadataStore {
+    pub fn new(camera_ids: &[String], data_dir: PathBuf) -> Self {
+        let mut cameras = HashMap::new();
+        for id in camera_ids {
+            cameras.insert(id.clone(), RwLock::new(BTreeMap::new()));
+        }
+        Self {
+            cameras: Arc::new(cameras),
+            data_dir,
+        }
+    }
+
+    pub fn load(&self) {
+        for (camera_id, lock) in self.cameras.iter() {
+            let path = self.log_path(camera_id);
+            let content = match std::fs::read_to_string(&path) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+
+            let mut events = BTreeMap::new();
+            let mut skipped = 0usize;
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<LogRecord>(line) {
+                    Ok(LogRecord::Insert(meta)) => {
+                        events.insert(meta.start_pts_ns, meta);
+                    }
+                    Ok(LogRecord::Remove { start_pts_ns }) => {
+                        events.remove(&start_pts_ns);
+                    }
+                    // A torn trailing line is expected after a crash mid-append
+                    Err(_) => skipped += 1,
+                }
+            }
+
+            if let Err(e) = compact(&path, &events) {
+                tracing::warn!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    error = %e,
+                    "failed to compact metadata log"
+                );
+            }
+
+            tracing::info!(
+                camera = %camera_id,
+                events = events.len(),
+                skipped,
+                "loaded event metadata"
+            );
+            *lock.write().unwrap() = events;
+        }
+    }
+
+    /// Match the log to the events in `indexes`, after they were scanned
+    pub fn reconcile(&self, indexes: &[&WarmEventIndex]) {
+        for (camera_id, lock) in self.cameras.iter() {
+            let entries: Vec<_> = indexes
+                .iter()
+                .flat_map(|index| index.entries(camera_id))
+                .collect();
+            let mut events = lock.write().unwrap();
+
+            let before = events.len();
+            events
+                .retain(|start_pts_ns, _| entries.iter().any(|e| e.start_pts_ns == *start_pts_ns));
+            let dropped = before - events.len();
+
+            let mut rebuilt = 0usize;
+            for entry in &entries {
+                let Some(sidecar) = &entry.sidecar else {
+                    continue;
+                };
+                if events.contains_key(&entry.start_pts_ns) {
+                    continue;
+                }
+                let start = entry.start_pts_ns;
+                let mut alerts = sidecar.alerts.clone();
+                for (index, alert) in alerts.iter_mut().enumerate() {
+                    alert.has_snapshots = ["before", "after"]
+                        .iter()
+                        .all(|which| self.snapshot_path(camera_id, start, index, which).exists());
+                }
+                events.insert(
+                    start,
+                    EventMetadata {
+                        start_pts_ns: start,
+                        motion: sidecar.motion(),
+                        detections: sidecar.detections.clone(),
+                        tracks: sidecar.tracks.clone(),
+                        alerts,
+                        has_thumbnail: self.thumbnail_path(camera_id, start).exists(),
+                    },
+                );
+                rebuilt += 1;
+            }
+
+            if dropped == 0 && rebuilt == 0 {
+                continue;
+            }
+            let path = self.log_path(camera_id);
+            if let Err(e) = compact(&path, &events) {
+                tracing::warn!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    error = %e,
+                    "failed to rewrite metadata log"
+                );
+            }
+            tracing::info!(
+                camera = %camera_id,
+                dropped,
+                rebuilt,
+                "reconciled event metadata with sidecars"
+            );
+        }
+    }
+
+    /// Store an event's metadata with its thumbnail and the snapshots of
+    /// alerts that have them, by index into `metadata.alerts`
+    pub async fn record(
+        &self,
+        camera_id: &str,
+        mut metadata: EventMetadata,
+        thumbnail: Option<Vec<u8>>,
+        snapshots: Vec<(usize, AlertSnapshots)>,
+    ) {
+        let Some(lock) = self.cameras.get(camera_id) else {
+            return;
+        };
+
+        if let Some(jpeg) = thumbnail {
+            let path = self.thumbnail_path(camera_id, metadata.start_pts_ns);
+            match write_thumbnail(&path, &jpeg).await {
+                Ok(()) => metadata.has_thumbnail = true,
+                Err(e) => {
+                    tracing::warn!(
+                        camera = %camera_id,
+                        path = %path.display(),
+                        error = %e,
+                        "failed to write event thumbnail"
+                    );
+                }
+            }
+        }
+
+        for (index, snapshot) in snapshots {
+            let Some(alert) = metadata.alerts.get_mut(index) else {
+                continue;
+            };
+            let before = self.snapshot_path(camera_id, metadata.start_pts_ns, index, "before");
+            let after = self.snapshot_path(camera_id, metadata.start_pts_ns, index, "after");
+            let result = match write_thumbnail(&before, &snapshot.before).await {
+                Ok(()) => write_thumbnail(&after, &snapshot.after).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => alert.has_snapshots = true,
+                Err(e) => {
+                    tracing::warn!(
+                        camera = %camera_id,
+                        path = %after.display(),
+                        error = %e,
+                        "failed to write alert snapshots"
+                    );
+                }
+            }
+        }
+
+        self.append(camera_id, &LogRecord::Insert(metadata.clone()))
+            .await;
+        lock.write()
+            .unwrap()
+            .insert(metadata.start_pts_ns, metadata);
+    }
+
+    pub async fn remove(&self, camera_id: &str, start_pts_ns: u64) {
+        let Some(lock) = self.cameras.get(camera_id) else {
+            return;
+        };
+        let removed = lock.write().unwrap().remove(&start_pts_ns);
+        let Some(metadata) = removed else {
+            return;
+        };
+
+        self.append(camera_id, &LogRecord::Remove { start_pts_ns })
+            .await;
+        if metadata.has_thumbnail {
+            let _ = tokio::fs::remove_file(self.thumbnail_path(camera_id, start_pts_ns)).await;
+        }
+        for (index, alert) in metadata.alerts.iter().enumerate() {
+            if !alert.has_snapshots {
+                continue;
+            }
+            for which in ["before", "after"] {
+                let path = self.snapshot_path(camera_id, start_pts_ns, index, which);
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        }
+    }
+
+    pub fn get(&self, camera_id: &str, start_pts_ns: u64) -> Option<EventMetadata> {
+        let lock = self.cameras.get(camera_id)?;
+        lock.read().unwrap().get(&start_pts_ns).cloned()
+    }
+
+    pub fn thumbnail_path(&self, camera_id: &str, start_pts_ns: u64) -> PathBuf {
+        self.data_dir
+            .join(camera_id)
+            .join(THUMBNAIL_DIR)
+            .join(format!("{start_pts_ns}.jpg"))
+    }
+
+    /// `which` is `before` or `after`
+    pub fn snapshot_path(
+        &self,
+        camera_id: &str,
+        start_pts_ns: u64,
+        index: usize,
+        which: &str,
+    ) -> PathBuf {
+        self.data_dir
+            .join(camera_id)
+            .join(THUMBNAIL_DIR)
+            .join(format!("{start_pts_ns}_{index}_{which}.jpg"))
+    }
+
+    fn log_path(&self, camera_id: &str) -> PathBuf {
+        self.data_dir.join(camera_id).join(LOG_FILE)
+    }
+
+    async fn append(&self, camera_id: &str, record: &LogRecord) {
+        let path = self.log_path(camera_id);
+        let result = match serde_json::to_string(record) {
+            Ok(line) => append_line(&path, &line).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            tracing::error!(
+                camera = %camera_id,
+                path = %path.display(),
+                error = %e,
+                "failed to append to metadata log"
+            );
+        }
+    }
+}
+
+async fn append_line(path: &Path, line: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(format!("{line}\n").as_bytes()).await?;
+    file.sync_data().await
+}
+
+async fn write_thumbnail(path: &Path, jpeg: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    write_atomic(path, jpeg).await
+}
+
+fn compact(path: &Path, events: &BTreeMap<u64, EventMetadata>) -> Result<(), std::io::Error> {
+    let mut out = String::new();
+    for meta in events.values() {
+        out.push_str(&serde_json::to_string(&LogRecord::Insert(meta.clone()))?);
+        out.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp_path, out)?;
+    std::fs::rename(&tmp_path, path)
+}
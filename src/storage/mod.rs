@@ -1,7 +1,21 @@
+mod alert_store;
+pub mod atomic;
 mod detection_store;
+mod metadata_store;
+mod recording_index;
+mod recovery;
+pub mod retention;
+mod sidecar;
 mod store;
 pub mod warm_index;
 
-pub use detection_store::DetectionStore;
-pub use store::{MotionEntry, MotionStore};
+pub use alert_store::{AlertEntry, AlertKind, AlertStore};
+pub use detection_store::{DetectionStore, TrackEntry};
+pub use metadata_store::{
+    AlertRecord, AlertSnapshots, DetectionRecord, EventMetadata, EventMetadataStore, MotionSample,
+    TrackPoint, TrackRecord,
+};
+pub use recording_index::{ChunkGop, RecordingEntry, RecordingIndex};
+pub use sidecar::{BoundingBox, EventSidecar, SegmentInfo};
+pub use store::{MotionEntry, MotionStore, SegmentMotion};
 pub use warm_index::{EventType, WarmEventEntry, WarmEventIndex};
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use super::recovery::{self, RecoveryStats};
+use super::warm_index::parse_event_file_name;
+
+const CONTINUOUS_DIR: &str = "continuous";
+const QUARANTINE_DIR: &str = "quarantine";
+/// Parsed GOP lists kept for chunks being played back
+const GOP_CACHE_CHUNKS: usize = 16;
+
+/// Camera, chunk start and the chunk's parsed GOP list
+type CachedGops = (String, u64, Arc<Vec<ChunkGop>>);
+
+#[derive(Debug, Clone)]
+pub struct RecordingEntry {
+    pub start_pts_ns: u64,
+    pub duration_ms: u32,
+    pub file_size: u64,
+}
+
+impl RecordingEntry {
+    pub fn end_ns(&self) -> u64 {
+        self.start_pts_ns + (self.duration_ms as u64) * 1_000_000
+    }
+}
+
+/// One GOP appended to a recording chunk, so a chunk can be played back in
+/// GOP-sized segments instead of as one hour-long file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkGop {
+    /// Byte offset in the chunk file
+    pub offset: u64,
+    pub bytes: u64,
+    pub duration_ns: u64,
+}
+
+/// Index of continuous recording chunks stored as
+/// `<data_dir>/<camera>/continuous/{start_pts}_{duration_ms}.ts`, each with
+/// its GOP list in a `.json` file next to it
+#[derive(Clone)]
+pub struct RecordingIndex {
+    cameras: Arc<HashMap<String, RwLock<Vec<RecordingEntry>>>>,
+    data_dir: PathBuf,
+    /// Recently used GOP lists by camera and chunk start, oldest first
+    gop_cache: Arc<Mutex<VecDeque<CachedGops>>>,
+}
+
+impl RecordingIndex {
+    pub fn new(camera_ids: &[String], data_dir: PathBuf) -> Self {
+        let mut cameras = HashMap::new();
+        for id in camera_ids {
+            cameras.insert(id.clone(), RwLock::new(Vec::new()));
+        }
+        Self {
+            cameras: Arc::new(cameras),
+            data_dir,
+            gop_cache: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn chunk_dir(&self, camera_id: &str) -> PathBuf {
+        self.data_dir.join(camera_id).join(CONTINUOUS_DIR)
+    }
+
+    /// Finalize chunks interrupted by a crash and quarantine unreadable ones
+    pub fn recover(&self) {
+        for camera_id in self.cameras.keys() {
+            let dir = self.chunk_dir(camera_id);
+            let mut stats = RecoveryStats::default();
+            recovery::recover_chunk_dir(&dir, &dir.join(QUARANTINE_DIR), &mut stats);
+            if stats.repaired > 0 || stats.quarantined > 0 {
+                tracing::warn!(
+                    camera = %camera_id,
+                    checked = stats.checked,
+                    repaired = stats.repaired,
+                    quarantined = stats.quarantined,
+                    "recovered recording chunks"
+                );
+            }
+        }
+    }
+
+    pub fn scan(&self) {
+        for (camera_id, lock) in self.cameras.iter() {
+            let read_dir = match std::fs::read_dir(self.chunk_dir(camera_id)) {
+                Ok(rd) => rd,
+                Err(_) => continue,
+            };
+            let mut entries = Vec::new();
+            for entry in read_dir.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("ts") {
+                    continue;
+                }
+                let (start_pts_ns, duration_ms) = match parse_event_file_name(&path) {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                entries.push(RecordingEntry {
+                    start_pts_ns,
+                    duration_ms,
+                    file_size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                });
+            }
+            entries.sort_by_key(|e| e.start_pts_ns);
+            let count = entries.len();
+            *lock.write().unwrap() = entries;
+            if count > 0 {
+                tracing::info!(camera = %camera_id, chunks = count, "scanned recording chunks");
+            }
+        }
+    }
+
+    pub fn insert(&self, camera_id: &str, entry: RecordingEntry) {
+        if let Some(lock) = self.cameras.get(camera_id) {
+            let mut entries = lock.write().unwrap();
+            let pos = entries
+                .binary_search_by_key(&entry.start_pts_ns, |e| e.start_pts_ns)
+                .unwrap_or_else(|p| p);
+            entries.insert(pos, entry);
+        }
+    }
+
+    pub fn remove(&self, camera_id: &str, start_pts_ns: u64) -> Option<RecordingEntry> {
+        let lock = self.cameras.get(camera_id)?;
+        self.gop_cache
+            .lock()
+            .unwrap()
+            .retain(|(id, start, _)| !(id == camera_id && *start == start_pts_ns));
+        let mut entries = lock.write().unwrap();
+        let pos = entries
+            .binary_search_by_key(&start_pts_ns, |e| e.start_pts_ns)
+            .ok()?;
+        Some(entries.remove(pos))
+    }
+
+    pub fn entries(&self, camera_id: &str) -> Vec<RecordingEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => lock.read().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn query(&self, camera_id: &str, from_ns: u64, to_ns: u64) -> Vec<RecordingEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => {
+                let entries = lock.read().unwrap();
+                let start = entries.partition_point(|e| e.end_ns() < from_ns);
+                let end = entries.partition_point(|e| e.start_pts_ns <= to_ns);
+                entries[start..end.max(start)].to_vec()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn find(&self, camera_id: &str, start_pts_ns: u64) -> Option<RecordingEntry> {
+        let lock = self.cameras.get(camera_id)?;
+        let entries = lock.read().unwrap();
+        entries
+            .binary_search_by_key(&start_pts_ns, |e| e.start_pts_ns)
+            .ok()
+            .map(|i| entries[i].clone())
+    }
+
+    pub fn resolve_file_path(&self, camera_id: &str, entry: &RecordingEntry) -> PathBuf {
+        self.chunk_dir(camera_id)
+            .join(format!("{}_{}.ts", entry.start_pts_ns, entry.duration_ms))
+    }
+
+    pub fn gops_path(&self, camera_id: &str, entry: &RecordingEntry) -> PathBuf {
+        self.resolve_file_path(camera_id, entry)
+            .with_extension("json")
+    }
+
+    /// GOP list of a chunk, read once and then served from a small cache
+    /// while the chunk is played back
+    pub async fn load_gops(
+        &self,
+        camera_id: &str,
+        entry: &RecordingEntry,
+    ) -> Option<Arc<Vec<ChunkGop>>> {
+        let cached = self
+            .gop_cache
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, start, _)| id == camera_id && *start == entry.start_pts_ns)
+            .map(|(_, _, gops)| Arc::clone(gops));
+        if cached.is_some() {
+            return cached;
+        }
+
+        let path = self.gops_path(camera_id, entry);
+        let data = tokio::fs::read(&path).await.ok()?;
+        let gops: Vec<ChunkGop> = match serde_json::from_slice(&data) {
+            Ok(gops) => gops,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "invalid recording GOP list");
+                return None;
+            }
+        };
+
+        let gops = Arc::new(gops);
+        let mut cache = self.gop_cache.lock().unwrap();
+        if cache.len() >= GOP_CACHE_CHUNKS {
+            cache.pop_front();
+        }
+        cache.push_back((camera_id.to_string(), entry.start_pts_ns, Arc::clone(&gops)));
+        Some(gops)
+    }
+}
@@ -0,0 +1,537 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::atomic::{self, PARTIAL_EXTENSION};
+use super::warm_index::parse_event_file_name;
+use super::{ChunkGop, EventSidecar};
+use crate::buffer::nal::VideoCodec;
+use crate::buffer::ts::{self, PACKET_SIZE, SYNC_BYTE};
+
+const HEAD_BYTES: u64 = (PACKET_SIZE * 512) as u64;
+const TAIL_BYTES: u64 = (PACKET_SIZE * 2048) as u64;
+/// Whole packets read at a time when scanning a full file
+const SCAN_BLOCK_BYTES: usize = PACKET_SIZE * 8192;
+const DURATION_TOLERANCE_MS: u64 = 2000;
+/// PES bytes searched for a slice header when a keyframe isn't flagged
+const KEYFRAME_SCAN_BYTES: usize = 64 * 1024;
+/// Timestamp jumps larger than this are a discontinuity, such as the camera
+/// restarting its clock after a reconnect, not elapsed time
+const MAX_PTS_STEP: u64 = 5 * ts::PTS_CLOCK_HZ;
+
+#[derive(Debug, Default)]
+pub struct RecoveryStats {
+    pub checked: usize,
+    pub repaired: usize,
+    pub quarantined: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Intact,
+    Repair { valid_len: u64, duration_ms: u32 },
+    Quarantine(&'static str),
+}
+
+/// Check every event file in `dir`, moving leftover `.partial` files and
+/// unreadable streams into `quarantine_dir`, truncating files whose tail is
+/// torn and renaming files whose real duration is shorter than their name says.
+///
+/// Finished `.mp4` events are skipped: they are written in one piece and
+/// renamed into place, so a crash leaves only a `.partial` file behind.
+pub fn recover_dir(dir: &Path, quarantine_dir: &Path, stats: &mut RecoveryStats) {
+    recover(dir, quarantine_dir, false, stats);
+}
+
+/// Like `recover_dir`, but for continuous recording chunks: a `.partial`
+/// chunk interrupted by a crash is finalized with its measured duration
+/// instead of being quarantined.
+pub fn recover_chunk_dir(dir: &Path, quarantine_dir: &Path, stats: &mut RecoveryStats) {
+    recover(dir, quarantine_dir, true, stats);
+}
+
+fn recover(dir: &Path, quarantine_dir: &Path, finalize_partials: bool, stats: &mut RecoveryStats) {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(rd) => rd,
+        Err(_) => return,
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(PARTIAL_EXTENSION) if finalize_partials => match finalize_partial(&path) {
+                Ok(Some(new_path)) => {
+                    tracing::warn!(
+                        path = %path.display(),
+                        finalized = %new_path.display(),
+                        "finalized interrupted recording chunk"
+                    );
+                    stats.repaired += 1;
+                }
+                Ok(None) => {
+                    quarantine(&path, quarantine_dir, "unusable interrupted chunk");
+                    stats.quarantined += 1;
+                }
+                Err(e) => {
+                    tracing::error!(path = %path.display(), error = %e, "failed to finalize recording chunk");
+                    quarantine(&path, quarantine_dir, "finalize failed");
+                    stats.quarantined += 1;
+                }
+            },
+            Some(PARTIAL_EXTENSION) => {
+                quarantine(&path, quarantine_dir, "interrupted write");
+                stats.quarantined += 1;
+            }
+            Some("ts") => {
+                let Some((start_pts, expected_ms)) = parse_event_file_name(&path) else {
+                    continue;
+                };
+                stats.checked += 1;
+                match check_ts_file(&path, expected_ms as u64) {
+                    Ok(Verdict::Intact) => {}
+                    Ok(Verdict::Repair {
+                        valid_len,
+                        duration_ms,
+                    }) => match repair(&path, start_pts, valid_len, duration_ms) {
+                        Ok(new_path) => {
+                            tracing::warn!(
+                                path = %path.display(),
+                                repaired = %new_path.display(),
+                                expected_ms,
+                                duration_ms,
+                                "repaired damaged event file"
+                            );
+                            stats.repaired += 1;
+                        }
+                        Err(e) => {
+                            tracing::error!(path = %path.display(), error = %e, "failed to repair event file");
+                            quarantine(&path, quarantine_dir, "repair failed");
+                            stats.quarantined += 1;
+                        }
+                    },
+                    Ok(Verdict::Quarantine(reason)) => {
+                        quarantine(&path, quarantine_dir, reason);
+                        stats.quarantined += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!(path = %path.display(), error = %e, "failed to check event file");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Packet walk over a whole file, read in blocks
+#[derive(Default)]
+struct Scan {
+    /// Length of the intact packet prefix
+    valid_len: u64,
+    timeline: Timeline,
+    gops: Option<GopFinder>,
+}
+
+impl Scan {
+    fn duration_ms(&self) -> u64 {
+        ts::pts_to_ns(self.timeline.ticks) / 1_000_000
+    }
+}
+
+/// Files with an intact tail whose head and tail timestamps cover the
+/// duration in their name are taken as they are. Otherwise the whole file is
+/// scanned, counting time across clock resets from reconnects, and it is
+/// truncated to its intact packets or renamed to the duration it really has.
+fn check_ts_file(path: &Path, expected_ms: u64) -> Result<Verdict, std::io::Error> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < PACKET_SIZE as u64 {
+        return Ok(Verdict::Quarantine("shorter than one packet"));
+    }
+
+    let head = read_range(&mut file, 0, len.min(HEAD_BYTES))?;
+    if head[0] != SYNC_BYTE {
+        return Ok(Verdict::Quarantine("missing sync byte"));
+    }
+    let (_, head_pts) = scan_packets(&head);
+    let Some(&first_pts) = head_pts.first() else {
+        return Ok(Verdict::Quarantine("no PES timestamps"));
+    };
+
+    // Fast path: a whole number of packets with an intact tail, spanning
+    // the expected duration. Only scan the whole file otherwise.
+    if len % PACKET_SIZE as u64 == 0 {
+        let tail_len = len.min(TAIL_BYTES);
+        let tail = read_range(&mut file, len - tail_len, tail_len)?;
+        let (valid, tail_pts) = scan_packets(&tail);
+        let span = head_pts
+            .iter()
+            .chain(&tail_pts)
+            .map(|&pts| ts::pts_diff(first_pts, pts))
+            .filter(|&diff| diff < ts::PTS_WRAP / 2)
+            .max()
+            .unwrap_or(0);
+        let span_ms = ts::pts_to_ns(span) / 1_000_000;
+        if valid == tail.len() && span_ms + DURATION_TOLERANCE_MS >= expected_ms {
+            return Ok(Verdict::Intact);
+        }
+    }
+
+    let scan = scan_file(&mut file, len, false)?;
+    if scan.valid_len == 0 {
+        return Ok(Verdict::Quarantine("no valid packets"));
+    }
+    if scan.valid_len == len && scan.duration_ms() + DURATION_TOLERANCE_MS >= expected_ms {
+        return Ok(Verdict::Intact);
+    }
+    Ok(Verdict::Repair {
+        valid_len: scan.valid_len,
+        duration_ms: scan.duration_ms() as u32,
+    })
+}
+
+/// Walk the packets of a file in fixed-size blocks until the first missing
+/// sync byte, so an hour-long chunk is never held in memory at once
+fn scan_file(file: &mut File, len: u64, find_gops: bool) -> Result<Scan, std::io::Error> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut scan = Scan {
+        gops: find_gops.then(GopFinder::default),
+        ..Scan::default()
+    };
+    let mut block = vec![0u8; SCAN_BLOCK_BYTES];
+    let packets_len = len / PACKET_SIZE as u64 * PACKET_SIZE as u64;
+    while scan.valid_len < packets_len {
+        let n = (packets_len - scan.valid_len).min(SCAN_BLOCK_BYTES as u64) as usize;
+        file.read_exact(&mut block[..n])?;
+        let mut offset = 0;
+        while offset < n {
+            let packet = &block[offset..offset + PACKET_SIZE];
+            if packet[0] != SYNC_BYTE {
+                return Ok(scan);
+            }
+            if let Some((p, _)) = ts::pes_timestamps(packet) {
+                scan.timeline.push(p);
+            }
+            if let Some(gops) = scan.gops.as_mut() {
+                gops.packet(scan.valid_len, packet);
+            }
+            offset += PACKET_SIZE;
+            scan.valid_len += PACKET_SIZE as u64;
+        }
+    }
+    Ok(scan)
+}
+
+/// Finds where each GOP of a recording chunk starts, to rebuild the GOP list
+/// of a chunk finalized after a crash. A GOP starts at its keyframe, or at
+/// the PAT and PMT written right in front of it.
+#[derive(Default)]
+struct GopFinder {
+    pmt_pid: Option<u16>,
+    video_pid: Option<u16>,
+    codec: Option<VideoCodec>,
+    /// Offset of the run of PAT/PMT packets right before the current packet
+    tables_offset: Option<u64>,
+    /// Access unit not known to be a keyframe yet: its start offset, PTS
+    /// and PES data so far
+    pending: Option<(u64, Option<u64>, Vec<u8>)>,
+    /// Offset and PTS of each keyframe
+    starts: Vec<(u64, u64)>,
+    last_pts: Option<u64>,
+}
+
+impl GopFinder {
+    fn packet(&mut self, offset: u64, packet: &[u8]) {
+        let pid = ts::pid(packet);
+        if pid == 0 || Some(pid) == self.pmt_pid {
+            if let Some(section) = ts::psi_section(packet) {
+                if pid == 0 {
+                    self.pmt_pid = ts::pat_pmt_pid(section).or(self.pmt_pid);
+                } else if let Some((codec, video_pid)) = ts::pmt_streams(section)
+                    .into_iter()
+                    .find_map(|(stream_type, pid)| {
+                        VideoCodec::from_stream_type(stream_type).map(|codec| (codec, pid))
+                    })
+                {
+                    self.codec = Some(codec);
+                    self.video_pid = Some(video_pid);
+                }
+            }
+            self.tables_offset.get_or_insert(offset);
+            return;
+        }
+        let tables_offset = self.tables_offset.take();
+        if Some(pid) != self.video_pid {
+            return;
+        }
+
+        if ts::payload_unit_start(packet) {
+            let start = tables_offset.unwrap_or(offset);
+            let pts = ts::pes_timestamps(packet).map(|(pts, _)| pts);
+            self.last_pts = pts.or(self.last_pts);
+            if ts::random_access(packet) {
+                self.pending = None;
+                self.keyframe(start, pts);
+                return;
+            }
+            self.pending = Some((start, pts, Vec::new()));
+        }
+
+        // Without the flag, look for the first slice like the segmenter does
+        let Some((_, _, pes)) = self.pending.as_mut() else {
+            return;
+        };
+        if let Some(payload) = ts::payload_offset(packet) {
+            pes.extend_from_slice(&packet[payload..]);
+        }
+        let keyframe = match (self.codec, ts::pes_payload(pes)) {
+            (Some(codec), Some(es)) => codec.access_unit_is_keyframe(es),
+            _ => None,
+        };
+        match keyframe.or((pes.len() > KEYFRAME_SCAN_BYTES).then_some(false)) {
+            Some(true) => {
+                if let Some((start, pts, _)) = self.pending.take() {
+                    self.keyframe(start, pts);
+                }
+            }
+            Some(false) => self.pending = None,
+            None => {}
+        }
+    }
+
+    fn keyframe(&mut self, offset: u64, pts: Option<u64>) {
+        if let Some(pts) = pts {
+            self.starts.push((offset, pts));
+        }
+    }
+
+    /// GOP list of a chunk cut to `len` bytes. The first GOP takes in
+    /// anything before its keyframe.
+    fn gops(&self, len: u64) -> Vec<ChunkGop> {
+        let starts: Vec<(u64, u64)> = self
+            .starts
+            .iter()
+            .copied()
+            .filter(|&(offset, _)| offset < len)
+            .collect();
+        let mut gops = Vec::with_capacity(starts.len());
+        for (i, &(offset, pts)) in starts.iter().enumerate() {
+            let offset = if i == 0 { 0 } else { offset };
+            let (end, end_pts) = match starts.get(i + 1) {
+                Some(&(next, next_pts)) => (next, Some(next_pts)),
+                None => (len, self.last_pts),
+            };
+            let ticks = end_pts
+                .map(|end_pts| ts::pts_diff(pts, end_pts))
+                .filter(|&ticks| ticks < ts::PTS_WRAP / 2)
+                .unwrap_or(0);
+            gops.push(ChunkGop {
+                offset,
+                bytes: end - offset,
+                duration_ns: ts::pts_to_ns(ticks),
+            });
+        }
+        gops
+    }
+}
+
+/// Ticks covered by timestamps in stream order. Small steps back (B-frames,
+/// interleaved audio) are ignored, and a large jump either way starts a new
+/// run instead of counting as elapsed time.
+#[derive(Default)]
+struct Timeline {
+    latest: Option<u64>,
+    ticks: u64,
+}
+
+impl Timeline {
+    fn push(&mut self, pts: u64) {
+        let Some(latest) = self.latest else {
+            self.latest = Some(pts);
+            return;
+        };
+        let forward = ts::pts_diff(latest, pts);
+        if forward <= MAX_PTS_STEP {
+            self.ticks += forward;
+            self.latest = Some(pts);
+        } else if ts::pts_diff(pts, latest) > MAX_PTS_STEP {
+            self.latest = Some(pts);
+        }
+    }
+}
+
+fn read_range(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, std::io::Error> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0u8; len as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Walk packets until the first missing sync byte, returning the length of
+/// the valid prefix and every PES presentation timestamp seen in it
+fn scan_packets(data: &[u8]) -> (usize, Vec<u64>) {
+    let mut pts = Vec::new();
+    let mut offset = 0;
+    while offset + PACKET_SIZE <= data.len() {
+        let packet = &data[offset..offset + PACKET_SIZE];
+        if packet[0] != SYNC_BYTE {
+            break;
+        }
+        if let Some((p, _)) = ts::pes_timestamps(packet) {
+            pts.push(p);
+        }
+        offset += PACKET_SIZE;
+    }
+    (offset, pts)
+}
+
+fn finalize_partial(path: &Path) -> Result<Option<PathBuf>, std::io::Error> {
+    let Some((start_pts, _)) = parse_event_file_name(&path.with_extension("")) else {
+        return Ok(None);
+    };
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < PACKET_SIZE as u64 {
+        return Ok(None);
+    }
+    let scan = scan_file(&mut file, len, true)?;
+    if scan.valid_len == 0 {
+        return Ok(None);
+    }
+    let new_path = repair(path, start_pts, scan.valid_len, scan.duration_ms() as u32)?;
+
+    // Rebuild the GOP list the recorder would have written on finishing
+    let gops = scan
+        .gops
+        .map(|finder| finder.gops(scan.valid_len))
+        .unwrap_or_default();
+    if !gops.is_empty() {
+        let gops_path = new_path.with_extension("json");
+        let result = serde_json::to_vec(&gops)
+            .map_err(std::io::Error::from)
+            .and_then(|json| atomic::write_atomic_sync(&gops_path, &json));
+        if let Err(e) = result {
+            tracing::warn!(path = %gops_path.display(), error = %e, "failed to write recording GOP list");
+        }
+    }
+    Ok(Some(new_path))
+}
+
+fn repair(
+    path: &Path,
+    start_pts: u64,
+    valid_len: u64,
+    duration_ms: u32,
+) -> Result<PathBuf, std::io::Error> {
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    file.set_len(valid_len)?;
+    file.sync_all()?;
+    drop(file);
+
+    let new_path = path.with_file_name(format!("{start_pts}_{duration_ms}.ts"));
+    std::fs::rename(path, &new_path)?;
+
+    // Event sidecars follow the file. Anything else there, such as a
+    // recording chunk's GOP list, described the file before truncation.
+    let sidecar_path = path.with_extension("json");
+    if let Some(mut sidecar) = EventSidecar::load(&sidecar_path) {
+        sidecar.duration_ms = duration_ms;
+        let json = serde_json::to_vec_pretty(&sidecar)?;
+        atomic::write_atomic_sync(&new_path.with_extension("json"), &json)?;
+        if sidecar_path != new_path.with_extension("json") {
+            let _ = std::fs::remove_file(&sidecar_path);
+        }
+    } else {
+        let _ = std::fs::remove_file(&sidecar_path);
+    }
+
+    Ok(new_path)
+}
+
+fn quarantine(path: &Path, quarantine_dir: &Path, reason: &str) {
+    let result = std::fs::create_dir_all(quarantine_dir).and_then(|_| {
+        let name = path.file_name().unwrap_or_default();
+        std::fs::rename(path, quarantine_dir.join(name))?;
+        let sidecar = path.with_extension("json");
+        if path.extension().and_then(|e| e.to_str()) == Some("ts") && sidecar.exists() {
+            let sidecar_name = sidecar.file_name().unwrap_or_default();
+            std::fs::rename(&sidecar, quarantine_dir.join(sidecar_name))?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => {
+            tracing::warn!(
+                path = %path.display(),
+                quarantine = %quarantine_dir.display(),
+                reason,
+                "quarantined event file"
+            );
+        }
+        Err(e) => {
+            tracing::error!(path = %path.display(), error = %e, "failed to quarantine event file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ts::PTS_WRAP;
+    use crate::buffer::ts_mux::TsMuxer;
+
+    fn timeline(pts: &[u64]) -> u64 {
+        let mut timeline = Timeline::default();
+        for &p in pts {
+            timeline.push(p);
+        }
+        timeline.ticks
+    }
+
+    #[test]
+    fn test_gop_finder_rebuilds_gop_list() {
+        let mut muxer = TsMuxer::new(ts::STREAM_TYPE_H264);
+        let mut data = Vec::new();
+        let mut gop_offsets = Vec::new();
+        for frame in 0..6u64 {
+            let keyframe = frame % 3 == 0;
+            if keyframe {
+                gop_offsets.push(data.len() as u64);
+            }
+            let nal: &[u8] = if keyframe {
+                &[0x65, 0x88]
+            } else {
+                &[0x41, 0x9A]
+            };
+            let au = [&[0, 0, 0, 1][..], nal].concat();
+            muxer.write_access_unit(frame * 3000, keyframe, &au, &mut data);
+        }
+
+        let mut finder = GopFinder::default();
+        for (i, packet) in data.chunks_exact(PACKET_SIZE).enumerate() {
+            finder.packet((i * PACKET_SIZE) as u64, packet);
+        }
+        let gops = finder.gops(data.len() as u64);
+        let offsets: Vec<u64> = gops.iter().map(|g| g.offset).collect();
+        assert_eq!(offsets, gop_offsets);
+        assert_eq!(gops[0].bytes, gop_offsets[1]);
+        assert_eq!(gops[1].offset + gops[1].bytes, data.len() as u64);
+        assert_eq!(gops[0].duration_ns, 100_000_000);
+        // The last GOP runs to its last frame's timestamp
+        assert_eq!(gops[1].duration_ns, 66_666_666);
+    }
+
+    #[test]
+    fn test_timeline_skips_discontinuities() {
+        let second = ts::PTS_CLOCK_HZ;
+        // B-frame reordering
+        assert_eq!(timeline(&[0, 3000, 1500, 6000, 4500, 9000]), 9000);
+        // 10s, a clock reset after a reconnect, then 20s more
+        let mut pts: Vec<u64> = (0..=10).map(|s| 1_000_000 + s * second).collect();
+        pts.extend((0..=20).map(|s| s * second));
+        assert_eq!(timeline(&pts), 30 * second);
+        // 33-bit wraparound
+        assert_eq!(timeline(&[PTS_WRAP - second, 0, second]), 2 * second);
+    }
+}
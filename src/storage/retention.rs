@@ -0,0 +1,310 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::{
+    EventMetadataStore, EventType, RecordingEntry, RecordingIndex, WarmEventEntry, WarmEventIndex,
+};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const SECS_PER_HOUR: u64 = 60 * 60;
+
+pub struct RetentionPolicy {
+    pub max_age_hours: u64,
+    pub max_bytes_per_camera: u64,
+    pub min_free_bytes: u64,
+    pub interval_secs: u64,
+}
+
+/// Periodically prunes warm events and continuous recording chunks by age,
+/// per-camera size budget and free disk space. Chunks are deleted before
+/// events, and Movement events before Object and Rule events.
+///
+/// With a cold tier, warm events are not pruned by age: the archiver moves
+/// them to cold storage once old enough. Cold events still count towards the
+/// free space floor and are evicted with the warm ones, oldest first.
+pub struct RetentionWorker {
+    warm_index: WarmEventIndex,
+    cold_index: Option<WarmEventIndex>,
+    recording_index: Option<RecordingIndex>,
+    metadata_store: EventMetadataStore,
+    data_dir: PathBuf,
+    policy: RetentionPolicy,
+}
+
+impl RetentionWorker {
+    pub fn new(
+        warm_index: WarmEventIndex,
+        cold_index: Option<WarmEventIndex>,
+        recording_index: Option<RecordingIndex>,
+        metadata_store: EventMetadataStore,
+        data_dir: PathBuf,
+        policy: RetentionPolicy,
+    ) -> Self {
+        Self {
+            warm_index,
+            cold_index,
+            recording_index,
+            metadata_store,
+            data_dir,
+            policy,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.policy.interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            let camera_ids = self.warm_index.camera_ids();
+            for camera_id in &camera_ids {
+                self.prune_by_age(camera_id).await;
+                self.prune_by_size(camera_id).await;
+            }
+            self.prune_by_free_space(&camera_ids).await;
+        }
+    }
+
+    async fn prune_by_age(&self, camera_id: &str) {
+        if self.policy.max_age_hours == 0 {
+            return;
+        }
+        let max_age_ns = self.policy.max_age_hours * SECS_PER_HOUR * NANOS_PER_SEC;
+        let cutoff = now_ns().saturating_sub(max_age_ns);
+        if self.cold_index.is_none() {
+            for entry in self.warm_index.older_than(camera_id, cutoff) {
+                self.delete_event(&self.warm_index, camera_id, &entry, "max age exceeded")
+                    .await;
+            }
+        }
+        for chunk in self.chunks(camera_id) {
+            if chunk.end_ns() >= cutoff {
+                break;
+            }
+            self.delete_chunk(camera_id, &chunk, "max age exceeded")
+                .await;
+        }
+    }
+
+    async fn prune_by_size(&self, camera_id: &str) {
+        if self.policy.max_bytes_per_camera == 0 {
+            return;
+        }
+        let mut entries = self.warm_index.entries(camera_id);
+        let chunks = self.chunks(camera_id);
+        let mut total: u64 = entries.iter().map(|e| e.file_size).sum::<u64>()
+            + chunks.iter().map(|c| c.file_size).sum::<u64>();
+        for chunk in chunks {
+            if total <= self.policy.max_bytes_per_camera {
+                return;
+            }
+            total = total.saturating_sub(chunk.file_size);
+            self.delete_chunk(camera_id, &chunk, "camera size budget exceeded")
+                .await;
+        }
+        while total > self.policy.max_bytes_per_camera {
+            let Some(pos) = pick_victim(&entries) else {
+                break;
+            };
+            let entry = entries.remove(pos);
+            total = total.saturating_sub(entry.file_size);
+            self.delete_event(
+                &self.warm_index,
+                camera_id,
+                &entry,
+                "camera size budget exceeded",
+            )
+            .await;
+        }
+    }
+
+    async fn prune_by_free_space(&self, camera_ids: &[String]) {
+        if self.policy.min_free_bytes == 0 {
+            return;
+        }
+        loop {
+            let free = match free_bytes(&self.data_dir) {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::warn!(
+                        path = %self.data_dir.display(),
+                        error = %e,
+                        "failed to query free disk space"
+                    );
+                    return;
+                }
+            };
+            if free >= self.policy.min_free_bytes {
+                return;
+            }
+
+            // Oldest recording chunk across all cameras goes first
+            let oldest_chunk = camera_ids
+                .iter()
+                .filter_map(|id| self.chunks(id).into_iter().next().map(|c| (id, c)))
+                .min_by_key(|(_, c)| c.start_pts_ns);
+            if let Some((camera_id, chunk)) = oldest_chunk {
+                self.delete_chunk(camera_id, &chunk, "disk free space below floor")
+                    .await;
+                continue;
+            }
+
+            // Oldest Movement event across all cameras and both tiers,
+            // falling back to the oldest of any type
+            let mut candidates: Vec<(&WarmEventIndex, String, WarmEventEntry)> = Vec::new();
+            for index in std::iter::once(&self.warm_index).chain(&self.cold_index) {
+                for camera_id in camera_ids {
+                    let entries = index.entries(camera_id);
+                    if let Some(pos) = pick_victim(&entries) {
+                        candidates.push((index, camera_id.clone(), entries[pos].clone()));
+                    }
+                }
+            }
+            let victim = candidates
+                .into_iter()
+                .min_by_key(|(_, _, e)| (e.event_type != EventType::Movement, e.start_pts_ns));
+            let Some((index, camera_id, entry)) = victim else {
+                tracing::warn!(
+                    free_bytes = free,
+                    min_free_bytes = self.policy.min_free_bytes,
+                    "disk below free space floor with no events left to prune"
+                );
+                return;
+            };
+            self.delete_event(index, &camera_id, &entry, "disk free space below floor")
+                .await;
+        }
+    }
+
+    async fn delete_event(
+        &self,
+        index: &WarmEventIndex,
+        camera_id: &str,
+        entry: &WarmEventEntry,
+        reason: &str,
+    ) {
+        let path = index.resolve_file_path(camera_id, entry);
+        let sidecar_path = index.resolve_sidecar_path(camera_id, entry);
+        index.remove(camera_id, entry.start_pts_ns);
+        let _ = tokio::fs::remove_file(&sidecar_path).await;
+        self.metadata_store
+            .remove(camera_id, entry.start_pts_ns)
+            .await;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {
+                tracing::info!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    bytes = entry.file_size,
+                    reason,
+                    "pruned event"
+                );
+            }
+            Err(e) => {
+                tracing::warn!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    error = %e,
+                    "failed to remove event file"
+                );
+            }
+        }
+    }
+
+    fn chunks(&self, camera_id: &str) -> Vec<RecordingEntry> {
+        match &self.recording_index {
+            Some(index) => index.entries(camera_id),
+            None => Vec::new(),
+        }
+    }
+
+    async fn delete_chunk(&self, camera_id: &str, entry: &RecordingEntry, reason: &str) {
+        let Some(index) = &self.recording_index else {
+            return;
+        };
+        let path = index.resolve_file_path(camera_id, entry);
+        index.remove(camera_id, entry.start_pts_ns);
+        let _ = tokio::fs::remove_file(index.gops_path(camera_id, entry)).await;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {
+                tracing::info!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    bytes = entry.file_size,
+                    reason,
+                    "pruned recording chunk"
+                );
+            }
+            Err(e) => {
+                tracing::warn!(
+                    camera = %camera_id,
+                    path = %path.display(),
+                    error = %e,
+                    "failed to remove recording chunk"
+                );
+            }
+        }
+    }
+}
+
+/// Index of the next event to delete: the oldest Movement event, or the
+/// oldest event of any type when only Object and Rule events remain.
+fn pick_victim(entries: &[WarmEventEntry]) -> Option<usize> {
+    entries
+        .iter()
+        .position(|e| e.event_type == EventType::Movement)
+        .or_else(|| (!entries.is_empty()).then_some(0))
+}
+
+fn free_bytes(path: &Path) -> Result<u64, std::io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageFormat;
+
+    fn entry(start_pts_ns: u64, event_type: EventType) -> WarmEventEntry {
+        WarmEventEntry {
+            start_pts_ns,
+            duration_ms: 1000,
+            event_type,
+            file_size: 100,
+            format: StorageFormat::Ts,
+            sidecar: None,
+        }
+    }
+
+    #[test]
+    fn test_pick_victim_prefers_movement() {
+        let entries = [
+            entry(1, EventType::Object),
+            entry(2, EventType::Movement),
+            entry(3, EventType::Movement),
+        ];
+        assert_eq!(pick_victim(&entries), Some(1));
+    }
+
+    #[test]
+    fn test_pick_victim_falls_back_to_oldest_object() {
+        let entries = [entry(1, EventType::Object), entry(2, EventType::Object)];
+        assert_eq!(pick_victim(&entries), Some(0));
+        assert_eq!(pick_victim(&[]), None);
+    }
+}
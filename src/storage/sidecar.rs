@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{AlertRecord, DetectionRecord, MotionSample, TrackRecord};
+
+/// Axis-aligned box in normalized frame coordinates (0.0–1.0)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub start_pts_ns: u64,
+    pub duration_ns: u64,
+    #[serde(default)]
+    pub motion_score: Option<f32>,
+    #[serde(default)]
+    pub motion_bbox: Option<BoundingBox>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<String>,
+}
+
+/// JSON document written next to each warm event file as `{pts}_{duration}.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventSidecar {
+    pub camera_id: String,
+    pub start_pts_ns: u64,
+    pub duration_ms: u32,
+    pub segments: Vec<SegmentInfo>,
+    #[serde(default)]
+    pub detections: Vec<DetectionRecord>,
+    #[serde(default)]
+    pub tracks: Vec<TrackRecord>,
+    #[serde(default)]
+    pub alerts: Vec<AlertRecord>,
+}
+
+impl EventSidecar {
+    pub fn load(path: &Path) -> Option<Self> {
+        let data = std::fs::read(path).ok()?;
+        match serde_json::from_slice(&data) {
+            Ok(sidecar) => Some(sidecar),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "invalid event sidecar");
+                None
+            }
+        }
+    }
+
+    /// Motion timeline rebuilt from the segment info
+    pub fn motion(&self) -> Vec<MotionSample> {
+        self.segments
+            .iter()
+            .filter_map(|seg| {
+                Some(MotionSample {
+                    offset_ms: seg.start_pts_ns.saturating_sub(self.start_pts_ns) / 1_000_000,
+                    duration_ms: seg.duration_ns / 1_000_000,
+                    score: seg.motion_score?,
+                    zones: seg.zones.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn classes(&self) -> Vec<String> {
+        let mut classes: Vec<String> = self
+            .detections
+            .iter()
+            .map(|d| d.object_class.clone())
+            .collect();
+        classes.sort();
+        classes.dedup();
+        classes
+    }
+}
@@ -1,12 +1,25 @@
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
 
+use super::BoundingBox;
+
 pub struct MotionEntry {
     pub segment_sequence: u64,
     pub start_time_ns: u64,
     pub end_time_ns: u64,
     pub motion_score: f32,
     pub mask_jpeg: Option<Vec<u8>>,
+    pub bbox: Option<BoundingBox>,
+    /// Named camera zones the motion fell in
+    pub zones: Vec<String>,
+}
+
+/// Motion recorded for one segment, as carried into warm events
+#[derive(Debug, Clone, Default)]
+pub struct SegmentMotion {
+    pub score: f32,
+    pub bbox: Option<BoundingBox>,
+    pub zones: Vec<String>,
 }
 
 pub struct MotionStore {
@@ -40,6 +53,7 @@ impl MotionStore {
                         segment_sequence: e.segment_sequence,
                         duration_ns: e.end_time_ns - e.start_time_ns,
                         motion_score: e.motion_score,
+                        zones: e.zones.clone(),
                     })
                     .collect()
             }
@@ -81,6 +95,20 @@ impl MotionStore {
         }
     }
 
+    /// Motion score, bounding box and zones recorded for a segment
+    pub fn segment_motion(&self, camera_id: &str, segment_sequence: u64) -> Option<SegmentMotion> {
+        let lock = self.cameras.get(camera_id)?;
+        let entries = lock.read().unwrap();
+        entries
+            .iter()
+            .find(|e| e.segment_sequence == segment_sequence)
+            .map(|e| SegmentMotion {
+                score: e.motion_score,
+                bbox: e.bbox,
+                zones: e.zones.clone(),
+            })
+    }
+
     pub fn last_sequence(&self, camera_id: &str) -> Option<u64> {
         self.cameras
             .get(camera_id)?
@@ -103,4 +131,5 @@ pub struct MotionSnapshot {
     pub segment_sequence: u64,
     pub duration_ns: u64,
     pub motion_score: f32,
+    pub zones: Vec<String>,
 }
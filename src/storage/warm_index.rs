@@ -1,18 +1,47 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
+use super::recovery::{self, RecoveryStats};
+use super::EventSidecar;
+use crate::config::StorageFormat;
+
+const COLD_DIR: &str = "cold";
+const QUARANTINE_DIR: &str = "quarantine";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventType {
     Movement,
     Object,
+    /// A configured rule fired on a tracked object
+    Rule,
+    /// The camera was covered, redirected, defocused or blacked out
+    Tamper,
 }
 
 impl EventType {
-    fn dir_name(self) -> &'static str {
+    const ALL: [EventType; 4] = [
+        EventType::Movement,
+        EventType::Object,
+        EventType::Rule,
+        EventType::Tamper,
+    ];
+
+    pub fn dir_name(self) -> &'static str {
         match self {
             EventType::Movement => "movements",
             EventType::Object => "objects",
+            EventType::Rule => "rules",
+            EventType::Tamper => "tamper",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventType::Movement => "movement",
+            EventType::Object => "object",
+            EventType::Rule => "rule",
+            EventType::Tamper => "tamper",
         }
     }
 }
@@ -24,16 +53,28 @@ pub struct WarmEventEntry {
     pub duration_ms: u32,
     pub event_type: EventType,
     pub file_size: u64,
+    pub format: StorageFormat,
+    pub sidecar: Option<Arc<EventSidecar>>,
 }
 
 #[derive(Clone)]
 pub struct WarmEventIndex {
     cameras: Arc<HashMap<String, RwLock<Vec<WarmEventEntry>>>>,
     data_dir: PathBuf,
+    tier_dir: Option<&'static str>,
 }
 
 impl WarmEventIndex {
     pub fn new(camera_ids: &[String], data_dir: PathBuf) -> Self {
+        Self::with_tier(camera_ids, data_dir, None)
+    }
+
+    /// Index over transcoded cold archive files, stored under `<camera>/cold/`
+    pub fn new_cold(camera_ids: &[String], data_dir: PathBuf) -> Self {
+        Self::with_tier(camera_ids, data_dir, Some(COLD_DIR))
+    }
+
+    fn with_tier(camera_ids: &[String], data_dir: PathBuf, tier_dir: Option<&'static str>) -> Self {
         let mut cameras = HashMap::new();
         for id in camera_ids {
             cameras.insert(id.clone(), RwLock::new(Vec::new()));
@@ -41,46 +82,80 @@ impl WarmEventIndex {
         Self {
             cameras: Arc::new(cameras),
             data_dir,
+            tier_dir,
+        }
+    }
+
+    fn camera_dir(&self, camera_id: &str) -> PathBuf {
+        let dir = self.data_dir.join(camera_id);
+        match self.tier_dir {
+            Some(tier) => dir.join(tier),
+            None => dir,
+        }
+    }
+
+    pub fn camera_ids(&self) -> Vec<String> {
+        self.cameras.keys().cloned().collect()
+    }
+
+    /// Quarantine interrupted writes and repair truncated event files left
+    /// behind by a crash. Run before `scan`.
+    pub fn recover(&self) {
+        for camera_id in self.cameras.keys() {
+            let camera_dir = self.camera_dir(camera_id);
+            let quarantine_dir = camera_dir.join(QUARANTINE_DIR);
+            let mut stats = RecoveryStats::default();
+            for event_type in &EventType::ALL {
+                recovery::recover_dir(
+                    &camera_dir.join(event_type.dir_name()),
+                    &quarantine_dir,
+                    &mut stats,
+                );
+            }
+            if stats.repaired > 0 || stats.quarantined > 0 {
+                tracing::warn!(
+                    camera = %camera_id,
+                    checked = stats.checked,
+                    repaired = stats.repaired,
+                    quarantined = stats.quarantined,
+                    "recovered event files"
+                );
+            }
         }
     }
 
     pub fn scan(&self) {
         for (camera_id, lock) in self.cameras.iter() {
             let mut entries = Vec::new();
-            for event_type in &[EventType::Movement, EventType::Object] {
-                let dir = self.data_dir.join(camera_id).join(event_type.dir_name());
+            for event_type in &EventType::ALL {
+                let dir = self.camera_dir(camera_id).join(event_type.dir_name());
                 let read_dir = match std::fs::read_dir(&dir) {
                     Ok(rd) => rd,
                     Err(_) => continue,
                 };
                 for entry in read_dir.flatten() {
                     let path = entry.path();
-                    let ext = path.extension().and_then(|e| e.to_str());
-                    if ext != Some("ts") {
-                        continue;
-                    }
-                    let stem = match path.file_stem().and_then(|s| s.to_str()) {
-                        Some(s) => s,
+                    let format = match path
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .and_then(StorageFormat::from_extension)
+                    {
+                        Some(format) => format,
                         None => continue,
                     };
-                    let (start_str, dur_str) = match stem.split_once('_') {
-                        Some(pair) => pair,
+                    let (start_pts_ns, duration_ms) = match parse_event_file_name(&path) {
+                        Some(parsed) => parsed,
                         None => continue,
                     };
-                    let start_pts_ns: u64 = match start_str.parse() {
-                        Ok(v) => v,
-                        Err(_) => continue,
-                    };
-                    let duration_ms: u32 = match dur_str.parse() {
-                        Ok(v) => v,
-                        Err(_) => continue,
-                    };
                     let file_size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                    let sidecar = EventSidecar::load(&path.with_extension("json")).map(Arc::new);
                     entries.push(WarmEventEntry {
                         start_pts_ns,
                         duration_ms,
                         event_type: *event_type,
                         file_size,
+                        format,
+                        sidecar,
                     });
                 }
             }
@@ -88,7 +163,12 @@ impl WarmEventIndex {
             let count = entries.len();
             *lock.write().unwrap() = entries;
             if count > 0 {
-                tracing::info!(camera = %camera_id, events = count, "scanned warm events");
+                tracing::info!(
+                    camera = %camera_id,
+                    events = count,
+                    tier = self.tier_dir.unwrap_or("warm"),
+                    "scanned events"
+                );
             }
         }
     }
@@ -117,6 +197,37 @@ impl WarmEventIndex {
         }
     }
 
+    pub fn entries(&self, camera_id: &str) -> Vec<WarmEventEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => lock.read().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn remove(&self, camera_id: &str, start_pts_ns: u64) -> Option<WarmEventEntry> {
+        let lock = self.cameras.get(camera_id)?;
+        let mut entries = lock.write().unwrap();
+        let pos = entries
+            .binary_search_by_key(&start_pts_ns, |e| e.start_pts_ns)
+            .ok()?;
+        Some(entries.remove(pos))
+    }
+
+    /// Events that ended before the given cutoff, oldest first
+    pub fn older_than(&self, camera_id: &str, cutoff_ns: u64) -> Vec<WarmEventEntry> {
+        match self.cameras.get(camera_id) {
+            Some(lock) => {
+                let entries = lock.read().unwrap();
+                entries
+                    .iter()
+                    .filter(|e| e.start_pts_ns + (e.duration_ms as u64) * 1_000_000 < cutoff_ns)
+                    .cloned()
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
     pub fn find_event(&self, camera_id: &str, start_pts_ns: u64) -> Option<WarmEventEntry> {
         let lock = self.cameras.get(camera_id)?;
         let entries = lock.read().unwrap();
@@ -127,10 +238,80 @@ impl WarmEventIndex {
     }
 
     pub fn resolve_file_path(&self, camera_id: &str, entry: &WarmEventEntry) -> PathBuf {
-        let dir = self
-            .data_dir
-            .join(camera_id)
-            .join(entry.event_type.dir_name());
-        dir.join(format!("{}_{}.ts", entry.start_pts_ns, entry.duration_ms))
+        let dir = self.camera_dir(camera_id).join(entry.event_type.dir_name());
+        dir.join(format!(
+            "{}_{}.{}",
+            entry.start_pts_ns,
+            entry.duration_ms,
+            entry.format.extension()
+        ))
+    }
+
+    pub fn resolve_sidecar_path(&self, camera_id: &str, entry: &WarmEventEntry) -> PathBuf {
+        self.resolve_file_path(camera_id, entry)
+            .with_extension("json")
+    }
+}
+
+/// Parse `{start_pts_ns}_{duration_ms}.{ext}` event file names
+pub fn parse_event_file_name(path: &Path) -> Option<(u64, u32)> {
+    let stem = path.file_stem()?.to_str()?;
+    let (start_str, dur_str) = stem.split_once('_')?;
+    Some((start_str.parse().ok()?, dur_str.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start_pts_ns: u64, duration_ms: u32) -> WarmEventEntry {
+        WarmEventEntry {
+            start_pts_ns,
+            duration_ms,
+            event_type: EventType::Object,
+            file_size: 100,
+            format: StorageFormat::Ts,
+            sidecar: None,
+        }
+    }
+
+    #[test]
+    fn test_cold_index_older_than_and_remove() {
+        let cameras = ["cam".to_string()];
+        let index = WarmEventIndex::new_cold(&cameras, PathBuf::from("/data"));
+        index.insert("cam", entry(3_000_000_000, 1000));
+        index.insert("cam", entry(1_000_000_000, 1000));
+        index.insert("cam", entry(5_000_000_000, 1000));
+
+        // Only events that ended before the cutoff, oldest first
+        let old: Vec<u64> = index
+            .older_than("cam", 4_500_000_000)
+            .iter()
+            .map(|e| e.start_pts_ns)
+            .collect();
+        assert_eq!(old, vec![1_000_000_000, 3_000_000_000]);
+        assert!(index.older_than("other", u64::MAX).is_empty());
+
+        let removed = index.remove("cam", 3_000_000_000).unwrap();
+        assert_eq!(removed.start_pts_ns, 3_000_000_000);
+        assert!(index.remove("cam", 3_000_000_000).is_none());
+        assert_eq!(index.entries("cam").len(), 2);
+
+        let path = index.resolve_file_path("cam", &removed);
+        assert_eq!(
+            path,
+            PathBuf::from("/data/cam/cold/objects/3000000000_1000.ts")
+        );
+        assert_eq!(
+            index.resolve_sidecar_path("cam", &removed),
+            PathBuf::from("/data/cam/cold/objects/3000000000_1000.json")
+        );
+
+        // The warm tier keeps events directly under the camera
+        let warm = WarmEventIndex::new(&cameras, PathBuf::from("/data"));
+        assert_eq!(
+            warm.resolve_file_path("cam", &removed),
+            PathBuf::from("/data/cam/objects/3000000000_1000.ts")
+        );
     }
 }